@@ -1,7 +1,11 @@
 #![cfg_attr(all(not(test), not(debug_assertions)), windows_subsystem = "windows")]
 #[macro_use]
 extern crate bitflags;
-use std::{io::Write, path::Path, process::exit};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::exit,
+};
 use url::Url;
 use whoami::username;
 
@@ -9,7 +13,10 @@ use clap::Parser;
 use native_windows_gui::{self as nwg, NativeUi};
 mod stremio_app;
 use crate::stremio_app::{
-    constants::{DEV_ENDPOINT, IPC_PATH, STA_ENDPOINT, STREMIO_SERVER_DEV_MODE, WEB_ENDPOINT},
+    constants::{
+        DEV_ENDPOINT, IPC_PATH, PLAYER_LOG_LEVEL, PLAYER_LOG_LEVELS, PLAYER_LOG_LEVEL_ENV,
+        PLAYER_SCRIPTS_DIR_ENV, STA_ENDPOINT, STREMIO_SERVER_DEV_MODE, WEB_ENDPOINT,
+    },
     MainWindow, PipeClient,
 };
 
@@ -36,6 +43,18 @@ struct Opt {
     force_update: bool,
     #[clap(long, help = "Check for RC updates")]
     release_candidate: bool,
+    #[clap(
+        long,
+        default_value = PLAYER_LOG_LEVEL,
+        value_parser = PLAYER_LOG_LEVELS,
+        help = "Minimum level of the player log messages kept for diagnostics"
+    )]
+    player_log_level: String,
+    #[clap(
+        long,
+        help = "Load the mpv scripts from this folder instead of the scripts folder in the config directory"
+    )]
+    player_scripts_dir: Option<PathBuf>,
 }
 
 fn main() {
@@ -78,6 +97,10 @@ fn main() {
         STREMIO_SERVER_DEV_MODE,
         if opt.development { "true" } else { "false" },
     );
+    std::env::set_var(PLAYER_LOG_LEVEL_ENV, &opt.player_log_level);
+    if let Some(dir) = &opt.player_scripts_dir {
+        std::env::set_var(PLAYER_SCRIPTS_DIR_ENV, dir);
+    }
 
     let webui_url = if opt.development && opt.webui_url == WEB_ENDPOINT {
         DEV_ENDPOINT.to_string()
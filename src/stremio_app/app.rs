@@ -1,7 +1,7 @@
 use native_windows_derive::NwgUi;
 use native_windows_gui as nwg;
 use rand::Rng;
-use serde_json;
+use serde_json::{self, json};
 use std::{
     cell::RefCell,
     io::Read,
@@ -9,7 +9,7 @@ use std::{
     path::{Path, PathBuf},
     process::{self, Command},
     str,
-    sync::{Arc, Mutex},
+    sync::{Arc, Mutex, PoisonError},
     thread, time,
 };
 use url::Url;
@@ -18,8 +18,9 @@ use winapi::um::{winbase::CREATE_BREAKAWAY_FROM_JOB, winuser::WS_EX_TOPMOST};
 use crate::stremio_app::{
     constants::{APP_NAME, UPDATE_ENDPOINT, UPDATE_INTERVAL, WINDOW_MIN_HEIGHT, WINDOW_MIN_WIDTH},
     ipc::{RPCRequest, RPCResponse},
+    media_probe,
     splash::SplashImage,
-    stremio_player::Player,
+    stremio_player::{InMsgFn, Player, PlayerCapabilities},
     stremio_wevbiew::WebView,
     systray::SystemTray,
     updater,
@@ -59,7 +60,7 @@ pub struct MainWindow {
     #[nwg_partial(parent: window)]
     #[nwg_events(
         (tray, MousePressLeftUp): [Self::on_show],
-        (tray_exit, OnMenuItemSelected): [nwg::stop_thread_dispatch()],
+        (tray_exit, OnMenuItemSelected): [Self::on_exit],
         (tray_show_hide, OnMenuItemSelected): [Self::on_show_hide],
         (tray_topmost, OnMenuItemSelected): [Self::on_toggle_topmost],
     )]
@@ -76,7 +77,7 @@ pub struct MainWindow {
     #[nwg_events(OnNotice: [Self::on_toggle_fullscreen_notice] )]
     pub toggle_fullscreen_notice: nwg::Notice,
     #[nwg_control]
-    #[nwg_events(OnNotice: [nwg::stop_thread_dispatch()] )]
+    #[nwg_events(OnNotice: [Self::on_exit] )]
     pub quit_notice: nwg::Notice,
     #[nwg_control]
     #[nwg_events(OnNotice: [Self::on_hide_splash_notice] )]
@@ -142,6 +143,7 @@ impl MainWindow {
             .expect("Cannont obtain communication channel for the Player");
         let player_tx = player_tx.clone();
         let player_rx = player_rx.clone();
+        let player_capabilities = self.player.capabilities.clone();
 
         let web_channel = self.webview.channel.borrow();
         let (web_tx, web_rx) = web_channel
@@ -229,6 +231,22 @@ impl MainWindow {
             });
         }
 
+        // ffprobe may take a while to open URLs, so the probes run one at a
+        // time on their own thread
+        let (probe_tx, probe_rx) = flume::unbounded::<(u64, Option<String>)>();
+        let web_tx_probe = web_tx.clone();
+        thread::spawn(move || {
+            for (id, input) in probe_rx.iter() {
+                let reply = media_probe::probe_reply(id, input);
+                web_tx_probe
+                    .send(RPCResponse::response_message(Some(json!([
+                        "media-probe",
+                        reply
+                    ]))))
+                    .ok();
+            }
+        }); // thread
+
         // Read message from player
         thread::spawn(move || loop {
             player_rx
@@ -251,7 +269,13 @@ impl MainWindow {
                 match msg.get_method() {
                     // The handshake. Here we send some useful data to the WEB UI
                     None if msg.is_handshake() => {
-                        web_tx_web.send(RPCResponse::get_handshake()).ok();
+                        let capabilities = PlayerCapabilities::from_names(msg.get_capabilities());
+                        *player_capabilities
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner) = capabilities;
+                        web_tx_web
+                            .send(RPCResponse::get_handshake(&capabilities.names()))
+                            .ok();
                     }
                     Some("win-set-visibility") => toggle_fullscreen_sender.notice(),
                     Some("quit") => quit_sender.notice(),
@@ -331,10 +355,31 @@ impl MainWindow {
                             }
                         }
                     }
-                    Some(player_command) if player_command.starts_with("mpv-") => {
-                        let resp_json = serde_json::to_string(
-                            &msg.args.expect("Cannot have method without args"),
-                        )
+                    Some("media-probe") => {
+                        let input = msg
+                            .get_params()
+                            .and_then(|params| params.as_str())
+                            .map(str::to_string);
+                        probe_tx.send((msg.id, input)).ok();
+                    }
+                    Some(player_command)
+                        if player_command.starts_with("mpv-")
+                            || player_command.starts_with("player-") =>
+                    {
+                        let expects_reply = player_command
+                            .parse::<InMsgFn>()
+                            .is_ok_and(|player_fn| player_fn.expects_reply());
+                        let resp_json = if expects_reply {
+                            // Pass the request id so the player can reply to it
+                            serde_json::to_string(&json!([
+                                player_command,
+                                { "id": msg.id, "params": msg.get_params() }
+                            ]))
+                        } else {
+                            serde_json::to_string(
+                                &msg.args.expect("Cannot have method without args"),
+                            )
+                        }
                         .expect("Cannot build response");
                         player_tx.send(resp_json).ok();
                     }
@@ -413,6 +458,14 @@ impl MainWindow {
             self.on_show();
         }
     }
+    fn on_exit(&self) {
+        // The shutdown may take a few seconds, so the app looks closed while
+        // mpv stops and delivers its last events
+        self.window.set_visible(false);
+        self.tray.tray.set_visibility(false);
+        self.player.shutdown();
+        nwg::stop_thread_dispatch();
+    }
     fn on_quit(&self, data: &nwg::EventData) {
         if let nwg::EventData::OnWindowClose(data) = data {
             data.close(false);
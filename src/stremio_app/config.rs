@@ -0,0 +1,13 @@
+use std::{env, path::PathBuf};
+
+use crate::stremio_app::constants::CONFIG_DIR;
+
+// The directory with the user's configuration files, `%APPDATA%\stremio\stremio-shell-ng`.
+// It is not created, all the files in it are optional.
+pub fn config_dir() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(|app_data| {
+        CONFIG_DIR
+            .iter()
+            .fold(PathBuf::from(app_data), |path, dir| path.join(dir))
+    })
+}
@@ -14,3 +14,66 @@ pub const UPDATE_ENDPOINT: [&str; 3] = [
 pub const STREMIO_SERVER_DEV_MODE: &str = "STREMIO_SERVER_DEV_MODE";
 pub const SRV_BUFFER_SIZE: usize = 1024;
 pub const SRV_LOG_SIZE: usize = 20;
+// Minimum interval in milliseconds between two changes of a property sent to the Web UI
+pub const PLAYER_PROP_THROTTLE: [(&str, u64); 3] = [
+    ("time-pos", 250),
+    ("cache-buffering-state", 500),
+    ("demuxer-cache-state", 1000),
+];
+// Time in milliseconds to wait for MPV and the player threads to stop on exit
+pub const PLAYER_SHUTDOWN_TIMEOUT: u64 = 3000;
+// MPV is recreated at most this many times within the window in seconds
+pub const PLAYER_MAX_RESTARTS: usize = 3;
+pub const PLAYER_RESTART_WINDOW: u64 = 60;
+// Load the file being played again when MPV is recreated
+pub const PLAYER_RESUME_AFTER_RESTART: bool = true;
+// A file decoded by hardware is loaded again with software decoding after
+// this many decoding errors
+pub const HWDEC_MAX_DECODE_ERRORS: u32 = 3;
+// Seconds a codec which failed with hardware decoding is decoded by software
+pub const HWDEC_FALLBACK_MAX_AGE: u64 = 30 * 24 * 60 * 60;
+// Seconds a source of "player-load-with-fallbacks" may take to start playing
+// before the next one is tried, unless the web UI sets another timeout
+pub const SOURCE_STARTUP_TIMEOUT: u64 = 20;
+// The configuration directory within %APPDATA%
+pub const CONFIG_DIR: [&str; 2] = ["stremio", "stremio-shell-ng"];
+pub const MPV_CONFIG_FILE: &str = "mpv.conf";
+// mpv scripts are loaded from this folder in the config directory, unless
+// another one is set with `--player-scripts-dir`
+pub const MPV_SCRIPTS_DIR: &str = "scripts";
+pub const PLAYER_SCRIPTS_DIR_ENV: &str = "STREMIO_PLAYER_SCRIPTS_DIR";
+// Resume positions are saved at most every this many seconds while playing
+pub const RESUME_FILE: &str = "resume.json";
+pub const RESUME_SAVE_INTERVAL: u64 = 10;
+pub const RESUME_MAX_ENTRIES: usize = 500;
+pub const RESUME_MAX_AGE: u64 = 90 * 24 * 60 * 60;
+// mpv log messages at this level or above are kept for diagnostics, the
+// level may be changed with `--player-log-level`
+pub const PLAYER_LOG_LEVEL: &str = "info";
+pub const PLAYER_LOG_LEVELS: [&str; 8] = [
+    "no", "fatal", "error", "warn", "info", "v", "debug", "trace",
+];
+pub const PLAYER_LOG_LEVEL_ENV: &str = "STREMIO_PLAYER_LOG_LEVEL";
+pub const PLAYER_LOG_SIZE: usize = 1000;
+pub const PLAYER_LOG_FILE: &str = "player-log.txt";
+// Screenshots are written to this folder within the temp one unless the web
+// UI asks for another, they are removed after the max age in seconds
+pub const SCREENSHOT_TEMP_DIR: &str = "stremio-screenshots";
+// The folder in the configuration directory which has the screenshots kept
+// in the subfolders the web UI asks for
+pub const SCREENSHOT_DIR: &str = "screenshots";
+pub const SCREENSHOT_TEMP_MAX_AGE: u64 = 60 * 60;
+pub const SCREENSHOT_TEMPLATE: &str = "{title}-{pos}-{date}";
+// Settings picked in the web UI which mpv doesn't keep, like the audio device
+pub const PLAYER_SETTINGS_FILE: &str = "player-settings.json";
+// Installed next to the shell executable
+pub const FFPROBE_EXE: &str = "ffprobe.exe";
+// Seconds to wait for ffprobe, URLs may be slow to open
+pub const MEDIA_PROBE_TIMEOUT: u64 = 15;
+pub const FFMPEG_EXE: &str = "ffmpeg.exe";
+// Thumbnails are extracted every interval in seconds to this folder within
+// the temp one, the files played before are removed past the budget in bytes
+pub const THUMBNAIL_DIR: &str = "stremio-thumbnails";
+pub const THUMBNAIL_INTERVAL: f64 = 10.0;
+pub const THUMBNAIL_WIDTH: u32 = 240;
+pub const THUMBNAIL_DISK_BUDGET: u64 = 200 * 1024 * 1024;
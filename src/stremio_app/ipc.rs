@@ -27,10 +27,20 @@ impl RPCRequest {
             .as_ref()
             .and_then(|args| if args.len() > 1 { Some(&args[1]) } else { None })
     }
+    // The protocol capabilities the web UI asks for with the handshake
+    pub fn get_capabilities(&self) -> Vec<&str> {
+        self.args
+            .as_ref()
+            .and_then(|args| args.first())
+            .and_then(|arg| arg.get("capabilities"))
+            .and_then(|capabilities| capabilities.as_array())
+            .map(|capabilities| capabilities.iter().filter_map(|c| c.as_str()).collect())
+            .unwrap_or_default()
+    }
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RPCResponseDataTransport {
-    pub properties: Vec<Vec<String>>,
+    pub properties: Vec<Vec<serde_json::Value>>,
     pub signals: Vec<String>,
     pub methods: Vec<Vec<String>>,
 }
@@ -53,7 +63,8 @@ pub struct RPCResponse {
 }
 
 impl RPCResponse {
-    pub fn get_handshake() -> String {
+    // `capabilities` are the ones enabled for the web UI that asked for them
+    pub fn get_handshake(capabilities: &[&str]) -> String {
         let resp = RPCResponse {
             id: 0,
             object: "transport".to_string(),
@@ -62,11 +73,12 @@ impl RPCResponse {
                 transport: RPCResponseDataTransport {
                     properties: vec![
                         vec![],
+                        vec![json!(""), json!("shellVersion"), json!(""), json!(VERSION)],
                         vec![
-                            "".to_string(),
-                            "shellVersion".to_string(),
-                            "".to_string(),
-                            VERSION.to_string(),
+                            json!(""),
+                            json!("capabilities"),
+                            json!(""),
+                            json!(capabilities),
                         ],
                     ],
                     signals: vec![],
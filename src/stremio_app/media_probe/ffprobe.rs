@@ -0,0 +1,57 @@
+use anyhow::{anyhow, Context};
+use std::{
+    io::Read,
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::stremio_app::media_probe::{
+    hide_window,
+    media_info::{self, MediaInfo},
+};
+
+// Runs ffprobe on `input`, which must have passed `media_input`. It is
+// killed if it doesn't finish within `timeout`.
+pub fn probe(ffprobe: &Path, input: &str, timeout: Duration) -> Result<MediaInfo, anyhow::Error> {
+    let mut child = hide_window(&mut Command::new(ffprobe))
+        .args(["-v", "error", "-print_format", "json"])
+        .args(["-show_format", "-show_streams", "-i", input])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Cannot start ffprobe")?;
+    // Both pipes are read while waiting, so ffprobe never blocks on a full one
+    let read_pipe = |pipe: Option<Box<dyn Read + Send>>| {
+        thread::spawn(move || {
+            let mut output = String::new();
+            if let Some(mut pipe) = pipe {
+                pipe.read_to_string(&mut output).ok();
+            }
+            output
+        })
+    };
+    let stdout = read_pipe(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = read_pipe(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            child.kill().ok();
+            child.wait().ok();
+            return Err(anyhow!("ffprobe timed out after {}s", timeout.as_secs()));
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        return Err(anyhow!("ffprobe failed: {}", stderr.trim()));
+    }
+    media_info::parse(&stdout).context("Invalid ffprobe output")
+}
@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// What ffprobe tells about a file or URL, as sent to the web UI
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaInfo {
+    pub format: MediaFormat,
+    pub streams: Vec<MediaStream>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaFormat {
+    // The demuxer names, e.g. "matroska,webm"
+    pub name: Option<String>,
+    // Seconds
+    pub duration: Option<f64>,
+    // Bits per second
+    pub bit_rate: Option<u64>,
+    // Bytes
+    pub size: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StreamKind {
+    Video,
+    Audio,
+    Subtitle,
+    Attachment,
+    Data,
+    Unknown,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Hdr {
+    Hdr10,
+    #[serde(rename = "hdr10+")]
+    Hdr10Plus,
+    Hlg,
+    DolbyVision,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MediaStream {
+    pub index: u32,
+    pub kind: StreamKind,
+    // ffmpeg's codec name, e.g. "hevc", "eac3" or "subrip"
+    pub codec: Option<String>,
+    pub profile: Option<String>,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub default: bool,
+    pub forced: bool,
+    pub bit_rate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub video: Option<VideoInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio: Option<AudioInfo>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoInfo {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_rate: Option<f64>,
+    pub pixel_format: Option<String>,
+    // Bits per color component, from the pixel format
+    pub bit_depth: Option<u32>,
+    pub hdr: Option<Hdr>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioInfo {
+    pub channels: Option<u32>,
+    pub channel_layout: Option<String>,
+    pub sample_rate: Option<u32>,
+}
+
+// The output of `ffprobe -print_format json -show_format -show_streams`.
+// ffprobe writes most numbers as strings and leaves out unknown values.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct Probe {
+    streams: Vec<ProbeStream>,
+    format: ProbeFormat,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct ProbeFormat {
+    format_name: Option<String>,
+    duration: Option<String>,
+    size: Option<String>,
+    bit_rate: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct ProbeStream {
+    index: u32,
+    codec_name: Option<String>,
+    profile: Option<String>,
+    codec_type: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    pix_fmt: Option<String>,
+    bits_per_raw_sample: Option<String>,
+    color_transfer: Option<String>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+    sample_rate: Option<String>,
+    channels: Option<u32>,
+    channel_layout: Option<String>,
+    bit_rate: Option<String>,
+    disposition: HashMap<String, i32>,
+    tags: HashMap<String, String>,
+    side_data_list: Vec<SideData>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default)]
+struct SideData {
+    side_data_type: String,
+}
+
+// Parses the JSON printed by ffprobe
+pub fn parse(json: &str) -> serde_json::Result<MediaInfo> {
+    let probe: Probe = serde_json::from_str(json)?;
+    Ok(MediaInfo {
+        format: MediaFormat {
+            name: probe.format.format_name,
+            duration: parse_num(&probe.format.duration),
+            bit_rate: parse_num(&probe.format.bit_rate),
+            size: parse_num(&probe.format.size),
+        },
+        streams: probe.streams.into_iter().map(MediaStream::from).collect(),
+    })
+}
+
+impl From<ProbeStream> for MediaStream {
+    fn from(stream: ProbeStream) -> Self {
+        let kind = match stream.codec_type.as_deref() {
+            Some("video") => StreamKind::Video,
+            Some("audio") => StreamKind::Audio,
+            Some("subtitle") => StreamKind::Subtitle,
+            Some("attachment") => StreamKind::Attachment,
+            Some("data") => StreamKind::Data,
+            _ => StreamKind::Unknown,
+        };
+        // Matroska tags are upper case in some files
+        let tag = |name: &str| {
+            stream
+                .tags
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+        let disposition = |name: &str| {
+            stream
+                .disposition
+                .get(name)
+                .is_some_and(|value| *value != 0)
+        };
+        // Cover art is stored as a video stream too
+        let video =
+            (kind == StreamKind::Video && !disposition("attached_pic")).then(|| VideoInfo {
+                width: stream.width,
+                height: stream.height,
+                frame_rate: parse_rate(&stream.avg_frame_rate)
+                    .or_else(|| parse_rate(&stream.r_frame_rate)),
+                bit_depth: parse_num(&stream.bits_per_raw_sample)
+                    .or_else(|| stream.pix_fmt.as_deref().map(pixel_format_depth)),
+                pixel_format: stream.pix_fmt.clone(),
+                hdr: hdr(&stream),
+            });
+        let audio = (kind == StreamKind::Audio).then(|| AudioInfo {
+            channels: stream.channels,
+            channel_layout: stream.channel_layout.clone(),
+            sample_rate: parse_num(&stream.sample_rate),
+        });
+        Self {
+            index: stream.index,
+            kind,
+            language: tag("language").filter(|language| language != "und"),
+            title: tag("title"),
+            default: disposition("default"),
+            forced: disposition("forced"),
+            // Matroska keeps the bit rate in the `BPS` tag
+            bit_rate: parse_num(&stream.bit_rate).or_else(|| parse_num(&tag("BPS"))),
+            codec: stream.codec_name,
+            profile: stream.profile,
+            video,
+            audio,
+        }
+    }
+}
+
+// Dolby Vision streams usually carry an HDR10 or HLG base layer, they are
+// reported as Dolby Vision
+fn hdr(stream: &ProbeStream) -> Option<Hdr> {
+    let has_side_data = |prefix: &str| {
+        stream
+            .side_data_list
+            .iter()
+            .any(|side_data| side_data.side_data_type.starts_with(prefix))
+    };
+    if has_side_data("DOVI configuration record") {
+        return Some(Hdr::DolbyVision);
+    }
+    match stream.color_transfer.as_deref() {
+        Some("smpte2084") if has_side_data("HDR Dynamic Metadata SMPTE2094-40") => {
+            Some(Hdr::Hdr10Plus)
+        }
+        Some("smpte2084") => Some(Hdr::Hdr10),
+        Some("arib-std-b67") => Some(Hdr::Hlg),
+        _ => None,
+    }
+}
+
+// E.g. 10 for "yuv420p10le", "p010le" or "gray10le", 8 for "yuv420p" or "nv12"
+fn pixel_format_depth(pixel_format: &str) -> u32 {
+    let format = pixel_format
+        .strip_suffix("le")
+        .or_else(|| pixel_format.strip_suffix("be"))
+        .unwrap_or(pixel_format);
+    let prefix = format.trim_end_matches(|c: char| c.is_ascii_digit());
+    if !prefix.ends_with('p') && !prefix.ends_with("gray") {
+        return 8;
+    }
+    match format[prefix.len()..].parse() {
+        Ok(depth) if (9..=16).contains(&depth) => depth,
+        _ => 8,
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(value: &Option<String>) -> Option<T> {
+    value.as_deref().and_then(|value| value.parse().ok())
+}
+
+// ffprobe writes frame rates as fractions, "0/0" if unknown
+fn parse_rate(value: &Option<String>) -> Option<f64> {
+    let (num, den) = value.as_deref()?.split_once('/')?;
+    let (num, den) = (num.parse::<f64>().ok()?, den.parse::<f64>().ok()?);
+    (num > 0. && den > 0.).then(|| num / den)
+}
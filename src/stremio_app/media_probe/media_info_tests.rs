@@ -0,0 +1,142 @@
+use crate::stremio_app::media_probe::media_info::{
+    parse, AudioInfo, Hdr, MediaFormat, StreamKind, VideoInfo,
+};
+
+#[test]
+fn media_info_hdr10_mkv() {
+    let info = parse(include_str!("fixtures/hevc_hdr10_mkv.json")).expect("invalid fixture");
+    assert_eq!(
+        info.format,
+        MediaFormat {
+            name: Some("matroska,webm".to_string()),
+            duration: Some(5400.458),
+            bit_rate: Some(21_205_717),
+            size: Some(14_315_012_386),
+        }
+    );
+    let kinds: Vec<_> = info.streams.iter().map(|stream| stream.kind).collect();
+    assert_eq!(
+        kinds,
+        [
+            StreamKind::Video,
+            StreamKind::Audio,
+            StreamKind::Audio,
+            StreamKind::Subtitle,
+            StreamKind::Attachment,
+        ]
+    );
+
+    let video = &info.streams[0];
+    assert_eq!(video.codec.as_deref(), Some("hevc"));
+    assert_eq!(video.profile.as_deref(), Some("Main 10"));
+    assert!(video.default);
+    // From the Matroska statistics tags
+    assert_eq!(video.bit_rate, Some(16_830_537));
+    let video_info = video.video.as_ref().unwrap();
+    assert_eq!(
+        (video_info.width, video_info.height),
+        (Some(3840), Some(2160))
+    );
+    assert!((video_info.frame_rate.unwrap() - 23.976).abs() < 0.001);
+    assert_eq!(video_info.bit_depth, Some(10));
+    assert_eq!(video_info.hdr, Some(Hdr::Hdr10));
+    assert_eq!(video.audio, None);
+
+    let eac3 = &info.streams[1];
+    assert_eq!(eac3.language.as_deref(), Some("eng"));
+    assert_eq!(eac3.title.as_deref(), Some("English DD+ 5.1"));
+    assert_eq!(eac3.bit_rate, Some(640_000));
+    assert_eq!(
+        eac3.audio,
+        Some(AudioInfo {
+            channels: Some(6),
+            channel_layout: Some("5.1(side)".to_string()),
+            sample_rate: Some(48000),
+        })
+    );
+    let truehd = &info.streams[2];
+    assert_eq!(truehd.codec.as_deref(), Some("truehd"));
+    // Upper case tags
+    assert_eq!(truehd.language.as_deref(), Some("ger"));
+    assert!(!truehd.default);
+    assert_eq!(truehd.audio.as_ref().unwrap().channels, Some(8));
+
+    let subtitle = &info.streams[3];
+    assert_eq!(subtitle.codec.as_deref(), Some("subrip"));
+    assert!(subtitle.forced);
+    assert_eq!(
+        (subtitle.video.as_ref(), subtitle.audio.as_ref()),
+        (None, None)
+    );
+}
+
+#[test]
+fn media_info_dolby_vision_mp4() {
+    let info = parse(include_str!("fixtures/dolby_vision_mp4.json")).expect("invalid fixture");
+    assert_eq!(info.format.duration, Some(7200.));
+    let video = &info.streams[0];
+    assert_eq!(video.bit_rate, Some(15_021_455));
+    // "und" is left out
+    assert_eq!(video.language, None);
+    assert_eq!(
+        video.video,
+        Some(VideoInfo {
+            width: Some(3840),
+            height: Some(1600),
+            frame_rate: Some(24.),
+            pixel_format: Some("yuv420p10le".to_string()),
+            bit_depth: Some(10),
+            hdr: Some(Hdr::DolbyVision),
+        })
+    );
+    assert_eq!(info.streams[1].language.as_deref(), Some("spa"));
+    assert_eq!(info.streams[1].profile.as_deref(), Some("LC"));
+    // Cover art has no video info
+    assert_eq!(info.streams[2].kind, StreamKind::Video);
+    assert_eq!(info.streams[2].video, None);
+}
+
+#[test]
+fn media_info_live_stream() {
+    let info = parse(include_str!("fixtures/live_stream_ts.json")).expect("invalid fixture");
+    // Live streams have no duration or size
+    assert_eq!(
+        info.format,
+        MediaFormat {
+            name: Some("mpegts".to_string()),
+            ..MediaFormat::default()
+        }
+    );
+    let video_info = info.streams[0].video.as_ref().unwrap();
+    assert_eq!(video_info.bit_depth, Some(8));
+    assert_eq!(video_info.hdr, None);
+    assert_eq!(video_info.frame_rate, Some(50.));
+    assert_eq!(info.streams[1].bit_rate, Some(192_000));
+    assert_eq!(info.streams[2].kind, StreamKind::Data);
+    assert_eq!(info.streams[2].codec, None);
+
+    assert_eq!(
+        serde_json::to_value(&info.streams[1]).unwrap(),
+        serde_json::json!({
+            "index": 1,
+            "kind": "audio",
+            "codec": "mp2",
+            "profile": null,
+            "language": "fre",
+            "title": null,
+            "default": false,
+            "forced": false,
+            "bitRate": 192000,
+            "audio": {"channels": 2, "channelLayout": "stereo", "sampleRate": 48000},
+        })
+    );
+}
+
+#[test]
+fn media_info_invalid() {
+    assert!(parse("").is_err());
+    assert!(parse(r#"{"streams": {}}"#).is_err());
+    // ffprobe prints an empty object for media without streams
+    let info = parse("{}").unwrap();
+    assert!(info.streams.is_empty());
+}
@@ -0,0 +1,16 @@
+use crate::stremio_app::media_probe::media_input;
+use std::env;
+
+#[test]
+fn media_probe_input() {
+    assert_eq!(
+        media_input("http://127.0.0.1:11470/a b/0").as_deref(),
+        Some("http://127.0.0.1:11470/a%20b/0")
+    );
+    let path = env::temp_dir().join("movie.mkv");
+    let path = path.to_string_lossy();
+    assert_eq!(media_input(&path).as_deref(), Some(path.as_ref()));
+    assert_eq!(media_input("movie.mkv"), None);
+    assert_eq!(media_input("concat:/a.ts|/b.ts"), None);
+    assert_eq!(media_input("rtmp://example.com/live"), None);
+}
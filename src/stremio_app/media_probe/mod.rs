@@ -0,0 +1,105 @@
+/*
+Media inspection with the ffprobe shipped with the shell
+
+["media-probe", "path or URL"] replies with
+["media-probe", {"id": 1, "input": "path or URL", "info": {
+    "format": {"name": "matroska,webm", "duration": 5400.5, "bitRate": 8000000, "size": 5400500000},
+    "streams": [{"index": 0, "kind": "video", "codec": "hevc", "profile": "Main 10",
+        "language": null, "title": null, "default": true, "forced": false, "bitRate": null,
+        "video": {"width": 3840, "height": 2160, "frameRate": 23.976, "pixelFormat": "yuv420p10le",
+        "bitDepth": 10, "hdr": "hdr10" | "hdr10+" | "hlg" | "dolby-vision" | null}},
+        {"index": 1, "kind": "audio", ..., "audio": {"channels": 6, "channelLayout": "5.1(side)",
+        "sampleRate": 48000}}]
+}}]
+or, if the media can't be probed, with ["media-probe", {"id": 1, "input": "...", "error": "..."}]
+Local paths, `file://` and HTTP(S) URLs are accepted. The media are probed one
+at a time, in the order of the requests.
+*/
+use anyhow::Context;
+use serde::Serialize;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::Command,
+    time::Duration,
+};
+use url::Url;
+
+use crate::stremio_app::constants::{FFPROBE_EXE, MEDIA_PROBE_TIMEOUT};
+
+pub mod ffprobe;
+pub mod media_info;
+pub use media_info::MediaInfo;
+#[cfg(test)]
+mod media_info_tests;
+#[cfg(test)]
+mod media_probe_tests;
+
+// A program shipped with the installer, next to the shell executable
+pub fn bin_path(exe: &str) -> Result<PathBuf, anyhow::Error> {
+    let mut path = env::current_exe()
+        .and_then(fs::canonicalize)
+        .context("Cannot get the current executable path")?;
+    path.pop();
+    Ok(path.join(exe))
+}
+
+// Only local files and HTTP(S) URLs are passed to ffmpeg and ffprobe, their
+// protocols like `concat:` could read other files. `file://` URLs are turned
+// into paths.
+pub fn media_input(input: &str) -> Option<String> {
+    match Url::parse(input) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Some(url.to_string()),
+        Ok(url) if url.scheme() == "file" => url
+            .to_file_path()
+            .ok()
+            .map(|path| path.to_string_lossy().into_owned()),
+        // Drive letters are parsed as URL schemes
+        _ => Path::new(input).is_absolute().then(|| input.to_string()),
+    }
+}
+
+// Console programs would open a window otherwise
+pub fn hide_window(command: &mut Command) -> &mut Command {
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::CommandExt;
+        command.creation_flags(winapi::um::winbase::CREATE_NO_WINDOW);
+    }
+    command
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct MediaProbeReply {
+    pub id: u64,
+    pub input: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info: Option<MediaInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Probes `input` on the calling thread, which may take up to `MEDIA_PROBE_TIMEOUT`
+pub fn probe_reply(id: u64, input: Option<String>) -> MediaProbeReply {
+    let result = input
+        .as_deref()
+        .and_then(media_input)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported input"))
+        .and_then(|media_input| {
+            let timeout = Duration::from_secs(MEDIA_PROBE_TIMEOUT);
+            ffprobe::probe(&bin_path(FFPROBE_EXE)?, &media_input, timeout)
+        });
+    let (info, error) = match result {
+        Ok(info) => (Some(info), None),
+        Err(error) => {
+            eprintln!("Cannot probe {:?}: {error:#}", input);
+            (None, Some(format!("{error:#}")))
+        }
+    };
+    MediaProbeReply {
+        id,
+        input,
+        info,
+        error,
+    }
+}
@@ -1,6 +1,8 @@
 pub mod app;
 pub use app::MainWindow;
+pub mod config;
 pub mod ipc;
+pub mod media_probe;
 pub mod stremio_player;
 pub mod stremio_server;
 pub mod stremio_wevbiew;
@@ -0,0 +1,278 @@
+use libmpv2::{
+    events::{Event, EventContext, PropertyData},
+    mpv_end_file_reason,
+    mpv_node::MpvNode,
+    EndFileReason, Format, Mpv,
+};
+use libmpv2_sys::{mpv_format, mpv_node, mpv_node__bindgen_ty_1, mpv_node_list};
+use std::{
+    ffi::{CString, NulError},
+    iter, mem,
+    os::raw::{c_char, c_int},
+    pin::Pin,
+    ptr,
+};
+
+use crate::stremio_app::stremio_player::{NodeValue, PlayerProprChange, PropVal};
+
+// The mpv events the player handles. Unlike `libmpv2::events::Event` they
+// don't borrow from mpv, so they can be scripted in tests.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendEvent {
+    PropertyChange {
+        id: u64,
+        change: PlayerProprChange,
+    },
+    StartFile {
+        playlist_entry_id: i64,
+    },
+    FileLoaded,
+    EndFile {
+        reason: EndFileReason,
+        error: Option<i32>,
+    },
+    Seek,
+    PlaybackRestart,
+    VideoReconfig,
+    AudioReconfig,
+    LogMessage {
+        level: String,
+        prefix: String,
+        text: String,
+    },
+    // A `script-message` from an mpv script
+    ClientMessage(Vec<String>),
+    Shutdown,
+}
+
+// Requests to mpv made by the message thread
+pub trait PlayerBackend: Send + Sync {
+    fn run_command(&self, args: &[String]) -> libmpv2::Result<()>;
+    // For the commands whose arguments are passed by name
+    fn run_command_node(&self, command: &NodeValue) -> libmpv2::Result<()>;
+    fn set_prop(&self, name: &str, value: &PropVal) -> libmpv2::Result<()>;
+    fn get_prop(&self, name: &str, format: Format) -> libmpv2::Result<PlayerProprChange>;
+    // Interrupts `PlayerEvents::next_event` on the event thread
+    fn wake_up(&self);
+}
+
+// The mpv event queue, owned by the event thread
+pub trait PlayerEvents {
+    fn observe_prop(&mut self, name: &str, format: Format, id: u64) -> libmpv2::Result<()>;
+    fn unobserve_prop(&mut self, id: u64) -> libmpv2::Result<()>;
+    // A negative timeout means to block until there is an event. Returns
+    // `None` on timeout, on a wake up call or for events the player ignores.
+    fn next_event(&mut self, timeout: f64) -> Option<libmpv2::Result<BackendEvent>>;
+}
+
+impl PlayerBackend for Mpv {
+    // `Mpv::command` joins the arguments into a single string which mpv then
+    // has to parse again, so quotes, backslashes and spaces in file names and
+    // URLs get mangled. `mpv_command` takes the arguments as an array instead.
+    fn run_command(&self, args: &[String]) -> libmpv2::Result<()> {
+        let args = command_c_args(args).map_err(|_| libmpv2::Error::Null)?;
+        let mut args_ptrs: Vec<*const c_char> = args
+            .iter()
+            .map(|arg| arg.as_ptr())
+            .chain(iter::once(ptr::null()))
+            .collect();
+        let result = unsafe { libmpv2_sys::mpv_command(self.ctx.as_ptr(), args_ptrs.as_mut_ptr()) };
+        if result < 0 {
+            Err(libmpv2::Error::Raw(result))
+        } else {
+            Ok(())
+        }
+    }
+    fn run_command_node(&self, command: &NodeValue) -> libmpv2::Result<()> {
+        let mut memory = CNodeMemory::default();
+        let mut command = c_node(command, &mut memory).map_err(|_| libmpv2::Error::Null)?;
+        let mut result: mpv_node = unsafe { mem::zeroed() };
+        let code =
+            unsafe { libmpv2_sys::mpv_command_node(self.ctx.as_ptr(), &mut command, &mut result) };
+        if code < 0 {
+            Err(libmpv2::Error::Raw(code))
+        } else {
+            unsafe { libmpv2_sys::mpv_free_node_contents(&mut result) };
+            Ok(())
+        }
+    }
+    fn set_prop(&self, name: &str, value: &PropVal) -> libmpv2::Result<()> {
+        match value {
+            PropVal::Bool(value) => self.set_property(name, *value),
+            PropVal::Num(value) => self.set_property(name, *value),
+            PropVal::Str(value) => self.set_property(name, value.as_str()),
+        }
+    }
+    fn get_prop(&self, name: &str, format: Format) -> libmpv2::Result<PlayerProprChange> {
+        let value = |data| PlayerProprChange::from_name_value(name.to_string(), data);
+        Ok(match format {
+            Format::Flag => value(PropertyData::Flag(self.get_property(name)?)),
+            Format::Int64 => value(PropertyData::Int64(self.get_property(name)?)),
+            Format::Double => value(PropertyData::Double(self.get_property(name)?)),
+            Format::String => value(PropertyData::Str(&self.get_property::<String>(name)?)),
+            Format::Node => value(PropertyData::Node(&self.get_property::<MpvNode>(name)?)),
+        })
+    }
+    // @TODO create a PR to the `libmpv` crate and then remove `libmpv-sys` from Cargo.toml?
+    fn wake_up(&self) {
+        unsafe { libmpv2_sys::mpv_wakeup(self.ctx.as_ptr()) }
+    }
+}
+
+impl PlayerEvents for EventContext {
+    fn observe_prop(&mut self, name: &str, format: Format, id: u64) -> libmpv2::Result<()> {
+        self.observe_property(name, format, id)
+    }
+    fn unobserve_prop(&mut self, id: u64) -> libmpv2::Result<()> {
+        self.unobserve_property(id)
+    }
+    fn next_event(&mut self, timeout: f64) -> Option<libmpv2::Result<BackendEvent>> {
+        let event = match self.wait_event(timeout)? {
+            Ok(event) => event,
+            // libmpv2 reports an `end-file` event that carries an error as
+            // `Err`. We don't make async requests, so that is the only
+            // event that can fail with an mpv error code.
+            Err(libmpv2::Error::Raw(code)) => {
+                return Some(Ok(BackendEvent::EndFile {
+                    reason: mpv_end_file_reason::Error,
+                    error: Some(code),
+                }))
+            }
+            Err(error) => return Some(Err(error)),
+        };
+        let event = match event {
+            Event::PropertyChange {
+                name,
+                change,
+                reply_userdata,
+            } => BackendEvent::PropertyChange {
+                id: reply_userdata,
+                change: PlayerProprChange::from_name_value(name.to_string(), change),
+            },
+            Event::StartFile(playlist_entry_id) => BackendEvent::StartFile { playlist_entry_id },
+            Event::FileLoaded => BackendEvent::FileLoaded,
+            Event::EndFile(reason) => BackendEvent::EndFile {
+                reason,
+                error: None,
+            },
+            Event::Seek => BackendEvent::Seek,
+            Event::PlaybackRestart => BackendEvent::PlaybackRestart,
+            Event::VideoReconfig => BackendEvent::VideoReconfig,
+            Event::AudioReconfig => BackendEvent::AudioReconfig,
+            Event::LogMessage {
+                prefix,
+                level,
+                text,
+                ..
+            } => BackendEvent::LogMessage {
+                level: level.to_string(),
+                prefix: prefix.to_string(),
+                text: text.to_string(),
+            },
+            Event::ClientMessage(args) => {
+                BackendEvent::ClientMessage(args.into_iter().map(ToString::to_string).collect())
+            }
+            Event::Shutdown => BackendEvent::Shutdown,
+            _ => return None,
+        };
+        Some(Ok(event))
+    }
+}
+
+// Makes mpv send its log messages at `level` or above as events. The level
+// is one of mpv's `--msg-level` levels, "no" turns them off.
+pub fn request_log_messages(mpv: &Mpv, level: &str) -> libmpv2::Result<()> {
+    let level = CString::new(level).map_err(|_| libmpv2::Error::Null)?;
+    let result = unsafe { libmpv2_sys::mpv_request_log_messages(mpv.ctx.as_ptr(), level.as_ptr()) };
+    if result < 0 {
+        Err(libmpv2::Error::Raw(result))
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn command_c_args(args: &[String]) -> Result<Vec<CString>, NulError> {
+    args.iter()
+        .map(|arg| CString::new(arg.as_bytes()))
+        .collect()
+}
+
+// Owns what the `mpv_node` made by `c_node` points to, moving the vectors
+// here doesn't move their items
+#[derive(Default)]
+struct CNodeMemory {
+    strings: Vec<CString>,
+    values: Vec<Vec<mpv_node>>,
+    keys: Vec<Vec<*mut c_char>>,
+    lists: Vec<Pin<Box<mpv_node_list>>>,
+}
+
+fn c_node(value: &NodeValue, memory: &mut CNodeMemory) -> Result<mpv_node, NulError> {
+    let mut c_string = |value: &str| {
+        let string = CString::new(value)?;
+        let ptr = string.as_ptr() as *mut c_char;
+        memory.strings.push(string);
+        Ok::<_, NulError>(ptr)
+    };
+    let (format, u) = match value {
+        NodeValue::None => (mpv_format::None, mpv_node__bindgen_ty_1 { flag: 0 }),
+        NodeValue::Flag(flag) => (
+            mpv_format::Flag,
+            mpv_node__bindgen_ty_1 {
+                flag: c_int::from(*flag),
+            },
+        ),
+        NodeValue::Int64(int64) => (mpv_format::Int64, mpv_node__bindgen_ty_1 { int64: *int64 }),
+        NodeValue::Double(double) => (
+            mpv_format::Double,
+            mpv_node__bindgen_ty_1 { double_: *double },
+        ),
+        NodeValue::Str(string) => (
+            mpv_format::String,
+            mpv_node__bindgen_ty_1 {
+                string: c_string(string)?,
+            },
+        ),
+        NodeValue::Array(items) => {
+            let values = items
+                .iter()
+                .map(|item| c_node(item, memory))
+                .collect::<Result<_, _>>()?;
+            let list = c_node_list(values, None, memory);
+            (mpv_format::Array, mpv_node__bindgen_ty_1 { list })
+        }
+        NodeValue::Map(entries) => {
+            let keys = entries
+                .iter()
+                .map(|(key, _)| c_string(key))
+                .collect::<Result<_, _>>()?;
+            let values = entries
+                .iter()
+                .map(|(_, item)| c_node(item, memory))
+                .collect::<Result<_, _>>()?;
+            let list = c_node_list(values, Some(keys), memory);
+            (mpv_format::Map, mpv_node__bindgen_ty_1 { list })
+        }
+    };
+    Ok(mpv_node { u, format })
+}
+
+fn c_node_list(
+    mut values: Vec<mpv_node>,
+    keys: Option<Vec<*mut c_char>>,
+    memory: &mut CNodeMemory,
+) -> *mut mpv_node_list {
+    let mut list = Box::pin(mpv_node_list {
+        num: values.len() as c_int,
+        values: values.as_mut_ptr(),
+        keys: ptr::null_mut(),
+    });
+    memory.values.push(values);
+    if let Some(mut keys) = keys {
+        list.keys = keys.as_mut_ptr();
+        memory.keys.push(keys);
+    }
+    let ptr = &mut *list as *mut mpv_node_list;
+    memory.lists.push(list);
+    ptr
+}
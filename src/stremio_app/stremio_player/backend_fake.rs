@@ -0,0 +1,197 @@
+use libmpv2::{events::PropertyData, EndFileReason, Format};
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use crate::stremio_app::stremio_player::{
+    backend::{BackendEvent, PlayerBackend, PlayerEvents},
+    NodeValue, PlayerProprChange, PropVal,
+};
+
+// MPV_ERROR_PROPERTY_NOT_FOUND
+const PROPERTY_NOT_FOUND: i32 = -8;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BackendCall {
+    Command(Vec<String>),
+    CommandNode(NodeValue),
+    SetProperty(String, PropVal),
+    GetProperty(String),
+    Observe(String, u64),
+    Unobserve(u64),
+    WakeUp,
+}
+
+// In-memory backend which records the calls made to it
+#[derive(Default)]
+pub struct FakeBackend {
+    calls: Mutex<Vec<BackendCall>>,
+    props: HashMap<String, PlayerProprChange>,
+    error: Option<i32>,
+    observe_error: Option<(String, i32)>,
+    panicking_command: Option<String>,
+    shut_down: AtomicBool,
+    crashed: AtomicBool,
+}
+
+impl FakeBackend {
+    pub fn with_prop(mut self, name: &str, data: PropertyData) -> Self {
+        self.props.insert(
+            name.to_string(),
+            PlayerProprChange::from_name_value(name.to_string(), data),
+        );
+        self
+    }
+    // For node properties, which can't be made from `PropertyData` here
+    pub fn with_json_prop(mut self, name: &str, data: serde_json::Value) -> Self {
+        self.props.insert(
+            name.to_string(),
+            serde_json::from_value(serde_json::json!({ "name": name, "data": data }))
+                .expect("invalid property"),
+        );
+        self
+    }
+    // Makes commands and property changes fail with the mpv error `code`
+    pub fn failing(mut self, code: i32) -> Self {
+        self.error = Some(code);
+        self
+    }
+    // Makes observing the property `name` fail with the mpv error `code`
+    pub fn failing_observe(mut self, name: &str, code: i32) -> Self {
+        self.observe_error = Some((name.to_string(), code));
+        self
+    }
+    // Makes running the command `name` panic
+    pub fn panicking(mut self, name: &str) -> Self {
+        self.panicking_command = Some(name.to_string());
+        self
+    }
+    // Makes the events shut down as if mpv quit
+    pub fn shut_down(&self) {
+        self.shut_down.store(true, Ordering::SeqCst);
+    }
+    // Makes the event thread panic
+    pub fn crash(&self) {
+        self.crashed.store(true, Ordering::SeqCst);
+    }
+    pub fn is_shut_down(&self) -> bool {
+        self.shut_down.load(Ordering::SeqCst)
+    }
+    pub fn calls(&self) -> Vec<BackendCall> {
+        self.calls.lock().expect("poisoned lock").clone()
+    }
+    fn record(&self, call: BackendCall) -> libmpv2::Result<()> {
+        self.calls.lock().expect("poisoned lock").push(call);
+        match self.error {
+            Some(code) => Err(libmpv2::Error::Raw(code)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl PlayerBackend for FakeBackend {
+    fn run_command(&self, args: &[String]) -> libmpv2::Result<()> {
+        if args.first() == self.panicking_command.as_ref() {
+            panic!("{} panicked", args[0]);
+        }
+        match args.first().map(String::as_str) {
+            Some("quit") => self.shut_down(),
+            // mpv writes the file before the command returns
+            Some("screenshot-to-file") if self.error.is_none() => {
+                fs::write(&args[1], b"PNG").expect("cannot write screenshot");
+            }
+            _ => {}
+        }
+        self.record(BackendCall::Command(args.to_vec()))
+    }
+    fn run_command_node(&self, command: &NodeValue) -> libmpv2::Result<()> {
+        if let NodeValue::Map(args) = command {
+            let name = args.iter().find(|(key, _)| key == "name");
+            if let Some((_, NodeValue::Str(name))) = name {
+                if Some(name) == self.panicking_command.as_ref() {
+                    panic!("{} panicked", name);
+                }
+            }
+        }
+        self.record(BackendCall::CommandNode(command.clone()))
+    }
+    fn set_prop(&self, name: &str, value: &PropVal) -> libmpv2::Result<()> {
+        self.record(BackendCall::SetProperty(name.to_string(), value.clone()))
+    }
+    fn get_prop(&self, name: &str, _format: Format) -> libmpv2::Result<PlayerProprChange> {
+        let _ = self.record(BackendCall::GetProperty(name.to_string()));
+        self.props
+            .get(name)
+            .cloned()
+            .ok_or(libmpv2::Error::Raw(PROPERTY_NOT_FOUND))
+    }
+    fn wake_up(&self) {
+        let _ = self.record(BackendCall::WakeUp);
+    }
+}
+
+// Scripted event queue which shuts down once all events are consumed and
+// the backend was shut down
+pub struct FakeEvents {
+    backend: Arc<FakeBackend>,
+    events: VecDeque<BackendEvent>,
+}
+
+impl FakeEvents {
+    pub fn new(backend: &Arc<FakeBackend>, events: impl IntoIterator<Item = BackendEvent>) -> Self {
+        Self {
+            backend: Arc::clone(backend),
+            events: events.into_iter().collect(),
+        }
+    }
+}
+
+impl PlayerEvents for FakeEvents {
+    fn observe_prop(&mut self, name: &str, _format: Format, id: u64) -> libmpv2::Result<()> {
+        self.backend
+            .record(BackendCall::Observe(name.to_string(), id))?;
+        match &self.backend.observe_error {
+            Some((failing, code)) if failing == name => Err(libmpv2::Error::Raw(*code)),
+            _ => Ok(()),
+        }
+    }
+    fn unobserve_prop(&mut self, id: u64) -> libmpv2::Result<()> {
+        self.backend.record(BackendCall::Unobserve(id))
+    }
+    fn next_event(&mut self, _timeout: f64) -> Option<libmpv2::Result<BackendEvent>> {
+        if self.backend.crashed.load(Ordering::SeqCst) {
+            panic!("mpv crashed");
+        }
+        match self.events.pop_front() {
+            Some(event) => Some(Ok(event)),
+            None if self.backend.is_shut_down() => Some(Ok(BackendEvent::Shutdown)),
+            // Same as a wake up call, so the observed properties are updated
+            None => {
+                thread::sleep(Duration::from_millis(1));
+                None
+            }
+        }
+    }
+}
+
+pub fn prop_change(id: u64, name: &str, data: PropertyData) -> BackendEvent {
+    BackendEvent::PropertyChange {
+        id,
+        change: PlayerProprChange::from_name_value(name.to_string(), data),
+    }
+}
+
+pub fn end_file(reason: EndFileReason, error: Option<i32>) -> BackendEvent {
+    BackendEvent::EndFile { reason, error }
+}
+
+pub fn start_file(playlist_entry_id: i64) -> BackendEvent {
+    BackendEvent::StartFile { playlist_entry_id }
+}
@@ -0,0 +1,73 @@
+use crate::stremio_app::stremio_player::{
+    backend::command_c_args,
+    backend_fake::{BackendCall, FakeBackend},
+    communication::loadfile_node,
+    dispatch::MessageHandler,
+    player_log::PlayerLog,
+    supervisor::PlayerStores,
+    CmdVal, LoadfileFlag,
+};
+use std::sync::{Arc, Mutex};
+
+// The command sent to mpv for a web UI message
+fn mpv_call(msg: &str) -> BackendCall {
+    let backend = Arc::new(FakeBackend::default());
+    let (observe_property_sender, _) = flume::unbounded();
+    let (player_event_sender, player_event_receiver) = flume::unbounded();
+    MessageHandler::new(
+        Arc::clone(&backend),
+        PlayerStores {
+            resume_store: Arc::default(),
+            player_log: Arc::new(Mutex::new(PlayerLog::new(10))),
+            settings: Arc::default(),
+            thumbnails: Arc::default(),
+            sources: Arc::default(),
+            loadfile_options: Arc::default(),
+            screenshot_dir: None,
+        },
+        observe_property_sender,
+        player_event_sender,
+    )
+    .handle(msg);
+    assert_eq!(player_event_receiver.drain().count(), 0);
+    match backend.calls().as_slice() {
+        [call] => call.clone(),
+        calls => panic!("not a single command: {:?}", calls),
+    }
+}
+
+#[test]
+fn command_args_verbatim() {
+    let loadfile =
+        |url| BackendCall::CommandNode(loadfile_node(url, LoadfileFlag::Replace, Vec::new()));
+    assert_eq!(
+        mpv_call(r#"["mpv-command", ["loadfile", "C:\\My \"Shows\"\\ep 1.mkv"]]"#),
+        loadfile(r#"C:\My "Shows"\ep 1.mkv"#)
+    );
+    let url = "http://127.0.0.1:11470/hlsv2/f00/video0.m3u8?mediaURL=http%3A%2F%2Fexample.com%2Fa%20b.mkv&videoCodecs=h264&videoCodecs=hevc&maxAudioChannels=2#frag";
+    assert_eq!(
+        mpv_call(&format!(r#"["mpv-command", ["loadfile", "{}"]]"#, url)),
+        loadfile(url)
+    );
+    assert_eq!(
+        mpv_call(
+            r#"["mpv-command", ["sub-add", "C:\\subs\\it's \"a\" sub.srt", "select", "Title, with 'quotes'"]]"#
+        ),
+        BackendCall::Command(
+            [
+                "sub-add",
+                r#"C:\subs\it's "a" sub.srt"#,
+                "select",
+                "Title, with 'quotes'"
+            ]
+            .map(String::from)
+            .to_vec()
+        )
+    );
+}
+
+#[test]
+fn command_args_nul_byte() {
+    assert!(command_c_args(&["loadfile".to_string(), "a\0b".to_string()]).is_err());
+    assert!(command_c_args(&[CmdVal::Stop.name().to_string()]).is_ok());
+}
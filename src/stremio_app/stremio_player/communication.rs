@@ -1,36 +1,107 @@
 use core::convert::TryFrom;
-use libmpv2::{events::PropertyData, mpv_end_file_reason, EndFileReason};
+use libmpv2::{
+    events::PropertyData,
+    mpv_end_file_reason, mpv_error,
+    mpv_node::{MpvNode, MpvNodeValue},
+    EndFileReason, Format,
+};
 use parse_display::{Display, FromStr};
-use serde::{Deserialize, Serialize};
-use std::fmt;
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    ser::SerializeTuple,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{collections::BTreeMap, ffi::CStr, fmt, iter};
+
+use crate::stremio_app::stremio_player::{player_log::LogEntry, resume_store::ResumeEntry};
 
 // Responses
 const JSON_RESPONSES: [&str; 3] = ["track-list", "video-params", "metadata"];
 
+// Owned copy of an mpv node tree. `MpvNode` borrows mpv's memory and can't be
+// built outside of libmpv, so it is converted to this first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeValue {
+    None,
+    Flag(bool),
+    Int64(i64),
+    Double(f64),
+    Str(String),
+    Array(Vec<NodeValue>),
+    Map(Vec<(String, NodeValue)>),
+}
+impl From<&MpvNode> for NodeValue {
+    fn from(node: &MpvNode) -> Self {
+        match node.value() {
+            Ok(MpvNodeValue::String(s)) => Self::Str(s.to_string()),
+            Ok(MpvNodeValue::Flag(d)) => Self::Flag(d),
+            Ok(MpvNodeValue::Int64(d)) => Self::Int64(d),
+            Ok(MpvNodeValue::Double(d)) => Self::Double(d),
+            Ok(MpvNodeValue::Array(items)) => {
+                Self::Array(items.map(|item| Self::from(&item)).collect())
+            }
+            Ok(MpvNodeValue::Map(entries)) => Self::Map(
+                entries
+                    .map(|(key, item)| (key.to_string(), Self::from(&item)))
+                    .collect(),
+            ),
+            Ok(MpvNodeValue::None) => Self::None,
+            Err(error) => {
+                eprintln!("cannot read MPV node: '{error:#}'");
+                Self::None
+            }
+        }
+    }
+}
+impl From<NodeValue> for serde_json::Value {
+    fn from(node: NodeValue) -> Self {
+        match node {
+            NodeValue::None => serde_json::Value::Null,
+            NodeValue::Flag(d) => serde_json::Value::Bool(d),
+            NodeValue::Int64(d) => serde_json::Value::from(d),
+            // NaN and infinity are valid in a node but not in JSON
+            NodeValue::Double(d) => serde_json::Number::from_f64(d)
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+            NodeValue::Str(s) => serde_json::Value::String(s),
+            NodeValue::Array(items) => {
+                serde_json::Value::Array(items.into_iter().map(Self::from).collect())
+            }
+            NodeValue::Map(entries) => serde_json::Value::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, item)| (key, Self::from(item)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
 pub struct PlayerProprChange {
     name: String,
     data: serde_json::Value,
 }
 impl PlayerProprChange {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    pub fn data(&self) -> &serde_json::Value {
+        &self.data
+    }
     fn value_from_format(data: PropertyData, as_json: bool) -> serde_json::Value {
         match data {
             PropertyData::Flag(d) => serde_json::Value::Bool(d),
-            PropertyData::Int64(d) => serde_json::Value::Number(
-                serde_json::Number::from_f64(d as f64).expect("MPV returned invalid number"),
-            ),
-            PropertyData::Double(d) => serde_json::Value::Number(
-                serde_json::Number::from_f64(d).expect("MPV returned invalid number"),
-            ),
+            PropertyData::Int64(d) => serde_json::Value::from(d),
+            // NaN and infinity are valid doubles but not JSON numbers
+            PropertyData::Double(d) => serde_json::Number::from_f64(d)
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
             PropertyData::OsdStr(s) => serde_json::Value::String(s.to_string()),
-            PropertyData::Str(s) => {
-                if as_json {
-                    serde_json::from_str(s).expect("MPV returned invalid JSON data")
-                } else {
-                    serde_json::Value::String(s.to_string())
-                }
-            }
-            PropertyData::Node(_) => unimplemented!("`PropertyData::Node` is not supported"),
+            PropertyData::Str(s) if as_json => serde_json::from_str(s).unwrap_or_else(|error| {
+                eprintln!("MPV returned invalid JSON data: {error}");
+                serde_json::Value::Null
+            }),
+            PropertyData::Str(s) => serde_json::Value::String(s.to_string()),
+            PropertyData::Node(node) => NodeValue::from(node).into(),
         }
     }
     pub fn from_name_value(name: String, value: PropertyData) -> Self {
@@ -40,36 +111,384 @@ impl PlayerProprChange {
             data: Self::value_from_format(value, is_json),
         }
     }
+    // The integers from mpv as floats, as they were sent before the
+    // `int-props` capability. The JSON properties are left as they are.
+    fn with_float_ints(self) -> Self {
+        fn float_ints(value: serde_json::Value) -> serde_json::Value {
+            match value {
+                serde_json::Value::Number(n) if !n.is_f64() => n
+                    .as_f64()
+                    .and_then(serde_json::Number::from_f64)
+                    .map_or(serde_json::Value::Null, serde_json::Value::Number),
+                serde_json::Value::Array(items) => {
+                    serde_json::Value::Array(items.into_iter().map(float_ints).collect())
+                }
+                serde_json::Value::Object(entries) => serde_json::Value::Object(
+                    entries
+                        .into_iter()
+                        .map(|(key, item)| (key, float_ints(item)))
+                        .collect(),
+                ),
+                value => value,
+            }
+        }
+        if JSON_RESPONSES.contains(&self.name.as_str()) {
+            return self;
+        }
+        Self {
+            data: float_ints(self.data),
+            ..self
+        }
+    }
+}
+
+// Protocol features the web UI opted in to with the handshake
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlayerCapabilities {
+    // Integers from mpv are sent as JSON integers rather than floats, so
+    // track ids are `1` instead of `1.0` and byte counts keep their precision
+    pub int_props: bool,
+}
+impl PlayerCapabilities {
+    pub const INT_PROPS: &'static str = "int-props";
+    // Unknown capabilities are ignored
+    pub fn from_names<'a>(names: impl IntoIterator<Item = &'a str>) -> Self {
+        Self {
+            int_props: names.into_iter().any(|name| name == Self::INT_PROPS),
+        }
+    }
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.int_props {
+            names.push(Self::INT_PROPS);
+        }
+        names
+    }
 }
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EndReason {
+    Eof,
+    Stop,
+    Quit,
+    Error,
+    Redirect,
+    Other,
+}
+impl From<EndFileReason> for EndReason {
+    fn from(reason: EndFileReason) -> Self {
+        match reason {
+            mpv_end_file_reason::Eof => Self::Eof,
+            mpv_end_file_reason::Stop => Self::Stop,
+            mpv_end_file_reason::Quit => Self::Quit,
+            mpv_end_file_reason::Error => Self::Error,
+            mpv_end_file_reason::Redirect => Self::Redirect,
+            _ => Self::Other,
+        }
+    }
+}
+
+// The mpv errors a file can end with. The names are part of the protocol so
+// they must not change even if mpv renames its error constants.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EndFileError {
+    // MPV_ERROR_LOADING_FAILED, also network and I/O errors
+    LoadingFailed,
+    // MPV_ERROR_AO_INIT_FAILED
+    AudioOutputFailed,
+    // MPV_ERROR_VO_INIT_FAILED
+    VideoOutputFailed,
+    // MPV_ERROR_NOTHING_TO_PLAY, there are no audio or video streams
+    NothingToPlay,
+    // MPV_ERROR_UNKNOWN_FORMAT
+    UnknownFormat,
+    // MPV_ERROR_UNSUPPORTED
+    Unsupported,
+    // MPV_ERROR_NOT_IMPLEMENTED
+    NotImplemented,
+    // MPV_ERROR_GENERIC and anything else
+    Generic,
+}
+impl From<i32> for EndFileError {
+    fn from(code: i32) -> Self {
+        match code {
+            mpv_error::LoadingFailed => Self::LoadingFailed,
+            mpv_error::AoInitFailed => Self::AudioOutputFailed,
+            mpv_error::VoInitFailed => Self::VideoOutputFailed,
+            mpv_error::NothingToPlay => Self::NothingToPlay,
+            mpv_error::UnknownFormat => Self::UnknownFormat,
+            mpv_error::Unsupported => Self::Unsupported,
+            mpv_error::NotImplemented => Self::NotImplemented,
+            _ => Self::Generic,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[serde(rename_all = "camelCase")]
 pub struct PlayerEnded {
-    reason: String,
+    pub reason: EndReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<EndFileError>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub playlist_entry_id: Option<i64>,
 }
 impl PlayerEnded {
-    fn string_from_end_reason(data: EndFileReason) -> String {
-        match data {
-            mpv_end_file_reason::Error => "error".to_string(),
-            mpv_end_file_reason::Quit => "quit".to_string(),
-            _ => "other".to_string(),
+    pub fn from_end_reason(data: EndFileReason) -> Self {
+        Self {
+            reason: data.into(),
+            error: None,
+            playlist_entry_id: None,
         }
     }
-    pub fn from_end_reason(data: EndFileReason) -> Self {
+    pub fn from_end_file(
+        data: EndFileReason,
+        error: Option<i32>,
+        playlist_entry_id: Option<i64>,
+    ) -> Self {
         Self {
-            reason: Self::string_from_end_reason(data),
+            error: error.map(EndFileError::from),
+            playlist_entry_id,
+            ..Self::from_end_reason(data)
         }
     }
+    // Errors where trying another stream for the same item may help
+    pub fn is_stream_error(&self) -> bool {
+        matches!(
+            self.error,
+            Some(
+                EndFileError::LoadingFailed
+                    | EndFileError::NothingToPlay
+                    | EndFileError::UnknownFormat
+                    | EndFileError::Unsupported
+            )
+        )
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct PlayerPropValue {
+    pub id: u64,
+    #[serde(flatten)]
+    pub prop: PlayerProprChange,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct PlayerError {
     pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<i32>,
+    pub message: serde_json::Value,
 }
-#[derive(Serialize, Deserialize, Debug, Clone)]
+impl PlayerError {
+    // The originating message is sent back as JSON if possible so the web UI
+    // can match it to the request that failed
+    fn message_value(message: &str) -> serde_json::Value {
+        serde_json::from_str(message)
+            .unwrap_or_else(|_| serde_json::Value::String(message.to_string()))
+    }
+    pub fn new(message: &str, error: impl ToString) -> Self {
+        Self {
+            error: error.to_string(),
+            code: None,
+            message: Self::message_value(message),
+        }
+    }
+    pub fn from_mpv(message: &str, error: &libmpv2::Error) -> Self {
+        match error {
+            libmpv2::Error::Raw(code) => Self {
+                error: mpv_error_string(*code),
+                code: Some(*code),
+                message: Self::message_value(message),
+            },
+            error => Self::new(message, format!("{error:#}")),
+        }
+    }
+}
+fn mpv_error_string(code: i32) -> String {
+    // mpv returns a static string even for unknown error codes
+    unsafe { CStr::from_ptr(libmpv2_sys::mpv_error_string(code)) }
+        .to_string_lossy()
+        .into_owned()
+}
+// Sent after mpv was recreated because it stopped unexpectedly or quit by
+// itself. The observed properties are restored, the path and position are set
+// if the file being played was loaded again.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerRestarted {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_pos: Option<f64>,
+}
+
+// The audio device saved in the settings is not available when mpv starts,
+// so mpv uses the "auto" device instead
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerAudioDeviceMissing {
+    pub device: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HwdecFallbackReason {
+    // The hardware decoder kept failing to decode frames
+    DecodeErrors,
+    // mpv dropped the hardware decoder after decoding errors
+    DecoderLost,
+    // The file ended with an error while it was decoded by hardware
+    EndFileError,
+    // The codec failed with hardware decoding before
+    Remembered,
+}
+
+// A `script-message` sent by an mpv script
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerScriptMessage {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+// The file being played switched to software decoding. The codec is saved,
+// so its next files are decoded by software right away.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerHwdecFallback {
+    pub reason: HwdecFallbackReason,
+    // The hardware decoder which failed, e.g. "d3d11va"
+    pub hwdec: Option<String>,
+    // The codec of the video, e.g. "hevc"
+    pub codec: Option<String>,
+    // Whether the file was loaded again, from `time_pos` if it is set
+    pub reloaded: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_pos: Option<f64>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SourceFailReason {
+    // The source ended with an error, another source may not have it
+    Error,
+    // The source didn't start playing before the startup timeout
+    Timeout,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerSourceFailure {
+    pub url: String,
+    pub reason: SourceFailReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<EndFileError>,
+}
+
+// Reply to "player-load-with-fallbacks" once one of the sources plays, with
+// the ones which failed before it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerSourceLoaded {
+    pub id: u64,
+    pub index: usize,
+    pub url: String,
+    pub failed: Vec<PlayerSourceFailure>,
+}
+
+// Reply to "player-load-with-fallbacks" if none of the sources played
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct PlayerSourcesFailed {
+    pub id: u64,
+    pub failed: Vec<PlayerSourceFailure>,
+}
+
+// Reply to "player-get-resume" and "player-clear-resume"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerResume {
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub resume: Option<ResumeEntry>,
+}
+
+// Reply to "player-get-logs"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerLogs {
+    pub id: u64,
+    pub logs: Vec<LogEntry>,
+}
+
+// Reply to "player-export-logs" with the file the logs were written to
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerLogsExported {
+    pub id: u64,
+    pub path: String,
+}
+
+// Reply to "player-screenshot"
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerScreenshot {
+    pub id: u64,
+    pub path: String,
+    // The PNG file encoded as base64, if it was asked for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub base64: Option<String>,
+}
+
+// Reply to "player-thumbnail-at", all null if no frame is extracted yet
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PlayerThumbnail {
+    pub id: u64,
+    // The time of the frame in seconds
+    pub time: Option<f64>,
+    pub path: Option<String>,
+    // The JPEG file encoded as base64
+    pub base64: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum PlayerEvent {
     PropChange(PlayerProprChange),
+    PropValue(PlayerPropValue),
+    Resume(PlayerResume),
+    Logs(PlayerLogs),
+    LogsExported(PlayerLogsExported),
+    Screenshot(PlayerScreenshot),
+    Thumbnail(PlayerThumbnail),
     End(PlayerEnded),
     Error(PlayerError),
+    AudioDeviceMissing(PlayerAudioDeviceMissing),
+    HwdecFallback(PlayerHwdecFallback),
+    SourceLoaded(PlayerSourceLoaded),
+    SourcesFailed(PlayerSourcesFailed),
+    ScriptMessage(PlayerScriptMessage),
+    Restarted(PlayerRestarted),
+    // Lifecycle events without any data are sent with `null`
+    StartFile,
+    FileLoaded,
+    Seek,
+    PlaybackRestart,
+    VideoReconfig,
+    AudioReconfig,
+    Idle,
+}
+
+impl PlayerEvent {
+    // Converts the event to the form the web UI asked for
+    pub fn for_capabilities(self, capabilities: PlayerCapabilities) -> Self {
+        match self {
+            Self::PropChange(change) if !capabilities.int_props => {
+                Self::PropChange(change.with_float_ints())
+            }
+            Self::PropValue(PlayerPropValue { id, prop }) if !capabilities.int_props => {
+                Self::PropValue(PlayerPropValue {
+                    id,
+                    prop: prop.with_float_ints(),
+                })
+            }
+            event => event,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -79,29 +498,159 @@ impl PlayerResponse<'_> {
         serde_json::to_value(self).ok()
     }
 }
+impl From<PlayerEvent> for PlayerResponse<'static> {
+    fn from(event: PlayerEvent) -> Self {
+        let name = match event {
+            PlayerEvent::PropChange(_) => "mpv-prop-change",
+            PlayerEvent::PropValue(_) => "mpv-prop-value",
+            PlayerEvent::Resume(_) => "player-resume",
+            PlayerEvent::Logs(_) => "player-logs",
+            PlayerEvent::LogsExported(_) => "player-logs-exported",
+            PlayerEvent::Screenshot(_) => "player-screenshot",
+            PlayerEvent::Thumbnail(_) => "player-thumbnail",
+            PlayerEvent::End(_) => "mpv-event-ended",
+            PlayerEvent::Error(_) => "mpv-error",
+            PlayerEvent::AudioDeviceMissing(_) => "player-audio-device-missing",
+            PlayerEvent::HwdecFallback(_) => "player-hwdec-fallback",
+            PlayerEvent::SourceLoaded(_) => "player-source-loaded",
+            PlayerEvent::SourcesFailed(_) => "player-sources-failed",
+            PlayerEvent::ScriptMessage(_) => "mpv-script-message",
+            PlayerEvent::Restarted(_) => "player-restarted",
+            PlayerEvent::StartFile => "mpv-event-start-file",
+            PlayerEvent::FileLoaded => "mpv-event-file-loaded",
+            PlayerEvent::Seek => "mpv-event-seek",
+            PlayerEvent::PlaybackRestart => "mpv-event-playback-restart",
+            PlayerEvent::VideoReconfig => "mpv-event-video-reconfig",
+            PlayerEvent::AudioReconfig => "mpv-event-audio-reconfig",
+            PlayerEvent::Idle => "mpv-event-idle",
+        };
+        Self(name, event)
+    }
+}
 
 // Player incoming messages from the web UI
 /*
 Message general case - ["function-name", ["arguments", ...]]
-The function could be either mpv-observe-prop, mpv-set-prop or mpv-command.
+The function could be either mpv-observe-prop, mpv-unobserve-prop,
+mpv-get-prop, mpv-set-prop or mpv-command.
 
 ["mpv-observe-prop", "prop-name"]
+["mpv-unobserve-prop", "prop-name"]
+["mpv-get-prop", "prop-name"]
 ["mpv-set-prop", ["prop-name", prop-val]]
 ["mpv-command", ["command-name"<, "arguments">]]
 
 All the function and property names are in kebab-case.
 
+Integer property values, like track ids, are sent as floats unless the web UI
+asks for the "int-props" capability with the handshake:
+{"id": 0, "args": [{"capabilities": ["int-props"]}]}
+The handshake reply lists the enabled capabilities in the "capabilities" property.
+
 MPV requires type for any prop-name when observing or setting it's value.
 The type for setting is not always the same as the type for observing the prop.
+Both are listed in `PROP_SCHEMA`, a message with an unknown property or with
+a value of the wrong type or out of range is rejected with "mpv-error".
+"mute" is a flag, it is observed as true or false rather than a number. It is
+still set with 0 or 1 as well, like any other flag. "input-defalt-bindings" is still accepted for "input-default-bindings",
+which is the name sent back in "mpv-prop-change".
+
+"mpv-observe-prop" and "mpv-unobserve-prop" functions are the only ones that
+accept single string instead of array of arguments. Observing an already
+observed property does nothing. If mpv fails to observe or unobserve it, the
+message is answered with "mpv-error". No "mpv-prop-change" is sent for a
+property once it is unobserved.
+
+Structured properties like "chapter-list" are observed as mpv nodes and
+arrive in "mpv-prop-change" as plain JSON arrays and objects.
 
-"mpv-observe-prop" function is the only one that accepts single string
-instead of array of arguments
+"mpv-get-prop" replies with ["mpv-prop-value", {"id": 1, "name": "prop-name", "data": prop-val}]
+where the id is the one of the RPC request. The shell passes the id to the
+player by wrapping the arguments: ["mpv-get-prop", {"id": 1, "params": "prop-name"}]
 
 "mpv-command" function always takes an array even if the command doesn't
-have any arguments. For example this are the commands we support:
+have any arguments. The arguments are typed and checked per command, optional
+ones are in angle brackets:
 
-["mpv-command", ["loadfile", "file name"]]
+["mpv-command", ["loadfile", "file name"<, "replace" | "append" | "append-play"<, {"start": 61.5,
+"httpHeaders": {"Cookie": "a=1"}, "referrer": "https://example.com/", "userAgent": "..."}>>]]
+The options only apply to the file being loaded, they need a flag before them.
 ["mpv-command", ["stop"]]
+["mpv-command", ["seek", 10.5<, "relative" | "absolute" | "relative-percent" | "absolute-percent">]]
+["mpv-command", ["sub-add", "url"<, "select" | "auto" | "cached"<, "title"<, "lang">>>]]
+["mpv-command", ["audio-add", "url"<, "select" | "auto" | "cached"<, "title"<, "lang">>>]]
+["mpv-command", ["sub-remove"<, track-id>]]
+["mpv-command", ["cycle", "prop-name"<, "up" | "down">]]
+["mpv-command", ["frame-step"]]
+["mpv-command", ["playlist-next"<, "weak" | "force">]]
+["mpv-command", ["playlist-prev"<, "weak" | "force">]]
+["mpv-command", ["screenshot-to-file", "file name"<, "subtitles" | "video" | "window">]]
+["mpv-command", ["set", "prop-name", prop-val]]
+["mpv-command", ["script-message", "message-name"<, "arg", ...>]]
+["mpv-command", ["script-message-to", "script-name", "message-name"<, "arg", ...>]]
+
+mpv scripts are loaded from the "scripts" folder of the config directory, or from
+the folder set with `--player-scripts-dir`. The messages the scripts send to the
+shell, and those sent to all the scripts, are forwarded as
+["mpv-script-message", {"name": "message-name", "args": ["arg", ...]}]
+
+The shell keeps the position, the selected tracks and the subtitle delay of
+the files played. Both functions reply the same way as "mpv-get-prop":
+
+["player-get-resume", "path or URL"] replies with
+["player-resume", {"id": 1, "path": "path or URL", "resume": {"path": "...",
+"timePos": 61.5, "aid": "1", "sid": "no", "subDelay": 0, "updatedAt": 1700000000}}]
+where "resume" is null if there is no saved position. URLs are saved without
+their user name, password and query parameters like "token" or "api_key", so
+the "path" of "resume" may differ from the one requested.
+["player-clear-resume"<, "path or URL">] forgets the position of the file, or
+of all the files, and replies with ["player-resume", {"id": 1<, "path": "...">, "resume": null}]
+
+The shell keeps the last mpv log messages for diagnostics:
+
+["player-get-logs"] replies with ["player-logs", {"id": 1, "logs": [{"time": 1700000000000,
+"level": "error", "prefix": "ffmpeg", "text": "..."}]}] with the oldest message first.
+["player-export-logs"] writes the versions and the log messages to "player-log.txt"
+in the configuration directory and replies with
+["player-logs-exported", {"id": 1, "path": "file name"}]
+
+["player-screenshot"<, {"subtitles": true, "dir": "folder", "template": "{title}-{pos}-{date}",
+"base64": false}>] saves the current frame as a PNG file and replies with
+["player-screenshot", {"id": 1, "path": "file name"<, "base64": "PNG data">}]
+All the options are optional. "dir" is a subfolder of the "screenshots" folder in
+the configuration directory, absolute paths and ".." are rejected. Without "dir"
+the file is written to a temp folder and removed after a while. The template may contain "{title}", "{pos}" (HH-MM-SS)
+and "{date}" (UTC, YYYYMMDD-HHMMSS), ".png" is appended to it.
+
+While a file with a duration plays, ffmpeg extracts a small frame every few
+seconds in the background. ["player-thumbnail-at", 61.5] replies with the
+frame nearest to the time, or the last one extracted so far:
+["player-thumbnail", {"id": 1, "time": 60, "path": "file name", "base64": "JPEG data"}]
+All the fields but "id" are null if there is no frame yet.
+
+The output devices are in "audio-device-list" as [{"name": "wasapi/{guid}",
+"description": "Speakers"}, ...]. The device set with ["mpv-set-prop", ["audio-device",
+"wasapi/{guid}"]] is saved and used again the next time mpv starts, "auto" forgets it.
+If the saved device is missing then, mpv uses "auto" and the shell sends
+["player-audio-device-missing", {"device": "wasapi/{guid}"}]
+
+If hardware decoding fails, the file is played with software decoding and the
+shell sends ["player-hwdec-fallback", {"reason": "decode-errors", "hwdec": "d3d11va",
+"codec": "hevc", "reloaded": true<, "timePos": 61.5>}]
+"decode-errors" and "end-file-error" load the file again from the same position,
+"decoder-lost" means mpv already switched to software decoding by itself. The codec
+is saved and its next files are decoded by software right away, which is sent as
+"remembered" with "reloaded": false.
+
+Add-ons may return several streams for the same item. ["player-load-with-fallbacks",
+{"sources": [{"url": "...", "options": {...}}, ...]<, "startupTimeout": 20>}] loads
+the first source, with the same options as "loadfile". If it ends with a stream error
+or doesn't start playing within the timeout in seconds, the next one is loaded
+instead, without sending "mpv-event-ended" for it. Replies with
+["player-source-loaded", {"id": 1, "index": 1, "url": "...", "failed": [{"url": "...",
+"reason": "error" | "timeout"<, "error": "loading-failed">}]}] once a source plays, or
+with ["player-sources-failed", {"id": 1, "failed": [...]}] after the last one failed.
+There is no reply if another file is loaded or playback is stopped in the meantime.
 */
 macro_rules! stringable {
     ($t:ident) => {
@@ -127,88 +676,248 @@ pub enum InMsgFn {
     MpvSetProp,
     MpvCommand,
     MpvObserveProp,
+    MpvUnobserveProp,
+    MpvGetProp,
+    PlayerGetResume,
+    PlayerClearResume,
+    PlayerGetLogs,
+    PlayerExportLogs,
+    PlayerScreenshot,
+    PlayerThumbnailAt,
+    PlayerLoadWithFallbacks,
 }
 stringable!(InMsgFn);
-// Bool
-#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-#[serde(try_from = "String", into = "String")]
-#[display(style = "kebab-case")]
-pub enum BoolProp {
-    Pause,
-    PausedForCache,
-    Seeking,
-    EofReached,
-}
-stringable!(BoolProp);
-// Int
-#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-#[serde(try_from = "String", into = "String")]
-#[display(style = "kebab-case")]
-pub enum IntProp {
-    Aid,
-    Vid,
-    Sid,
+impl InMsgFn {
+    // The shell adds the RPC request id to the arguments of these functions
+    pub fn expects_reply(&self) -> bool {
+        matches!(
+            self,
+            Self::MpvGetProp
+                | Self::PlayerGetResume
+                | Self::PlayerClearResume
+                | Self::PlayerGetLogs
+                | Self::PlayerExportLogs
+                | Self::PlayerScreenshot
+                | Self::PlayerThumbnailAt
+                | Self::PlayerLoadWithFallbacks
+        )
+    }
 }
-stringable!(IntProp);
-// Fp
-#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-#[serde(try_from = "String", into = "String")]
-#[display(style = "kebab-case")]
-pub enum FpProp {
-    TimePos,
-    Mute,
-    Volume,
-    Duration,
-    SubDelay,
-    SubScale,
-    CacheBufferingState,
-    SubPos,
-    Speed,
-}
-stringable!(FpProp);
-// Str
-#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-#[serde(try_from = "String", into = "String")]
-#[display(style = "kebab-case")]
-pub enum StrProp {
-    FfmpegVersion,
-    Hwdec,
-    InputDefaltBindings,
-    InputVoKeyboard,
-    Metadata,
-    MpvVersion,
-    Osc,
-    Path,
-    SubAssOverride,
-    SubBackColor,
-    SubBorderColor,
-    SubColor,
-    TrackList,
-    VideoParams,
-    // Vo,
-}
-stringable!(StrProp);
-
-// Any
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-#[serde(untagged)]
-pub enum PropKey {
-    Bool(BoolProp),
-    Int(IntProp),
-    Fp(FpProp),
-    Str(StrProp),
+// A kind of value a property can be set to. Strings are accepted too if mpv
+// would parse them as the same kind, e.g. "yes" for a flag or "2" for a track.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PropType {
+    Flag,
+    // Inclusive range, the bounds may be infinite
+    Num(f64, f64),
+    Int(i64, i64),
+    Str,
+    Choice(&'static [&'static str]),
 }
-impl fmt::Display for PropKey {
+impl PropType {
+    fn accepts(&self, value: &PropVal) -> bool {
+        match (self, value) {
+            (Self::Flag, PropVal::Bool(_)) => true,
+            (Self::Flag, PropVal::Str(s)) => s == "yes" || s == "no",
+            // What the web UI sent while "mute" was a number
+            (Self::Flag, PropVal::Num(n)) => *n == 0. || *n == 1.,
+            (Self::Num(min, max), PropVal::Num(n)) => (*min..=*max).contains(n),
+            (Self::Num(min, max), PropVal::Str(s)) => {
+                s.parse::<f64>().is_ok_and(|n| (*min..=*max).contains(&n))
+            }
+            (Self::Int(min, max), PropVal::Num(n)) => {
+                n.fract() == 0. && (*min as f64..=*max as f64).contains(n)
+            }
+            (Self::Int(min, max), PropVal::Str(s)) => {
+                s.parse::<i64>().is_ok_and(|n| (*min..=*max).contains(&n))
+            }
+            (Self::Str, PropVal::Str(_)) => true,
+            (Self::Choice(choices), PropVal::Str(s)) => choices.contains(&s.as_str()),
+            _ => false,
+        }
+    }
+}
+impl fmt::Display for PropType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn range(f: &mut fmt::Formatter, min: f64, max: f64) -> fmt::Result {
+            match (min.is_finite(), max.is_finite()) {
+                (true, true) => write!(f, " between {min} and {max}"),
+                (true, false) => write!(f, " of at least {min}"),
+                (false, true) => write!(f, " of at most {max}"),
+                (false, false) => Ok(()),
+            }
+        }
         match self {
-            Self::Bool(v) => write!(f, "{}", v),
-            Self::Int(v) => write!(f, "{}", v),
-            Self::Fp(v) => write!(f, "{}", v),
-            Self::Str(v) => write!(f, "{}", v),
+            Self::Flag => write!(f, "a flag"),
+            Self::Num(min, max) => {
+                write!(f, "a number")?;
+                range(f, *min, *max)
+            }
+            Self::Int(min, max) => {
+                write!(f, "an integer")?;
+                let bound = |n: i64| {
+                    if n == i64::MIN || n == i64::MAX {
+                        f64::INFINITY.copysign(n as f64)
+                    } else {
+                        n as f64
+                    }
+                };
+                range(f, bound(*min), bound(*max))
+            }
+            Self::Str => write!(f, "a string"),
+            Self::Choice(choices) => write!(f, "one of {}", choices.join(", ")),
         }
     }
 }
 
+pub struct PropSchema {
+    pub name: &'static str,
+    // The format the property is observed and read with
+    pub format: Format,
+    // What "mpv-set-prop" and the `set` command accept, nothing if the
+    // property is read-only
+    pub set: &'static [PropType],
+}
+
+const fn prop(name: &'static str, format: Format, set: &'static [PropType]) -> PropSchema {
+    PropSchema { name, format, set }
+}
+const TRACK: [PropType; 2] = [
+    PropType::Int(1, i64::MAX),
+    PropType::Choice(&["no", "auto"]),
+];
+
+// The properties the web UI may observe, read and set
+pub const PROP_SCHEMA: &[PropSchema] = &[
+    prop("pause", Format::Flag, &[PropType::Flag]),
+    prop("paused-for-cache", Format::Flag, &[]),
+    prop("seeking", Format::Flag, &[]),
+    prop("eof-reached", Format::Flag, &[]),
+    prop("mute", Format::Flag, &[PropType::Flag]),
+    prop("aid", Format::Int64, &TRACK),
+    prop("vid", Format::Int64, &TRACK),
+    prop("sid", Format::Int64, &TRACK),
+    prop(
+        "time-pos",
+        Format::Double,
+        &[PropType::Num(0., f64::INFINITY)],
+    ),
+    prop("volume", Format::Double, &[PropType::Num(0., 1000.)]),
+    prop("duration", Format::Double, &[]),
+    prop(
+        "sub-delay",
+        Format::Double,
+        &[PropType::Num(f64::NEG_INFINITY, f64::INFINITY)],
+    ),
+    prop("sub-scale", Format::Double, &[PropType::Num(0., 100.)]),
+    prop("sub-pos", Format::Double, &[PropType::Num(0., 150.)]),
+    prop("speed", Format::Double, &[PropType::Num(0.01, 100.)]),
+    prop("cache-buffering-state", Format::Double, &[]),
+    prop("ffmpeg-version", Format::String, &[]),
+    prop("mpv-version", Format::String, &[]),
+    prop("hwdec", Format::String, &[PropType::Str]),
+    prop("input-default-bindings", Format::String, &[PropType::Flag]),
+    prop("input-vo-keyboard", Format::String, &[PropType::Flag]),
+    prop("osc", Format::String, &[PropType::Flag]),
+    prop("path", Format::String, &[]),
+    prop("metadata", Format::String, &[]),
+    prop("track-list", Format::String, &[]),
+    prop("video-params", Format::String, &[]),
+    prop(
+        "sub-ass-override",
+        Format::String,
+        &[
+            PropType::Flag,
+            PropType::Choice(&["force", "scale", "strip"]),
+        ],
+    ),
+    // Colors are either names or "#RRGGBB" like strings, mpv checks them
+    prop("sub-color", Format::String, &[PropType::Str]),
+    prop("sub-border-color", Format::String, &[PropType::Str]),
+    prop("sub-back-color", Format::String, &[PropType::Str]),
+    prop("chapter-list", Format::Node, &[]),
+    prop("demuxer-cache-state", Format::Node, &[]),
+    // Devices are names from `audio-device-list`, e.g. "wasapi/{guid}" or "auto"
+    prop("audio-device", Format::String, &[PropType::Str]),
+    prop("audio-device-list", Format::Node, &[]),
+];
+
+// Names the web UI used before they were fixed, with the property they stand for
+const PROP_ALIASES: [(&str, &str); 1] = [("input-defalt-bindings", "input-default-bindings")];
+
+// A property from `PROP_SCHEMA`, sent as its name
+#[derive(Clone, Copy)]
+pub struct PropKey(&'static PropSchema);
+impl PropKey {
+    pub fn find(name: &str) -> Option<Self> {
+        let name = PROP_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, name)| name);
+        PROP_SCHEMA
+            .iter()
+            .find(|schema| schema.name == name)
+            .map(Self)
+    }
+    pub fn name(&self) -> &'static str {
+        self.0.name
+    }
+    pub fn format(&self) -> Format {
+        self.0.format
+    }
+    // Whether the property can be set to `value`, with the reason if it can't
+    pub fn check(&self, value: &PropVal) -> Result<(), String> {
+        let set = self.0.set;
+        if set.is_empty() {
+            return Err(format!("`{}` is read-only", self.name()));
+        }
+        if set.iter().any(|prop_type| prop_type.accepts(value)) {
+            return Ok(());
+        }
+        let expected: Vec<String> = set.iter().map(ToString::to_string).collect();
+        Err(format!(
+            "invalid value for `{}`: expected {}",
+            self.name(),
+            expected.join(" or ")
+        ))
+    }
+    // Checks `value`, a flag sent as 0 or 1 is passed to mpv as a flag
+    pub fn set_value(&self, value: PropVal) -> Result<PropVal, String> {
+        self.check(&value)?;
+        Ok(match (self.format(), value) {
+            (Format::Flag, PropVal::Num(n)) => PropVal::Bool(n == 1.),
+            (_, value) => value,
+        })
+    }
+}
+impl PartialEq for PropKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
+}
+impl Eq for PropKey {}
+impl fmt::Debug for PropKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PropKey({:?})", self.name())
+    }
+}
+impl fmt::Display for PropKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+impl Serialize for PropKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+impl<'de> Deserialize<'de> for PropKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        Self::find(&name).ok_or_else(|| de::Error::custom(format!("unknown property `{name}`")))
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum PropVal {
@@ -217,46 +926,506 @@ pub enum PropVal {
     Num(f64),
 }
 
-#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
 #[serde(try_from = "String", into = "String")]
 #[display(style = "kebab-case")]
 #[serde(untagged)]
 pub enum MpvCmd {
     Loadfile,
     Stop,
+    Seek,
+    SubAdd,
+    AudioAdd,
+    SubRemove,
+    Cycle,
+    FrameStep,
+    PlaylistNext,
+    PlaylistPrev,
+    ScreenshotToFile,
+    Set,
+    ScriptMessage,
+    ScriptMessageTo,
 }
 stringable!(MpvCmd);
 
-#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
-#[serde(untagged)]
+#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+#[display(style = "kebab-case")]
+pub enum SeekMode {
+    Relative,
+    Absolute,
+    RelativePercent,
+    AbsolutePercent,
+}
+stringable!(SeekMode);
+
+#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+#[display(style = "kebab-case")]
+pub enum TrackFlag {
+    Select,
+    Auto,
+    Cached,
+}
+stringable!(TrackFlag);
+
+#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+#[display(style = "kebab-case")]
+pub enum CycleDirection {
+    Up,
+    Down,
+}
+stringable!(CycleDirection);
+
+#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+#[display(style = "kebab-case")]
+pub enum PlaylistFlag {
+    Weak,
+    Force,
+}
+stringable!(PlaylistFlag);
+
+#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+#[display(style = "kebab-case")]
+pub enum ScreenshotFlag {
+    Subtitles,
+    Video,
+    Window,
+}
+stringable!(ScreenshotFlag);
+
+#[derive(Display, FromStr, Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+#[display(style = "kebab-case")]
+pub enum LoadfileFlag {
+    Replace,
+    Append,
+    AppendPlay,
+}
+stringable!(LoadfileFlag);
+
+// Options of `loadfile` which only apply to the file being loaded
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default, deny_unknown_fields)]
+pub struct LoadfileOptions {
+    // Seconds, negative ones are from the end
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub start: Option<f64>,
+    // Header names and values sent with the HTTP requests
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub http_headers: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub referrer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+}
+impl LoadfileOptions {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+    // The values end up in HTTP requests, so line breaks could add headers
+    pub fn check(&self) -> Result<(), String> {
+        let is_valid_value = |value: &str| !value.chars().any(|c| c.is_control());
+        if self.start.is_some_and(|start| !start.is_finite()) {
+            return Err("`start` must be a finite number".to_string());
+        }
+        for (name, value) in &self.http_headers {
+            let is_token = |c: char| c.is_ascii_graphic() && c != ':';
+            if name.is_empty() || !name.chars().all(is_token) {
+                return Err(format!("invalid HTTP header name `{name}`"));
+            }
+            if !is_valid_value(value) {
+                return Err(format!("invalid value for the HTTP header `{name}`"));
+            }
+        }
+        for (name, value) in [
+            ("referrer", &self.referrer),
+            ("userAgent", &self.user_agent),
+        ] {
+            if value.as_deref().is_some_and(|value| !is_valid_value(value)) {
+                return Err(format!("invalid `{name}`"));
+            }
+        }
+        Ok(())
+    }
+    // mpv's per-file options as names and values
+    pub fn to_mpv(&self) -> Vec<(String, String)> {
+        let mut options = Vec::new();
+        if let Some(start) = self.start {
+            options.push(("start".to_string(), start.to_string()));
+        }
+        if !self.http_headers.is_empty() {
+            // A list option, the commas and backslashes in its items are escaped
+            let fields: Vec<String> = self
+                .http_headers
+                .iter()
+                .map(|(name, value)| {
+                    format!("{name}: {value}")
+                        .replace('\\', "\\\\")
+                        .replace(',', "\\,")
+                })
+                .collect();
+            options.push(("http-header-fields".to_string(), fields.join(",")));
+        }
+        if let Some(referrer) = &self.referrer {
+            options.push(("referrer".to_string(), referrer.clone()));
+        }
+        if let Some(user_agent) = &self.user_agent {
+            options.push(("user-agent".to_string(), user_agent.clone()));
+        }
+        options
+    }
+}
+
+// `loadfile` with named arguments. mpv 0.38 added an argument before the
+// options, so they can't be passed by position to every mpv version.
+pub fn loadfile_node(url: &str, flag: LoadfileFlag, options: Vec<(String, String)>) -> NodeValue {
+    let mut args = vec![
+        (
+            "name".to_string(),
+            NodeValue::Str(MpvCmd::Loadfile.to_string()),
+        ),
+        ("url".to_string(), NodeValue::Str(url.to_string())),
+        ("flags".to_string(), NodeValue::Str(flag.to_string())),
+    ];
+    if !options.is_empty() {
+        let options = options
+            .into_iter()
+            .map(|(name, value)| (name, NodeValue::Str(value)))
+            .collect();
+        args.push(("options".to_string(), NodeValue::Map(options)));
+    }
+    NodeValue::Map(args)
+}
+
+// External track for `sub-add` and `audio-add`:
+// <url> [<flag> [<title> [<lang>]]]
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TrackSource {
+    pub url: String,
+    pub flag: Option<TrackFlag>,
+    pub title: Option<String>,
+    pub lang: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum CmdVal {
-    Single((MpvCmd,)),
-    Double(MpvCmd, String),
-    Tripple(MpvCmd, String, String),
-    Quadruple(MpvCmd, String, String, String),
-    Quintuple(MpvCmd, String, String, String, String),
+    Loadfile(String, Option<LoadfileFlag>, Option<LoadfileOptions>),
+    Stop,
+    Seek(f64, Option<SeekMode>),
+    SubAdd(TrackSource),
+    AudioAdd(TrackSource),
+    SubRemove(Option<u32>),
+    Cycle(PropKey, Option<CycleDirection>),
+    FrameStep,
+    PlaylistNext(Option<PlaylistFlag>),
+    PlaylistPrev(Option<PlaylistFlag>),
+    ScreenshotToFile(String, Option<ScreenshotFlag>),
+    Set(PropKey, PropVal),
+    // The message name followed by its arguments, for every mpv script
+    ScriptMessage(Vec<String>),
+    // The same for the script with the given name only
+    ScriptMessageTo(String, Vec<String>),
+}
+
+// A single command argument as it appears in the JSON message
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+enum CmdArg {
+    Str(String),
+    Num(f64),
+    Int(u32),
+    Val(PropVal),
 }
+impl From<CmdArg> for String {
+    fn from(arg: CmdArg) -> Self {
+        match arg {
+            CmdArg::Str(s) | CmdArg::Val(PropVal::Str(s)) => s,
+            CmdArg::Num(n) | CmdArg::Val(PropVal::Num(n)) => n.to_string(),
+            CmdArg::Int(n) => n.to_string(),
+            CmdArg::Val(PropVal::Bool(b)) => if b { "yes" } else { "no" }.to_string(),
+        }
+    }
+}
+
+impl CmdVal {
+    pub fn name(&self) -> MpvCmd {
+        match self {
+            Self::Loadfile(..) => MpvCmd::Loadfile,
+            Self::Stop => MpvCmd::Stop,
+            Self::Seek(..) => MpvCmd::Seek,
+            Self::SubAdd(..) => MpvCmd::SubAdd,
+            Self::AudioAdd(..) => MpvCmd::AudioAdd,
+            Self::SubRemove(..) => MpvCmd::SubRemove,
+            Self::Cycle(..) => MpvCmd::Cycle,
+            Self::FrameStep => MpvCmd::FrameStep,
+            Self::PlaylistNext(..) => MpvCmd::PlaylistNext,
+            Self::PlaylistPrev(..) => MpvCmd::PlaylistPrev,
+            Self::ScreenshotToFile(..) => MpvCmd::ScreenshotToFile,
+            Self::Set(..) => MpvCmd::Set,
+            Self::ScriptMessage(..) => MpvCmd::ScriptMessage,
+            Self::ScriptMessageTo(..) => MpvCmd::ScriptMessageTo,
+        }
+    }
+    // The commands whose arguments are passed to mpv by name
+    pub fn to_node(&self) -> Option<NodeValue> {
+        match self {
+            Self::Loadfile(url, flag, options) => Some(loadfile_node(
+                url,
+                flag.unwrap_or(LoadfileFlag::Replace),
+                options
+                    .as_ref()
+                    .map(LoadfileOptions::to_mpv)
+                    .unwrap_or_default(),
+            )),
+            _ => None,
+        }
+    }
+    fn args(&self) -> Vec<CmdArg> {
+        fn flag(flag: Option<impl ToString>) -> Vec<CmdArg> {
+            flag.map(|flag| CmdArg::Str(flag.to_string()))
+                .into_iter()
+                .collect()
+        }
+        match self {
+            Self::Loadfile(url, flag, options) => {
+                let mut args = vec![CmdArg::Str(url.clone())];
+                let options = options.as_ref().filter(|options| !options.is_empty());
+                // The options can only be passed after a flag
+                if flag.is_some() || options.is_some() {
+                    let flag = flag.unwrap_or(LoadfileFlag::Replace);
+                    args.push(CmdArg::Str(flag.to_string()));
+                }
+                args
+            }
+            Self::Stop | Self::FrameStep => vec![],
+            Self::Seek(target, mode) => [vec![CmdArg::Num(*target)], flag(*mode)].concat(),
+            Self::SubAdd(track) | Self::AudioAdd(track) => {
+                let mut args = vec![CmdArg::Str(track.url.clone())];
+                // The arguments are positional, so a title requires a flag and
+                // a language requires a title
+                if track.flag.is_some() || track.title.is_some() || track.lang.is_some() {
+                    let flag = track.flag.unwrap_or(TrackFlag::Select);
+                    args.push(CmdArg::Str(flag.to_string()));
+                }
+                if track.title.is_some() || track.lang.is_some() {
+                    args.push(CmdArg::Str(track.title.clone().unwrap_or_default()));
+                }
+                if let Some(lang) = &track.lang {
+                    args.push(CmdArg::Str(lang.clone()));
+                }
+                args
+            }
+            Self::SubRemove(id) => id.map(CmdArg::Int).into_iter().collect(),
+            Self::Cycle(prop, direction) => {
+                [vec![CmdArg::Str(prop.to_string())], flag(*direction)].concat()
+            }
+            Self::PlaylistNext(mode) | Self::PlaylistPrev(mode) => flag(*mode),
+            Self::ScreenshotToFile(file, mode) => {
+                [vec![CmdArg::Str(file.clone())], flag(*mode)].concat()
+            }
+            Self::Set(prop, value) => {
+                vec![CmdArg::Str(prop.to_string()), CmdArg::Val(value.clone())]
+            }
+            Self::ScriptMessage(message) => message.iter().cloned().map(CmdArg::Str).collect(),
+            Self::ScriptMessageTo(target, message) => iter::once(target)
+                .chain(message)
+                .cloned()
+                .map(CmdArg::Str)
+                .collect(),
+        }
+    }
+}
+// The positional arguments, the `loadfile` options are only passed by name
 impl From<CmdVal> for Vec<String> {
     fn from(cmd: CmdVal) -> Vec<String> {
-        match cmd {
-            CmdVal::Single(cmd) => vec![cmd.0.to_string()],
-            CmdVal::Double(cmd, arg) => vec![cmd.to_string(), arg],
-            CmdVal::Tripple(cmd, arg1, arg2) => vec![cmd.to_string(), arg1, arg2],
-            CmdVal::Quadruple(cmd, arg1, arg2, arg3) => vec![cmd.to_string(), arg1, arg2, arg3],
-            CmdVal::Quintuple(cmd, arg1, arg2, arg3, arg4) => {
-                vec![cmd.to_string(), arg1, arg2, arg3, arg4]
+        let mut args = vec![cmd.name().to_string()];
+        args.extend(cmd.args().into_iter().map(String::from));
+        args
+    }
+}
+impl Serialize for CmdVal {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let args = self.args();
+        let options = match self {
+            Self::Loadfile(_, _, options) => options.as_ref().filter(|options| !options.is_empty()),
+            _ => None,
+        };
+        let mut tuple = serializer.serialize_tuple(args.len() + 1 + options.iter().len())?;
+        tuple.serialize_element(&self.name())?;
+        for arg in args {
+            tuple.serialize_element(&arg)?;
+        }
+        if let Some(options) = options {
+            tuple.serialize_element(options)?;
+        }
+        tuple.end()
+    }
+}
+impl<'de> Deserialize<'de> for CmdVal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_seq(CmdValVisitor)
+    }
+}
+
+struct CmdValVisitor;
+impl<'de> Visitor<'de> for CmdValVisitor {
+    type Value = CmdVal;
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array with an mpv command name followed by its arguments")
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        macro_rules! required {
+            ($index:literal) => {
+                seq.next_element()?
+                    .ok_or_else(|| de::Error::invalid_length($index, &self))?
+            };
+        }
+        fn not_empty<E: de::Error>(name: &str, value: String) -> Result<String, E> {
+            if value.is_empty() {
+                Err(E::custom(format!("`{name}` must not be empty")))
+            } else {
+                Ok(value)
             }
         }
+
+        let name: MpvCmd = required!(0);
+        let cmd = match name {
+            MpvCmd::Loadfile => {
+                let url = not_empty("url", required!(1))?;
+                let flag = seq.next_element::<Option<LoadfileFlag>>()?.flatten();
+                let options: Option<LoadfileOptions> = seq.next_element()?;
+                if let Some(options) = &options {
+                    options.check().map_err(de::Error::custom)?;
+                }
+                CmdVal::Loadfile(url, flag, options)
+            }
+            MpvCmd::Stop => CmdVal::Stop,
+            MpvCmd::Seek => {
+                let target: f64 = required!(1);
+                let mode: Option<SeekMode> = seq.next_element()?;
+                if !target.is_finite() {
+                    return Err(de::Error::custom("seek target must be a finite number"));
+                }
+                if mode == Some(SeekMode::AbsolutePercent) && !(0.0..=100.0).contains(&target) {
+                    return Err(de::Error::custom(
+                        "absolute-percent seek target must be between 0 and 100",
+                    ));
+                }
+                CmdVal::Seek(target, mode)
+            }
+            MpvCmd::SubAdd | MpvCmd::AudioAdd => {
+                let track = TrackSource {
+                    url: not_empty("url", required!(1))?,
+                    flag: seq.next_element()?,
+                    title: seq.next_element()?,
+                    lang: seq.next_element()?,
+                };
+                if name == MpvCmd::SubAdd {
+                    CmdVal::SubAdd(track)
+                } else {
+                    CmdVal::AudioAdd(track)
+                }
+            }
+            MpvCmd::SubRemove => CmdVal::SubRemove(seq.next_element()?),
+            MpvCmd::Cycle => CmdVal::Cycle(required!(1), seq.next_element()?),
+            MpvCmd::FrameStep => CmdVal::FrameStep,
+            MpvCmd::PlaylistNext => CmdVal::PlaylistNext(seq.next_element()?),
+            MpvCmd::PlaylistPrev => CmdVal::PlaylistPrev(seq.next_element()?),
+            MpvCmd::ScreenshotToFile => {
+                CmdVal::ScreenshotToFile(not_empty("filename", required!(1))?, seq.next_element()?)
+            }
+            MpvCmd::Set => {
+                let prop: PropKey = required!(1);
+                let value = prop.set_value(required!(2)).map_err(de::Error::custom)?;
+                CmdVal::Set(prop, value)
+            }
+            MpvCmd::ScriptMessage => {
+                let mut message = vec![not_empty("message", required!(1))?];
+                while let Some(arg) = seq.next_element()? {
+                    message.push(arg);
+                }
+                CmdVal::ScriptMessage(message)
+            }
+            MpvCmd::ScriptMessageTo => {
+                let target = not_empty("target", required!(1))?;
+                let mut message = vec![not_empty("message", required!(2))?];
+                while let Some(arg) = seq.next_element()? {
+                    message.push(arg);
+                }
+                CmdVal::ScriptMessageTo(target, message)
+            }
+        };
+        if seq.next_element::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::custom(format!(
+                "too many arguments for `{name}`"
+            )));
+        }
+        Ok(cmd)
     }
 }
 
+// Arguments of the functions that reply to the web UI
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RequestArgs {
+    pub id: u64,
+    pub params: serde_json::Value,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
 #[serde(untagged)]
 pub enum InMsgArgs {
     StProp(PropKey, PropVal),
     Cmd(CmdVal),
     ObProp(PropKey),
+    Request(RequestArgs),
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+// The arguments of "mpv-set-prop", checked against the property schema
+struct SetPropArgs(PropKey, PropVal);
+impl<'de> Deserialize<'de> for SetPropArgs {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (prop, value): (PropKey, PropVal) = Deserialize::deserialize(deserializer)?;
+        let value = prop.set_value(value).map_err(de::Error::custom)?;
+        Ok(Self(prop, value))
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub struct InMsg(pub InMsgFn, pub InMsgArgs);
+// The arguments are parsed according to the function, so a message with
+// invalid arguments is rejected with the reason instead of matching another
+// kind of arguments
+impl<'de> Deserialize<'de> for InMsg {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_tuple_struct("InMsg", 2, InMsgVisitor)
+    }
+}
+
+struct InMsgVisitor;
+impl<'de> Visitor<'de> for InMsgVisitor {
+    type Value = InMsg;
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array with a function name and its arguments")
+    }
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let in_msg_fn: InMsgFn = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let args = match in_msg_fn {
+            InMsgFn::MpvObserveProp | InMsgFn::MpvUnobserveProp => {
+                seq.next_element()?.map(InMsgArgs::ObProp)
+            }
+            InMsgFn::MpvSetProp => seq
+                .next_element()?
+                .map(|SetPropArgs(prop, value)| InMsgArgs::StProp(prop, value)),
+            InMsgFn::MpvCommand => seq.next_element()?.map(InMsgArgs::Cmd),
+            _ => seq.next_element()?.map(InMsgArgs::Request),
+        };
+        let args = args.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(InMsg(in_msg_fn, args))
+    }
+}
@@ -1,10 +1,21 @@
 use crate::stremio_app::stremio_player::communication::{
-    BoolProp, CmdVal, InMsg, InMsgArgs, InMsgFn, MpvCmd, PlayerEnded, PlayerProprChange, PropKey,
-    PropVal,
+    CmdVal, CycleDirection, EndFileError, HwdecFallbackReason, InMsg, InMsgArgs, InMsgFn,
+    LoadfileFlag, LoadfileOptions, NodeValue, PlayerAudioDeviceMissing, PlayerCapabilities,
+    PlayerEnded, PlayerError, PlayerEvent, PlayerHwdecFallback, PlayerLogs, PlayerLogsExported,
+    PlayerPropValue, PlayerProprChange, PlayerResponse, PlayerScreenshot, PlayerScriptMessage,
+    PlayerSourceFailure, PlayerSourceLoaded, PlayerSourcesFailed, PlayerThumbnail, PlaylistFlag,
+    PropKey, PropVal, RequestArgs, ScreenshotFlag, SeekMode, SourceFailReason, TrackFlag,
+    TrackSource, PROP_SCHEMA,
 };
-use libmpv2::{events::PropertyData, mpv_end_file_reason};
+use crate::stremio_app::stremio_player::player_log::LogEntry;
+use libmpv2::{events::PropertyData, mpv_end_file_reason, Format};
 
-use serde_test::{assert_tokens, Token};
+use serde_json::json;
+use serde_test::{assert_ser_tokens, assert_tokens, Token};
+
+fn prop(name: &str) -> PropKey {
+    PropKey::find(name).expect("unknown property")
+}
 
 #[test]
 fn propr_change_tokens() {
@@ -31,7 +42,8 @@ fn propr_change_tokens() {
         );
     }
     tokens_by_type(&tokens, prop, PropertyData::Flag(true), Token::Bool(true));
-    tokens_by_type(&tokens, prop, PropertyData::Int64(1), Token::F64(1.0));
+    tokens_by_type(&tokens, prop, PropertyData::Int64(1), Token::U64(1));
+    tokens_by_type(&tokens, prop, PropertyData::Int64(-1), Token::I64(-1));
     tokens_by_type(&tokens, prop, PropertyData::Double(1.0), Token::F64(1.0));
     tokens_by_type(&tokens, prop, PropertyData::OsdStr("ok"), Token::Str("ok"));
     tokens_by_type(&tokens, prop, PropertyData::Str("ok"), Token::Str("ok"));
@@ -55,6 +67,125 @@ fn propr_change_tokens() {
         PropertyData::Str(r#""ok""#),
         Token::Str("ok"),
     );
+
+    // Values JSON can't hold are sent as null
+    tokens_by_type(&tokens, prop, PropertyData::Double(f64::NAN), Token::Unit);
+    tokens_by_type(&tokens, "metadata", PropertyData::Str("{"), Token::Unit);
+}
+
+#[test]
+fn node_to_json() {
+    // A trimmed down `chapter-list`
+    let chapters = NodeValue::Array(vec![
+        NodeValue::Map(vec![
+            ("title".to_string(), NodeValue::Str("Intro".to_string())),
+            ("time".to_string(), NodeValue::Double(0.0)),
+        ]),
+        NodeValue::Map(vec![
+            ("title".to_string(), NodeValue::Str("Episode".to_string())),
+            ("time".to_string(), NodeValue::Double(90.5)),
+        ]),
+    ]);
+    assert_eq!(
+        serde_json::Value::from(chapters),
+        json!([
+            {"title": "Intro", "time": 0.0},
+            {"title": "Episode", "time": 90.5},
+        ])
+    );
+
+    // A trimmed down `demuxer-cache-state`
+    let cache_state = NodeValue::Map(vec![
+        (
+            "seekable-ranges".to_string(),
+            NodeValue::Array(vec![NodeValue::Map(vec![
+                ("start".to_string(), NodeValue::Double(0.0)),
+                ("end".to_string(), NodeValue::Double(42.0)),
+            ])]),
+        ),
+        ("bof-cached".to_string(), NodeValue::Flag(true)),
+        ("fw-bytes".to_string(), NodeValue::Int64(1024)),
+        ("debug-low-level-seeks".to_string(), NodeValue::None),
+    ]);
+    assert_eq!(
+        serde_json::Value::from(cache_state),
+        json!({
+            "seekable-ranges": [{"start": 0.0, "end": 42.0}],
+            "bof-cached": true,
+            "fw-bytes": 1024,
+            "debug-low-level-seeks": null,
+        })
+    );
+}
+
+#[test]
+fn int_props_capability() {
+    assert_eq!(
+        PlayerCapabilities::from_names(["int-props", "unknown"]),
+        PlayerCapabilities { int_props: true }
+    );
+    assert_eq!(
+        PlayerCapabilities::from_names([]),
+        PlayerCapabilities::default()
+    );
+    assert_eq!(
+        PlayerCapabilities { int_props: true }.names(),
+        vec!["int-props"]
+    );
+
+    let prop_change = |name: &str, data| -> PlayerProprChange {
+        serde_json::from_value(json!({"name": name, "data": data})).unwrap()
+    };
+    let change = |name: &str, data| PlayerEvent::PropChange(prop_change(name, data));
+    let data = |event: PlayerEvent| match event {
+        PlayerEvent::PropChange(change) => serde_json::to_value(change).unwrap()["data"].take(),
+        event => panic!("unexpected event {:?}", event),
+    };
+    let legacy = PlayerCapabilities::default();
+    let int_props = PlayerCapabilities { int_props: true };
+    let cache_state = json!({"fw-bytes": 1024, "ranges": [{"start": 1.5}], "eof": true});
+
+    assert_eq!(
+        data(change("aid", json!(2)).for_capabilities(int_props)),
+        json!(2)
+    );
+    // Older web UI builds get the integers as floats
+    assert_eq!(
+        data(change("aid", json!(2)).for_capabilities(legacy)),
+        json!(2.0)
+    );
+    assert_eq!(
+        data(change("demuxer-cache-state", cache_state.clone()).for_capabilities(legacy)),
+        json!({"fw-bytes": 1024.0, "ranges": [{"start": 1.5}], "eof": true})
+    );
+    // The JSON properties always had their integers
+    assert_eq!(
+        data(change("track-list", json!([{"id": 1}])).for_capabilities(legacy)),
+        json!([{"id": 1}])
+    );
+    match PlayerEvent::PropValue(PlayerPropValue {
+        id: 7,
+        prop: prop_change("playlist-pos", json!(0)),
+    })
+    .for_capabilities(legacy)
+    {
+        PlayerEvent::PropValue(value) => {
+            assert_eq!(value.id, 7);
+            assert_eq!(value.prop, prop_change("playlist-pos", json!(0.0)));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn node_to_json_invalid_numbers() {
+    assert_eq!(
+        serde_json::Value::from(NodeValue::Array(vec![
+            NodeValue::Double(f64::NAN),
+            NodeValue::Double(f64::INFINITY),
+        ])),
+        json!([null, null])
+    );
 }
 
 #[test]
@@ -69,25 +200,291 @@ fn ended_tokens() {
         Token::StructEnd,
     ];
     let mut typed_tokens = tokens.clone();
-    typed_tokens[2] = Token::Str("error");
+    typed_tokens[2] = Token::UnitVariant {
+        name: "EndReason",
+        variant: "error",
+    };
     assert_tokens(
         &PlayerEnded::from_end_reason(mpv_end_file_reason::Error),
         &typed_tokens,
     );
     let mut typed_tokens = tokens.clone();
-    typed_tokens[2] = Token::Str("quit");
+    typed_tokens[2] = Token::UnitVariant {
+        name: "EndReason",
+        variant: "quit",
+    };
     assert_tokens(
         &PlayerEnded::from_end_reason(mpv_end_file_reason::Quit),
         &typed_tokens,
     );
 }
 
+#[test]
+fn ended_reasons() {
+    let reasons = [
+        (mpv_end_file_reason::Eof, "eof"),
+        (mpv_end_file_reason::Stop, "stop"),
+        (mpv_end_file_reason::Quit, "quit"),
+        (mpv_end_file_reason::Error, "error"),
+        (mpv_end_file_reason::Redirect, "redirect"),
+        (42, "other"),
+    ];
+    for (reason, name) in reasons {
+        assert_eq!(
+            serde_json::to_value(PlayerEnded::from_end_reason(reason)).ok(),
+            Some(json!({ "reason": name }))
+        );
+    }
+}
+
+#[test]
+fn ended_errors() {
+    let errors = [
+        (-13, "loading-failed", true),
+        (-14, "audio-output-failed", false),
+        (-15, "video-output-failed", false),
+        (-16, "nothing-to-play", true),
+        (-17, "unknown-format", true),
+        (-18, "unsupported", true),
+        (-19, "not-implemented", false),
+        (-20, "generic", false),
+        (-100, "generic", false),
+    ];
+    for (code, name, is_stream_error) in errors {
+        let ended = PlayerEnded::from_end_file(mpv_end_file_reason::Error, Some(code), Some(3));
+        assert_eq!(ended.is_stream_error(), is_stream_error, "{}", name);
+        assert_eq!(
+            serde_json::to_value(&ended).ok(),
+            Some(json!({ "reason": "error", "error": name, "playlistEntryId": 3 }))
+        );
+    }
+    assert!(!PlayerEnded::from_end_file(mpv_end_file_reason::Eof, None, None).is_stream_error());
+}
+
+#[test]
+fn error_response() {
+    let msg = r#"["mpv-command",["loadfile","http://example.com/video.mkv"]]"#;
+    let error = PlayerError::from_mpv(msg, &libmpv2::Error::Raw(-13));
+    assert_eq!(error.code, Some(-13));
+    assert_eq!(
+        error.message,
+        json!(["mpv-command", ["loadfile", "http://example.com/video.mkv"]])
+    );
+    assert!(!error.error.is_empty());
+
+    assert_eq!(
+        PlayerResponse(
+            "mpv-error",
+            PlayerEvent::Error(PlayerError::new("not json", "invalid message"))
+        )
+        .to_value(),
+        Some(json!(["mpv-error", {
+            "error": "invalid message",
+            "message": "not json",
+        }]))
+    );
+}
+
+#[test]
+fn lifecycle_responses() {
+    let events = [
+        (PlayerEvent::StartFile, "mpv-event-start-file"),
+        (PlayerEvent::FileLoaded, "mpv-event-file-loaded"),
+        (PlayerEvent::Seek, "mpv-event-seek"),
+        (PlayerEvent::PlaybackRestart, "mpv-event-playback-restart"),
+        (PlayerEvent::VideoReconfig, "mpv-event-video-reconfig"),
+        (PlayerEvent::AudioReconfig, "mpv-event-audio-reconfig"),
+        (PlayerEvent::Idle, "mpv-event-idle"),
+    ];
+    for (event, name) in events {
+        assert_eq!(
+            PlayerResponse::from(event).to_value(),
+            Some(json!([name, null]))
+        );
+    }
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::End(PlayerEnded::from_end_reason(
+            mpv_end_file_reason::Error
+        )))
+        .to_value(),
+        Some(json!(["mpv-event-ended", {"reason": "error"}]))
+    );
+}
+
+#[test]
+fn logs_responses() {
+    assert!(InMsgFn::PlayerGetLogs.expects_reply());
+    assert!(InMsgFn::PlayerExportLogs.expects_reply());
+    let entry = LogEntry {
+        time: 1_700_000_000_000,
+        level: "error".to_string(),
+        prefix: "ffmpeg".to_string(),
+        text: "http: 404 Not Found".to_string(),
+    };
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::Logs(PlayerLogs {
+            id: 3,
+            logs: vec![entry],
+        }))
+        .to_value(),
+        Some(json!(["player-logs", {"id": 3, "logs": [{
+            "time": 1_700_000_000_000u64,
+            "level": "error",
+            "prefix": "ffmpeg",
+            "text": "http: 404 Not Found",
+        }]}]))
+    );
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::LogsExported(PlayerLogsExported {
+            id: 4,
+            path: "C:\\player-log.txt".to_string(),
+        }))
+        .to_value(),
+        Some(json!(["player-logs-exported", {"id": 4, "path": "C:\\player-log.txt"}]))
+    );
+}
+
+#[test]
+fn audio_device_props() {
+    assert_eq!(prop("audio-device").format(), Format::String);
+    assert!(prop("audio-device")
+        .check(&PropVal::Str("wasapi/{hdmi}".to_string()))
+        .is_ok());
+    assert!(prop("audio-device-list")
+        .check(&PropVal::Str("auto".to_string()))
+        .is_err());
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::AudioDeviceMissing(PlayerAudioDeviceMissing {
+            device: "wasapi/{hdmi}".to_string(),
+        }))
+        .to_value(),
+        Some(json!(["player-audio-device-missing", {"device": "wasapi/{hdmi}"}]))
+    );
+}
+
+#[test]
+fn screenshot_response() {
+    assert!(InMsgFn::PlayerScreenshot.expects_reply());
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::Screenshot(PlayerScreenshot {
+            id: 5,
+            path: "C:\\shot.png".to_string(),
+            base64: None,
+        }))
+        .to_value(),
+        Some(json!(["player-screenshot", {"id": 5, "path": "C:\\shot.png"}]))
+    );
+}
+
+#[test]
+fn script_message_response() {
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::ScriptMessage(PlayerScriptMessage {
+            name: "silence-skipped".to_string(),
+            args: vec!["12.5".to_string()],
+        }))
+        .to_value(),
+        Some(json!(["mpv-script-message", {"name": "silence-skipped", "args": ["12.5"]}]))
+    );
+}
+
+#[test]
+fn hwdec_fallback_response() {
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::HwdecFallback(PlayerHwdecFallback {
+            reason: HwdecFallbackReason::EndFileError,
+            hwdec: Some("d3d11va".to_string()),
+            codec: Some("hevc".to_string()),
+            reloaded: true,
+            time_pos: Some(61.5),
+        }))
+        .to_value(),
+        Some(
+            json!(["player-hwdec-fallback", {"reason": "end-file-error", "hwdec": "d3d11va",
+            "codec": "hevc", "reloaded": true, "timePos": 61.5}])
+        )
+    );
+}
+
+#[test]
+fn thumbnail_response() {
+    assert!(InMsgFn::PlayerThumbnailAt.expects_reply());
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::Thumbnail(PlayerThumbnail {
+            id: 5,
+            time: None,
+            path: None,
+            base64: None,
+        }))
+        .to_value(),
+        Some(json!(["player-thumbnail", {"id": 5, "time": null, "path": null, "base64": null}]))
+    );
+}
+
+#[test]
+fn source_responses() {
+    assert!(InMsgFn::PlayerLoadWithFallbacks.expects_reply());
+    let failed = vec![
+        PlayerSourceFailure {
+            url: "https://a/1.mkv".to_string(),
+            reason: SourceFailReason::Error,
+            error: Some(EndFileError::LoadingFailed),
+        },
+        PlayerSourceFailure {
+            url: "https://b/1.mkv".to_string(),
+            reason: SourceFailReason::Timeout,
+            error: None,
+        },
+    ];
+    let failed_json = json!([
+        {"url": "https://a/1.mkv", "reason": "error", "error": "loading-failed"},
+        {"url": "https://b/1.mkv", "reason": "timeout"},
+    ]);
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::SourceLoaded(PlayerSourceLoaded {
+            id: 3,
+            index: 2,
+            url: "https://c/1.mkv".to_string(),
+            failed: failed.clone(),
+        }))
+        .to_value(),
+        Some(
+            json!(["player-source-loaded", {"id": 3, "index": 2, "url": "https://c/1.mkv",
+            "failed": failed_json}])
+        )
+    );
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::SourcesFailed(PlayerSourcesFailed {
+            id: 3,
+            failed
+        }))
+        .to_value(),
+        Some(json!(["player-sources-failed", {"id": 3, "failed": failed_json}]))
+    );
+}
+
 #[test]
 fn ob_propr_tokens() {
+    assert_tokens(
+        &InMsg(InMsgFn::MpvObserveProp, InMsgArgs::ObProp(prop("pause"))),
+        &[
+            Token::TupleStruct {
+                name: "InMsg",
+                len: 2,
+            },
+            Token::Str("mpv-observe-prop"),
+            Token::Str("pause"),
+            Token::TupleStructEnd,
+        ],
+    );
+}
+
+#[test]
+fn ob_node_propr_tokens() {
     assert_tokens(
         &InMsg(
             InMsgFn::MpvObserveProp,
-            InMsgArgs::ObProp(PropKey::Bool(BoolProp::Pause)),
+            InMsgArgs::ObProp(prop("chapter-list")),
         ),
         &[
             Token::TupleStruct {
@@ -95,18 +492,63 @@ fn ob_propr_tokens() {
                 len: 2,
             },
             Token::Str("mpv-observe-prop"),
-            Token::Str("pause"),
+            Token::Str("chapter-list"),
             Token::TupleStructEnd,
         ],
     );
 }
 
+#[test]
+fn unob_propr_tokens() {
+    assert_tokens(
+        &InMsg(
+            InMsgFn::MpvUnobserveProp,
+            InMsgArgs::ObProp(prop("time-pos")),
+        ),
+        &[
+            Token::TupleStruct {
+                name: "InMsg",
+                len: 2,
+            },
+            Token::Str("mpv-unobserve-prop"),
+            Token::Str("time-pos"),
+            Token::TupleStructEnd,
+        ],
+    );
+}
+
+#[test]
+fn get_propr_request() {
+    assert!(InMsgFn::MpvGetProp.expects_reply());
+    assert!(!InMsgFn::MpvObserveProp.expects_reply());
+    assert_eq!(
+        serde_json::from_value::<InMsg>(json!(["mpv-get-prop", { "id": 7, "params": "time-pos" }]))
+            .ok(),
+        Some(InMsg(
+            InMsgFn::MpvGetProp,
+            InMsgArgs::Request(RequestArgs {
+                id: 7,
+                params: json!("time-pos"),
+            })
+        ))
+    );
+}
+
+#[test]
+fn get_propr_response() {
+    let prop = PlayerProprChange::from_name_value("pause".to_string(), PropertyData::Flag(true));
+    assert_eq!(
+        PlayerResponse::from(PlayerEvent::PropValue(PlayerPropValue { id: 7, prop })).to_value(),
+        Some(json!(["mpv-prop-value", { "id": 7, "name": "pause", "data": true }]))
+    );
+}
+
 #[test]
 fn set_propr_tokens() {
     assert_tokens(
         &InMsg(
             InMsgFn::MpvSetProp,
-            InMsgArgs::StProp(PropKey::Bool(BoolProp::Pause), PropVal::Bool(true)),
+            InMsgArgs::StProp(prop("pause"), PropVal::Bool(true)),
         ),
         &[
             Token::TupleStruct {
@@ -123,13 +565,118 @@ fn set_propr_tokens() {
     );
 }
 
+#[test]
+fn set_propr_schema() {
+    let set_prop = |args: serde_json::Value| {
+        serde_json::from_value::<InMsg>(json!(["mpv-set-prop", args]))
+            .map_err(|error| error.to_string())
+    };
+    for args in [
+        json!(["pause", false]),
+        json!(["pause", "yes"]),
+        json!(["mute", true]),
+        json!(["aid", 2]),
+        json!(["sid", "3"]),
+        json!(["sid", "no"]),
+        json!(["time-pos", 61.5]),
+        json!(["sub-delay", -2.5]),
+        json!(["speed", "1.25"]),
+        json!(["sub-ass-override", "force"]),
+        json!(["sub-color", "#FFFFFF"]),
+    ] {
+        assert!(
+            set_prop(args.clone()).is_ok(),
+            "{} should be accepted",
+            args
+        );
+    }
+    for (args, error) in [
+        (
+            json!(["pause", 3]),
+            "invalid value for `pause`: expected a flag",
+        ),
+        (
+            json!(["pause", "maybe"]),
+            "invalid value for `pause`: expected a flag",
+        ),
+        (
+            json!(["aid", 1.5]),
+            "invalid value for `aid`: expected an integer of at least 1 or one of no, auto",
+        ),
+        (
+            json!(["volume", 1001]),
+            "invalid value for `volume`: expected a number between 0 and 1000",
+        ),
+        (
+            json!(["time-pos", -1]),
+            "invalid value for `time-pos`: expected a number of at least 0",
+        ),
+        (
+            json!(["hwdec", true]),
+            "invalid value for `hwdec`: expected a string",
+        ),
+        (json!(["duration", 10]), "`duration` is read-only"),
+        (
+            json!(["no-such-prop", 10]),
+            "unknown property `no-such-prop`",
+        ),
+    ] {
+        match set_prop(args.clone()) {
+            Err(message) => assert!(message.starts_with(error), "{}: {}", args, message),
+            Ok(in_msg) => panic!("{} should be rejected: {:?}", args, in_msg),
+        }
+    }
+    // `set` is checked the same way
+    assert!(serde_json::from_value::<CmdVal>(json!(["set", "pause", 3])).is_err());
+
+    // Flags set with 0 or 1, like "mute" used to be
+    assert_eq!(
+        set_prop(json!(["mute", 1])).unwrap(),
+        InMsg(
+            InMsgFn::MpvSetProp,
+            InMsgArgs::StProp(prop("mute"), PropVal::Bool(true))
+        )
+    );
+    assert_eq!(
+        serde_json::from_value::<CmdVal>(json!(["set", "mute", 0])).unwrap(),
+        CmdVal::Set(prop("mute"), PropVal::Bool(false))
+    );
+}
+
+#[test]
+fn prop_schema() {
+    for (index, schema) in PROP_SCHEMA.iter().enumerate() {
+        assert!(
+            schema
+                .name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c == '-'),
+            "{}",
+            schema.name
+        );
+        assert!(
+            PROP_SCHEMA[..index]
+                .iter()
+                .all(|other| other.name != schema.name),
+            "{} is listed twice",
+            schema.name
+        );
+    }
+    assert!(matches!(prop("pause").format(), Format::Flag));
+    assert!(matches!(prop("sid").format(), Format::Int64));
+    assert!(matches!(prop("chapter-list").format(), Format::Node));
+    assert!(matches!(prop("mute").format(), Format::Flag));
+    // The misspelled name is still accepted
+    assert_eq!(
+        prop("input-defalt-bindings").name(),
+        "input-default-bindings"
+    );
+}
+
 #[test]
 fn command_stop_tokens() {
     assert_tokens(
-        &InMsg(
-            InMsgFn::MpvCommand,
-            InMsgArgs::Cmd(CmdVal::Single((MpvCmd::Stop,))),
-        ),
+        &InMsg(InMsgFn::MpvCommand, InMsgArgs::Cmd(CmdVal::Stop)),
         &[
             Token::TupleStruct {
                 name: "InMsg",
@@ -149,7 +696,7 @@ fn command_loadfile_tokens() {
     assert_tokens(
         &InMsg(
             InMsgFn::MpvCommand,
-            InMsgArgs::Cmd(CmdVal::Double(MpvCmd::Loadfile, "some_file".to_string())),
+            InMsgArgs::Cmd(CmdVal::Loadfile("some_file".to_string(), None, None)),
         ),
         &[
             Token::TupleStruct {
@@ -165,3 +712,301 @@ fn command_loadfile_tokens() {
         ],
     );
 }
+
+fn assert_cmd_tokens(cmd: CmdVal, args: &[Token]) {
+    let mut tokens = vec![Token::Tuple { len: args.len() }];
+    tokens.extend_from_slice(args);
+    tokens.push(Token::TupleEnd);
+    assert_tokens(&cmd, &tokens);
+}
+
+#[test]
+fn command_loadfile_options_tokens() {
+    assert_ser_tokens(
+        &CmdVal::Loadfile(
+            "https://example.com/a.m3u8".to_string(),
+            Some(LoadfileFlag::AppendPlay),
+            Some(LoadfileOptions {
+                start: Some(61.5),
+                http_headers: vec![("Cookie".to_string(), "a=1".to_string())]
+                    .into_iter()
+                    .collect(),
+                referrer: None,
+                user_agent: Some("Stremio".to_string()),
+            }),
+        ),
+        &[
+            Token::Tuple { len: 4 },
+            Token::Str("loadfile"),
+            Token::Str("https://example.com/a.m3u8"),
+            Token::Str("append-play"),
+            Token::Struct {
+                name: "LoadfileOptions",
+                len: 3,
+            },
+            Token::Str("start"),
+            Token::Some,
+            Token::F64(61.5),
+            Token::Str("httpHeaders"),
+            Token::Map { len: Some(1) },
+            Token::Str("Cookie"),
+            Token::Str("a=1"),
+            Token::MapEnd,
+            Token::Str("userAgent"),
+            Token::Some,
+            Token::Str("Stremio"),
+            Token::StructEnd,
+            Token::TupleEnd,
+        ],
+    );
+    assert_eq!(
+        serde_json::from_value::<CmdVal>(json!(["loadfile", "a.mkv", "append-play", {
+            "start": 61.5,
+            "httpHeaders": {"Cookie": "a=1"},
+            "userAgent": "Stremio",
+        }]))
+        .unwrap(),
+        CmdVal::Loadfile(
+            "a.mkv".to_string(),
+            Some(LoadfileFlag::AppendPlay),
+            Some(LoadfileOptions {
+                start: Some(61.5),
+                http_headers: vec![("Cookie".to_string(), "a=1".to_string())]
+                    .into_iter()
+                    .collect(),
+                referrer: None,
+                user_agent: Some("Stremio".to_string()),
+            })
+        )
+    );
+    // The flag may be null to keep the default
+    assert_eq!(
+        serde_json::from_value::<CmdVal>(json!(["loadfile", "a.mkv", null, {"start": 10}]))
+            .unwrap(),
+        CmdVal::Loadfile(
+            "a.mkv".to_string(),
+            None,
+            Some(LoadfileOptions {
+                start: Some(10.),
+                ..LoadfileOptions::default()
+            })
+        )
+    );
+}
+
+#[test]
+fn command_seek_tokens() {
+    assert_cmd_tokens(
+        CmdVal::Seek(-10.0, None),
+        &[Token::Str("seek"), Token::F64(-10.0)],
+    );
+    assert_cmd_tokens(
+        CmdVal::Seek(50.0, Some(SeekMode::AbsolutePercent)),
+        &[
+            Token::Str("seek"),
+            Token::F64(50.0),
+            Token::Str("absolute-percent"),
+        ],
+    );
+}
+
+#[test]
+fn command_track_tokens() {
+    assert_cmd_tokens(
+        CmdVal::SubAdd(TrackSource {
+            url: "http://127.0.0.1:11470/subs.srt".to_string(),
+            flag: Some(TrackFlag::Cached),
+            title: Some("English".to_string()),
+            lang: Some("eng".to_string()),
+        }),
+        &[
+            Token::Str("sub-add"),
+            Token::Str("http://127.0.0.1:11470/subs.srt"),
+            Token::Str("cached"),
+            Token::Str("English"),
+            Token::Str("eng"),
+        ],
+    );
+    assert_cmd_tokens(
+        CmdVal::AudioAdd(TrackSource {
+            url: "audio.mka".to_string(),
+            flag: None,
+            title: None,
+            lang: None,
+        }),
+        &[Token::Str("audio-add"), Token::Str("audio.mka")],
+    );
+    assert_cmd_tokens(
+        CmdVal::SubRemove(Some(2)),
+        &[Token::Str("sub-remove"), Token::U32(2)],
+    );
+    assert_cmd_tokens(CmdVal::SubRemove(None), &[Token::Str("sub-remove")]);
+}
+
+#[test]
+fn command_misc_tokens() {
+    assert_cmd_tokens(
+        CmdVal::Cycle(prop("pause"), Some(CycleDirection::Down)),
+        &[Token::Str("cycle"), Token::Str("pause"), Token::Str("down")],
+    );
+    assert_cmd_tokens(CmdVal::FrameStep, &[Token::Str("frame-step")]);
+    assert_cmd_tokens(
+        CmdVal::PlaylistNext(Some(PlaylistFlag::Force)),
+        &[Token::Str("playlist-next"), Token::Str("force")],
+    );
+    assert_cmd_tokens(CmdVal::PlaylistPrev(None), &[Token::Str("playlist-prev")]);
+    assert_cmd_tokens(
+        CmdVal::ScreenshotToFile("shot.png".to_string(), Some(ScreenshotFlag::Video)),
+        &[
+            Token::Str("screenshot-to-file"),
+            Token::Str("shot.png"),
+            Token::Str("video"),
+        ],
+    );
+    assert_cmd_tokens(
+        CmdVal::Set(prop("speed"), PropVal::Num(1.5)),
+        &[Token::Str("set"), Token::Str("speed"), Token::F64(1.5)],
+    );
+}
+
+#[test]
+fn command_script_message_tokens() {
+    assert_cmd_tokens(
+        CmdVal::ScriptMessage(vec!["skip-silence".to_string()]),
+        &[Token::Str("script-message"), Token::Str("skip-silence")],
+    );
+    assert_cmd_tokens(
+        CmdVal::ScriptMessageTo(
+            "osd".to_string(),
+            vec![
+                "show-text".to_string(),
+                "Hello".to_string(),
+                "2000".to_string(),
+            ],
+        ),
+        &[
+            Token::Str("script-message-to"),
+            Token::Str("osd"),
+            Token::Str("show-text"),
+            Token::Str("Hello"),
+            Token::Str("2000"),
+        ],
+    );
+    assert_eq!(
+        Vec::<String>::from(CmdVal::ScriptMessage(vec![
+            "skip-silence".to_string(),
+            "".to_string()
+        ])),
+        vec!["script-message", "skip-silence", ""]
+    );
+}
+
+#[test]
+fn command_invalid_args() {
+    let invalid = [
+        json!(["loadfile"]),
+        json!(["loadfile", ""]),
+        json!(["loadfile", "file", "extra"]),
+        json!(["loadfile", "file", "replace", {"start": "10"}]),
+        json!(["loadfile", "file", "replace", {"startTime": 10}]),
+        json!(["loadfile", "file", "replace", {"httpHeaders": {"Bad Name": "a"}}]),
+        json!(["loadfile", "file", "replace", {"httpHeaders": {"X-A": "a\r\nX-B: b"}}]),
+        json!(["loadfile", "file", "replace", {"httpHeaders": {"": "a"}}]),
+        json!(["loadfile", "file", "replace", {"userAgent": "a\nb"}]),
+        json!(["loadfile", "file", "replace", {}, "extra"]),
+        json!(["stop", "now"]),
+        json!(["seek"]),
+        json!(["seek", "10"]),
+        json!(["seek", 10, "backwards"]),
+        json!(["seek", 150, "absolute-percent"]),
+        json!(["sub-add", "subs.srt", "always"]),
+        json!(["sub-remove", -1]),
+        json!(["cycle", "not-a-prop"]),
+        json!(["screenshot-to-file", ""]),
+        json!(["set", "speed"]),
+        json!(["script-message"]),
+        json!(["script-message", ""]),
+        json!(["script-message", "skip-silence", 1]),
+        json!(["script-message-to", "osd"]),
+        json!(["script-message-to", "", "show-text"]),
+        json!(["unknown-command"]),
+    ];
+    for cmd in invalid {
+        assert!(
+            serde_json::from_value::<CmdVal>(cmd.clone()).is_err(),
+            "{} should be rejected",
+            cmd
+        );
+    }
+}
+
+#[test]
+fn command_to_mpv_args() {
+    assert_eq!(
+        Vec::<String>::from(CmdVal::Seek(5.0, Some(SeekMode::Absolute))),
+        vec!["seek", "5", "absolute"]
+    );
+    assert_eq!(
+        Vec::<String>::from(CmdVal::Set(prop("pause"), PropVal::Bool(true))),
+        vec!["set", "pause", "yes"]
+    );
+    assert_eq!(
+        Vec::<String>::from(CmdVal::Loadfile("a.mkv".to_string(), None, None)),
+        vec!["loadfile", "a.mkv"]
+    );
+    assert_eq!(
+        Vec::<String>::from(CmdVal::Loadfile(
+            "a.mkv".to_string(),
+            Some(LoadfileFlag::Append),
+            Some(LoadfileOptions::default())
+        )),
+        vec!["loadfile", "a.mkv", "append"]
+    );
+    // The options are passed by name
+    let loadfile = CmdVal::Loadfile(
+        "https://example.com/a.mp4".to_string(),
+        None,
+        Some(LoadfileOptions {
+            start: Some(61.5),
+            http_headers: vec![
+                ("Cookie".to_string(), "a=1, b=2".to_string()),
+                ("X-Path".to_string(), "C:\\a".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            referrer: Some("https://example.com/?a=1,b=2".to_string()),
+            user_agent: Some("Mozilla/5.0 (Windows NT 10.0)".to_string()),
+        }),
+    );
+    assert_eq!(
+        loadfile.to_node().map(serde_json::Value::from),
+        Some(json!({
+            "name": "loadfile",
+            "url": "https://example.com/a.mp4",
+            "flags": "replace",
+            "options": {
+                "start": "61.5",
+                "http-header-fields": "Cookie: a=1\\, b=2,X-Path: C:\\\\a",
+                "referrer": "https://example.com/?a=1,b=2",
+                "user-agent": "Mozilla/5.0 (Windows NT 10.0)"
+            }
+        }))
+    );
+    assert_eq!(
+        CmdVal::Loadfile("a.mkv".to_string(), Some(LoadfileFlag::Append), None)
+            .to_node()
+            .map(serde_json::Value::from),
+        Some(json!({"name": "loadfile", "url": "a.mkv", "flags": "append"}))
+    );
+    assert_eq!(CmdVal::Stop.to_node(), None);
+    // A title can only be passed after a flag
+    assert_eq!(
+        Vec::<String>::from(CmdVal::SubAdd(TrackSource {
+            url: "subs.srt".to_string(),
+            flag: None,
+            title: Some("English".to_string()),
+            lang: None,
+        })),
+        vec!["sub-add", "subs.srt", "select", "English"]
+    );
+}
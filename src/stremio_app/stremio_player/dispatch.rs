@@ -0,0 +1,452 @@
+use flume::Sender;
+use libmpv2::Format;
+use std::{
+    fs,
+    sync::{Arc, MutexGuard, PoisonError},
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::stremio_app::constants::SCREENSHOT_TEMP_MAX_AGE;
+use crate::stremio_app::stremio_player::{
+    backend::PlayerBackend,
+    event_loop::ObserveProperty,
+    player_log::PlayerLog,
+    player_settings::PlayerSettings,
+    resume_store::ResumeStore,
+    screenshot,
+    screenshot::ScreenshotArgs,
+    source_fallback::{FallbackArgs, SourceFallback},
+    supervisor::PlayerStores,
+    CmdVal, InMsg, InMsgArgs, InMsgFn, LoadfileFlag, LoadfileOptions, PlayerError, PlayerEvent,
+    PlayerLogs, PlayerLogsExported, PlayerPropValue, PlayerResume, PlayerScreenshot,
+    PlayerThumbnail, PropKey, PropVal, RequestArgs,
+};
+
+// Routes the messages received from the web UI to the player backend.
+// Property observations are forwarded to the event thread, which owns the
+// mpv event queue. Replies and errors are sent as player events.
+pub struct MessageHandler<B: PlayerBackend + ?Sized> {
+    backend: Arc<B>,
+    stores: PlayerStores,
+    observe_property_sender: Sender<ObserveProperty>,
+    player_event_sender: Sender<PlayerEvent>,
+}
+
+impl<B: PlayerBackend + ?Sized> MessageHandler<B> {
+    pub fn new(
+        backend: Arc<B>,
+        stores: PlayerStores,
+        observe_property_sender: Sender<ObserveProperty>,
+        player_event_sender: Sender<PlayerEvent>,
+    ) -> Self {
+        Self {
+            backend,
+            stores,
+            observe_property_sender,
+            player_event_sender,
+        }
+    }
+
+    pub fn handle(&self, msg: &str) {
+        let in_msg: InMsg = match serde_json::from_str(msg) {
+            Ok(in_msg) => in_msg,
+            Err(error) => {
+                eprintln!("cannot parse InMsg:{msg:?} {error:#}");
+                self.send_error(PlayerError::new(msg, format!("invalid message: {error}")));
+                return;
+            }
+        };
+
+        let result = match in_msg {
+            InMsg(InMsgFn::MpvObserveProp, InMsgArgs::ObProp(prop)) => {
+                self.observe_property(ObserveProperty::Observe {
+                    name: prop.to_string(),
+                    format: prop.format(),
+                });
+                Ok(())
+            }
+            InMsg(InMsgFn::MpvUnobserveProp, InMsgArgs::ObProp(prop)) => {
+                self.observe_property(ObserveProperty::Unobserve {
+                    name: prop.to_string(),
+                });
+                Ok(())
+            }
+            InMsg(InMsgFn::MpvSetProp, InMsgArgs::StProp(name, value)) => self
+                .backend
+                .set_prop(&name.to_string(), &value)
+                .map(|()| self.prop_set(name, &value))
+                .map_err(|error| {
+                    eprintln!("cannot set MPV property: '{error:#}'");
+                    error
+                }),
+            InMsg(InMsgFn::MpvCommand, InMsgArgs::Cmd(cmd)) => {
+                let prop_set = match &cmd {
+                    CmdVal::Set(name, value) => Some((*name, value.clone())),
+                    CmdVal::Loadfile(url, flag, options) => {
+                        // The thumbnails of the file being replaced are not
+                        // needed anymore, nor are the sources being tried
+                        let replace = matches!(flag, None | Some(LoadfileFlag::Replace));
+                        if replace {
+                            self.stores.thumbnails.cancel();
+                            self.sources().cancel();
+                        }
+                        let options = options.clone().unwrap_or_default();
+                        self.remember_options(replace, [(url.clone(), options)]);
+                        None
+                    }
+                    CmdVal::Stop => {
+                        self.sources().cancel();
+                        None
+                    }
+                    _ => None,
+                };
+                let result = match cmd.to_node() {
+                    Some(command) => self.backend.run_command_node(&command),
+                    None => self.backend.run_command(&Vec::<String>::from(cmd)),
+                };
+                result
+                    .map(|()| {
+                        if let Some((name, value)) = prop_set {
+                            self.prop_set(name, &value);
+                        }
+                    })
+                    .map_err(|error| {
+                        eprintln!("failed to execute MPV command: '{error:#}'");
+                        error
+                    })
+            }
+            InMsg(InMsgFn::MpvGetProp, InMsgArgs::Request(RequestArgs { id, params })) => {
+                let prop: PropKey = match serde_json::from_value(params) {
+                    Ok(prop) => prop,
+                    Err(error) => {
+                        self.send_error(PlayerError::new(
+                            msg,
+                            format!("invalid property: {error}"),
+                        ));
+                        return;
+                    }
+                };
+                self.get_property(id, &prop.to_string(), prop.format())
+            }
+            InMsg(InMsgFn::PlayerGetResume, InMsgArgs::Request(RequestArgs { id, params })) => {
+                let path: String = match serde_json::from_value(params) {
+                    Ok(path) => path,
+                    Err(error) => {
+                        self.send_error(PlayerError::new(msg, format!("invalid path: {error}")));
+                        return;
+                    }
+                };
+                let resume = self.resume_store().get(&path).cloned();
+                self.send_event(PlayerEvent::Resume(PlayerResume {
+                    id,
+                    path: Some(path),
+                    resume,
+                }));
+                Ok(())
+            }
+            // Without a path all the resume positions are cleared
+            InMsg(InMsgFn::PlayerClearResume, InMsgArgs::Request(RequestArgs { id, params })) => {
+                let path: Option<String> = match serde_json::from_value(params) {
+                    Ok(path) => path,
+                    Err(error) => {
+                        self.send_error(PlayerError::new(msg, format!("invalid path: {error}")));
+                        return;
+                    }
+                };
+                let mut resume_store = self.resume_store();
+                match &path {
+                    Some(path) => {
+                        resume_store.remove(path);
+                    }
+                    None => resume_store.clear(),
+                }
+                if let Err(error) = resume_store.save() {
+                    eprintln!("cannot save resume store: {error}");
+                }
+                drop(resume_store);
+                self.send_event(PlayerEvent::Resume(PlayerResume {
+                    id,
+                    path,
+                    resume: None,
+                }));
+                Ok(())
+            }
+            InMsg(InMsgFn::PlayerGetLogs, InMsgArgs::Request(RequestArgs { id, .. })) => {
+                let logs = self.player_log().entries().cloned().collect();
+                self.send_event(PlayerEvent::Logs(PlayerLogs { id, logs }));
+                Ok(())
+            }
+            InMsg(InMsgFn::PlayerExportLogs, InMsgArgs::Request(RequestArgs { id, .. })) => {
+                let path = match self.player_log().export_file() {
+                    Some(path) => path.to_path_buf(),
+                    None => {
+                        self.send_error(PlayerError::new(msg, "no configuration directory"));
+                        return;
+                    }
+                };
+                let header = self.diagnostics_header();
+                if let Err(error) = self.player_log().export(&path, &header) {
+                    eprintln!("cannot export the player logs: {error}");
+                    self.send_error(PlayerError::new(msg, format!("cannot export: {error}")));
+                    return;
+                }
+                self.send_event(PlayerEvent::LogsExported(PlayerLogsExported {
+                    id,
+                    path: path.to_string_lossy().into_owned(),
+                }));
+                Ok(())
+            }
+            InMsg(InMsgFn::PlayerThumbnailAt, InMsgArgs::Request(RequestArgs { id, params })) => {
+                let time: f64 = match serde_json::from_value(params) {
+                    Ok(time) => time,
+                    Err(error) => {
+                        self.send_error(PlayerError::new(msg, format!("invalid time: {error}")));
+                        return;
+                    }
+                };
+                let thumbnail = self.stores.thumbnails.frame_at(time);
+                let base64 =
+                    thumbnail.as_ref().and_then(|thumbnail| {
+                        match screenshot::encode_base64(&thumbnail.path) {
+                            Ok(base64) => Some(base64),
+                            Err(error) => {
+                                eprintln!(
+                                    "cannot read thumbnail {}: {error}",
+                                    thumbnail.path.display()
+                                );
+                                None
+                            }
+                        }
+                    });
+                self.send_event(PlayerEvent::Thumbnail(PlayerThumbnail {
+                    id,
+                    time: thumbnail.as_ref().map(|thumbnail| thumbnail.time),
+                    path: thumbnail.map(|thumbnail| thumbnail.path.to_string_lossy().into_owned()),
+                    base64,
+                }));
+                Ok(())
+            }
+            InMsg(
+                InMsgFn::PlayerLoadWithFallbacks,
+                InMsgArgs::Request(RequestArgs { id, params }),
+            ) => {
+                let args = serde_json::from_value::<FallbackArgs>(params)
+                    .map_err(|error| error.to_string())
+                    .and_then(|args| args.check().map(|()| args));
+                let args = match args {
+                    Ok(args) => args,
+                    Err(error) => {
+                        self.send_error(PlayerError::new(msg, format!("invalid sources: {error}")));
+                        return;
+                    }
+                };
+                self.stores.thumbnails.cancel();
+                let sources = args.sources.iter();
+                let options = sources.map(|source| (source.url.clone(), source.options.clone()));
+                self.remember_options(true, options);
+                let loadfile = self.sources().start(id, args, Instant::now());
+                match self.backend.run_command_node(&loadfile) {
+                    // The event thread has to wait for the startup timeout
+                    Ok(()) => {
+                        self.backend.wake_up();
+                        Ok(())
+                    }
+                    Err(error) => {
+                        eprintln!("failed to execute MPV command: '{error:#}'");
+                        self.sources().cancel();
+                        Err(error)
+                    }
+                }
+            }
+            InMsg(InMsgFn::PlayerScreenshot, InMsgArgs::Request(RequestArgs { id, params })) => {
+                let args = match serde_json::from_value::<Option<ScreenshotArgs>>(params)
+                    .map_err(|error| error.to_string())
+                    .and_then(|args| {
+                        let args = args.unwrap_or_default();
+                        args.check().map(|()| args)
+                    }) {
+                    Ok(args) => args,
+                    Err(error) => {
+                        self.send_error(PlayerError::new(
+                            msg,
+                            format!("invalid screenshot options: {error}"),
+                        ));
+                        return;
+                    }
+                };
+                self.screenshot(msg, id, &args)
+            }
+            in_msg => {
+                eprintln!("MPV unsupported message: '{in_msg:?}'");
+                self.send_error(PlayerError::new(msg, "unsupported message"));
+                return;
+            }
+        };
+        if let Err(error) = result {
+            self.send_error(PlayerError::from_mpv(msg, &error));
+        }
+    }
+
+    fn observe_property(&self, observe_property: ObserveProperty) {
+        self.observe_property_sender
+            .send(observe_property)
+            .expect("cannot send ObserveProperty");
+        self.backend.wake_up();
+    }
+
+    fn resume_store(&self) -> MutexGuard<'_, ResumeStore> {
+        self.stores
+            .resume_store
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn player_log(&self) -> MutexGuard<'_, PlayerLog> {
+        self.stores
+            .player_log
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn sources(&self) -> MutexGuard<'_, SourceFallback> {
+        self.stores
+            .sources
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn settings(&self) -> MutexGuard<'_, PlayerSettings> {
+        self.stores
+            .settings
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // Keeps the options of the files being loaded, the ones of the playlist
+    // are dropped if it is replaced
+    fn remember_options(
+        &self,
+        replace: bool,
+        files: impl IntoIterator<Item = (String, LoadfileOptions)>,
+    ) {
+        let mut loadfile_options = self
+            .stores
+            .loadfile_options
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if replace {
+            loadfile_options.clear();
+        }
+        loadfile_options.extend(files);
+    }
+
+    // Saves the settings mpv doesn't keep once they were set successfully
+    fn prop_set(&self, name: PropKey, value: &PropVal) {
+        if name.name() != "audio-device" {
+            return;
+        }
+        let mut settings = self.settings();
+        settings.audio_device = match value {
+            PropVal::Str(device) if device != "auto" => Some(device.clone()),
+            _ => None,
+        };
+        if let Err(error) = settings.save() {
+            eprintln!("cannot save player settings: {error}");
+        }
+    }
+
+    // The versions of the shell and of mpv, as far as mpv can tell them
+    fn diagnostics_header(&self) -> Vec<String> {
+        let mut header = vec![format!("stremio-shell-ng {}", env!("CARGO_PKG_VERSION"))];
+        for name in ["mpv-version", "ffmpeg-version", "hwdec-current"] {
+            if let Ok(prop) = self.backend.get_prop(name, Format::String) {
+                if let Some(value) = prop.data().as_str() {
+                    header.push(format!("{name}: {value}"));
+                }
+            }
+        }
+        header
+    }
+
+    // Screenshots in the temp folder older than `SCREENSHOT_TEMP_MAX_AGE` are
+    // removed before a new one is taken
+    fn screenshot(&self, msg: &str, id: u64, args: &ScreenshotArgs) -> libmpv2::Result<()> {
+        let dir = match (&args.dir, &self.stores.screenshot_dir) {
+            (Some(dir), Some(screenshot_dir)) => screenshot_dir.join(dir),
+            (Some(_), None) => {
+                self.send_error(PlayerError::new(msg, "no configuration directory"));
+                return Ok(());
+            }
+            (None, _) => {
+                let dir = screenshot::temp_dir();
+                screenshot::cleanup(&dir, Duration::from_secs(SCREENSHOT_TEMP_MAX_AGE));
+                dir
+            }
+        };
+        if let Err(error) = fs::create_dir_all(&dir) {
+            eprintln!("cannot create screenshot folder {}: {error}", dir.display());
+            self.send_error(PlayerError::new(
+                msg,
+                format!("cannot create folder: {error}"),
+            ));
+            return Ok(());
+        }
+        let title = self.backend.get_prop("media-title", Format::String).ok();
+        let time_pos = self.backend.get_prop("time-pos", Format::Double).ok();
+        let name = screenshot::file_name(
+            args.template(),
+            title
+                .as_ref()
+                .and_then(|title| title.data().as_str())
+                .unwrap_or_default(),
+            time_pos
+                .and_then(|time_pos| time_pos.data().as_f64())
+                .unwrap_or_default(),
+            SystemTime::now(),
+        );
+        let path = screenshot::unique_path(&dir, &name);
+        let path_str = path.to_string_lossy().into_owned();
+        self.backend.run_command(&[
+            "screenshot-to-file".to_string(),
+            path_str.clone(),
+            args.mpv_flag().to_string(),
+        ])?;
+        let base64 = if args.base64 {
+            match screenshot::encode_base64(&path) {
+                Ok(base64) => Some(base64),
+                Err(error) => {
+                    eprintln!("cannot read screenshot {path_str}: {error}");
+                    self.send_error(PlayerError::new(
+                        msg,
+                        format!("cannot read screenshot: {error}"),
+                    ));
+                    return Ok(());
+                }
+            }
+        } else {
+            None
+        };
+        self.send_event(PlayerEvent::Screenshot(PlayerScreenshot {
+            id,
+            path: path_str,
+            base64,
+        }));
+        Ok(())
+    }
+
+    fn get_property(&self, id: u64, name: &str, format: Format) -> libmpv2::Result<()> {
+        let prop = self.backend.get_prop(name, format)?;
+        self.send_event(PlayerEvent::PropValue(PlayerPropValue { id, prop }));
+        Ok(())
+    }
+
+    fn send_event(&self, player_event: PlayerEvent) {
+        self.player_event_sender
+            .send(player_event)
+            .expect("failed to send PlayerEvent");
+    }
+
+    fn send_error(&self, error: PlayerError) {
+        self.send_event(PlayerEvent::Error(error));
+    }
+}
@@ -0,0 +1,535 @@
+use crate::stremio_app::stremio_player::{
+    backend_fake::{BackendCall, FakeBackend},
+    communication::loadfile_node,
+    dispatch::MessageHandler,
+    event_loop::ObserveProperty,
+    player_log::{LogEntry, PlayerLog},
+    resume_store::ResumeEntry,
+    supervisor::PlayerStores,
+    thumbnails::{cache_key, ThumbnailOptions, Thumbnails},
+    LoadfileFlag, PlayerError, PlayerEvent, PlayerLogs, PlayerLogsExported, PlayerPropValue,
+    PlayerProprChange, PlayerResume, PlayerScreenshot, PlayerThumbnail, PropVal,
+};
+use flume::Receiver;
+use libmpv2::events::PropertyData;
+use std::{
+    env, fs, process,
+    sync::{Arc, Mutex},
+};
+
+struct Dispatch {
+    backend: Arc<FakeBackend>,
+    handler: MessageHandler<FakeBackend>,
+    stores: PlayerStores,
+    observe_property_receiver: Receiver<ObserveProperty>,
+    player_event_receiver: Receiver<PlayerEvent>,
+}
+
+fn stores() -> PlayerStores {
+    PlayerStores {
+        resume_store: Arc::default(),
+        player_log: Arc::new(Mutex::new(PlayerLog::new(10))),
+        settings: Arc::default(),
+        thumbnails: Arc::default(),
+        sources: Arc::default(),
+        loadfile_options: Arc::default(),
+        screenshot_dir: None,
+    }
+}
+
+impl Dispatch {
+    fn new(backend: FakeBackend) -> Self {
+        Self::with_stores(backend, stores())
+    }
+    fn with_thumbnails(backend: FakeBackend, thumbnails: Thumbnails) -> Self {
+        let stores = PlayerStores {
+            thumbnails: Arc::new(thumbnails),
+            ..stores()
+        };
+        Self::with_stores(backend, stores)
+    }
+    fn with_stores(backend: FakeBackend, stores: PlayerStores) -> Self {
+        let backend = Arc::new(backend);
+        let (observe_property_sender, observe_property_receiver) = flume::unbounded();
+        let (player_event_sender, player_event_receiver) = flume::unbounded();
+        Self {
+            handler: MessageHandler::new(
+                Arc::clone(&backend),
+                stores.clone(),
+                observe_property_sender,
+                player_event_sender,
+            ),
+            backend,
+            stores,
+            observe_property_receiver,
+            player_event_receiver,
+        }
+    }
+    fn handle(&self, msg: &str) -> Vec<PlayerEvent> {
+        self.handler.handle(msg);
+        self.player_event_receiver.drain().collect()
+    }
+    fn observed(&self) -> Vec<(String, bool)> {
+        self.observe_property_receiver
+            .drain()
+            .map(|observe_property| match observe_property {
+                ObserveProperty::Observe { name, .. } => (name, true),
+                ObserveProperty::Unobserve { name } => (name, false),
+            })
+            .collect()
+    }
+}
+
+#[test]
+fn dispatch_command() {
+    let dispatch = Dispatch::new(FakeBackend::default());
+    assert!(dispatch
+        .handle(r#"["mpv-command", ["loadfile", "C:\\a b.mkv"]]"#)
+        .is_empty());
+    assert!(dispatch.handle(r#"["mpv-command", ["stop"]]"#).is_empty());
+    assert_eq!(
+        dispatch.backend.calls(),
+        vec![
+            BackendCall::CommandNode(loadfile_node(
+                r"C:\a b.mkv",
+                LoadfileFlag::Replace,
+                Vec::new()
+            )),
+            BackendCall::Command(vec!["stop".to_string()]),
+        ]
+    );
+}
+
+#[test]
+fn dispatch_loadfile_options() {
+    let dispatch = Dispatch::new(FakeBackend::default());
+    let options = |url: &str| {
+        let options = dispatch.stores.loadfile_options.lock().unwrap();
+        options.get(url).map(|options| options.user_agent.clone())
+    };
+    dispatch.handle(
+        r#"["mpv-command", ["loadfile", "https://a/1.mkv", "replace", {"userAgent": "a"}]]"#,
+    );
+    dispatch.handle(r#"["mpv-command", ["loadfile", "https://a/2.mkv", "append"]]"#);
+    assert_eq!(options("https://a/1.mkv"), Some(Some("a".to_string())));
+    assert_eq!(options("https://a/2.mkv"), Some(None));
+    // The playlist is replaced
+    dispatch.handle(
+        r#"["player-load-with-fallbacks", {"id": 1, "params": {"sources": [
+        {"url": "https://b/1.mkv", "options": {"userAgent": "b"}}]}}]"#,
+    );
+    assert_eq!(options("https://a/1.mkv"), None);
+    assert_eq!(options("https://b/1.mkv"), Some(Some("b".to_string())));
+}
+
+#[test]
+fn dispatch_set_prop() {
+    let dispatch = Dispatch::new(FakeBackend::default());
+    dispatch.handle(r#"["mpv-set-prop", ["pause", true]]"#);
+    dispatch.handle(r#"["mpv-set-prop", ["volume", 50]]"#);
+    dispatch.handle(r#"["mpv-set-prop", ["sid", "no"]]"#);
+    assert_eq!(
+        dispatch.backend.calls(),
+        vec![
+            BackendCall::SetProperty("pause".to_string(), PropVal::Bool(true)),
+            BackendCall::SetProperty("volume".to_string(), PropVal::Num(50.)),
+            BackendCall::SetProperty("sid".to_string(), PropVal::Str("no".to_string())),
+        ]
+    );
+}
+
+#[test]
+fn dispatch_observe_prop() {
+    let dispatch = Dispatch::new(FakeBackend::default());
+    dispatch.handle(r#"["mpv-observe-prop", "time-pos"]"#);
+    dispatch.handle(r#"["mpv-unobserve-prop", "time-pos"]"#);
+    assert_eq!(
+        dispatch.observed(),
+        vec![
+            ("time-pos".to_string(), true),
+            ("time-pos".to_string(), false)
+        ]
+    );
+    // The event thread is woken up to pick up the change
+    assert_eq!(
+        dispatch.backend.calls(),
+        vec![BackendCall::WakeUp, BackendCall::WakeUp]
+    );
+}
+
+#[test]
+fn dispatch_get_prop() {
+    let dispatch =
+        Dispatch::new(FakeBackend::default().with_prop("volume", PropertyData::Double(70.)));
+    assert_eq!(
+        dispatch.handle(r#"["mpv-get-prop", {"id": 3, "params": "volume"}]"#),
+        vec![PlayerEvent::PropValue(PlayerPropValue {
+            id: 3,
+            prop: PlayerProprChange::from_name_value(
+                "volume".to_string(),
+                PropertyData::Double(70.)
+            ),
+        })]
+    );
+
+    let msg = r#"["mpv-get-prop", {"id": 4, "params": "pause"}]"#;
+    match dispatch.handle(msg).as_slice() {
+        [PlayerEvent::Error(PlayerError { code: Some(-8), .. })] => {}
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    let msg = r#"["mpv-get-prop", {"id": 5, "params": "no-such-prop"}]"#;
+    match dispatch.handle(msg).as_slice() {
+        [PlayerEvent::Error(PlayerError {
+            error, code: None, ..
+        })] => {
+            assert!(error.starts_with("invalid property"), "{}", error)
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+}
+
+#[test]
+fn dispatch_errors() {
+    let dispatch = Dispatch::new(FakeBackend::default().failing(-4));
+    let msg = r#"["mpv-command", ["stop"]]"#;
+    match dispatch.handle(msg).as_slice() {
+        [PlayerEvent::Error(PlayerError {
+            code: Some(-4),
+            message,
+            ..
+        })] => assert_eq!(message, &serde_json::json!(["mpv-command", ["stop"]])),
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    match dispatch.handle("not json").as_slice() {
+        [PlayerEvent::Error(PlayerError { error, message, .. })] => {
+            assert!(error.starts_with("invalid message"), "{}", error);
+            assert_eq!(message, &serde_json::json!("not json"));
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    // The arguments must match the function
+    match dispatch.handle(r#"["mpv-command", "time-pos"]"#).as_slice() {
+        [PlayerEvent::Error(PlayerError { error, .. })] => {
+            assert!(error.starts_with("invalid message"), "{}", error)
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    match dispatch
+        .handle(r#"["mpv-set-prop", ["pause", 3]]"#)
+        .as_slice()
+    {
+        [PlayerEvent::Error(PlayerError { error, .. })] => assert!(
+            error.contains("invalid value for `pause`: expected a flag"),
+            "{}",
+            error
+        ),
+        events => panic!("unexpected events: {:?}", events),
+    }
+    // Only the failed command reached the backend
+    assert_eq!(
+        dispatch.backend.calls(),
+        vec![BackendCall::Command(vec!["stop".to_string()])]
+    );
+}
+
+#[test]
+fn dispatch_resume() {
+    let dispatch = Dispatch::new(FakeBackend::default());
+    let entry = |path: &str| ResumeEntry {
+        path: path.to_string(),
+        time_pos: 42.5,
+        aid: Some("2".to_string()),
+        sid: Some("no".to_string()),
+        sub_delay: 0.,
+        updated_at: 1_700_000_000,
+    };
+    {
+        let mut resume_store = dispatch.stores.resume_store.lock().unwrap();
+        resume_store.set(entry("C:\\a b.mkv"));
+        resume_store.set(entry("https://example.com/b.mp4"));
+    }
+    assert_eq!(
+        dispatch.handle(r#"["player-get-resume", {"id": 1, "params": "file:///C:/a%20b.mkv"}]"#),
+        vec![PlayerEvent::Resume(PlayerResume {
+            id: 1,
+            path: Some("file:///C:/a%20b.mkv".to_string()),
+            resume: Some(entry("C:\\a b.mkv")),
+        })]
+    );
+
+    dispatch.handle(r#"["player-clear-resume", {"id": 2, "params": "C:\\a b.mkv"}]"#);
+    assert_eq!(
+        dispatch.handle(r#"["player-get-resume", {"id": 3, "params": "C:\\a b.mkv"}]"#),
+        vec![PlayerEvent::Resume(PlayerResume {
+            id: 3,
+            path: Some("C:\\a b.mkv".to_string()),
+            resume: None,
+        })]
+    );
+    assert_eq!(dispatch.stores.resume_store.lock().unwrap().len(), 1);
+
+    assert_eq!(
+        dispatch.handle(r#"["player-clear-resume", {"id": 4, "params": null}]"#),
+        vec![PlayerEvent::Resume(PlayerResume {
+            id: 4,
+            path: None,
+            resume: None,
+        })]
+    );
+    assert!(dispatch.stores.resume_store.lock().unwrap().is_empty());
+
+    match dispatch
+        .handle(r#"["player-get-resume", {"id": 5, "params": 7}]"#)
+        .as_slice()
+    {
+        [PlayerEvent::Error(PlayerError { error, .. })] => {
+            assert!(error.starts_with("invalid path"), "{}", error)
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+}
+
+#[test]
+fn dispatch_logs() {
+    let dispatch = Dispatch::new(
+        FakeBackend::default().with_prop("mpv-version", PropertyData::Str("mpv 0.37.0")),
+    );
+    let entry = LogEntry {
+        time: 1_700_000_000_123,
+        level: "error".to_string(),
+        prefix: "ffmpeg".to_string(),
+        text: "http: 404 Not Found".to_string(),
+    };
+    let path = env::temp_dir().join(format!("stremio-player-log-{}.txt", process::id()));
+    let mut player_log = PlayerLog::new(10).with_export_file(path.clone());
+    player_log.push(entry.clone());
+    *dispatch.stores.player_log.lock().unwrap() = player_log;
+    assert_eq!(
+        dispatch.handle(r#"["player-get-logs", {"id": 1, "params": null}]"#),
+        vec![PlayerEvent::Logs(PlayerLogs {
+            id: 1,
+            logs: vec![entry],
+        })]
+    );
+
+    assert_eq!(
+        dispatch.handle(r#"["player-export-logs", {"id": 2, "params": null}]"#),
+        vec![PlayerEvent::LogsExported(PlayerLogsExported {
+            id: 2,
+            path: path.to_string_lossy().into_owned(),
+        })]
+    );
+    let exported = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).ok();
+    assert!(
+        exported.contains("mpv-version: mpv 0.37.0\n"),
+        "{}",
+        exported
+    );
+    assert!(
+        exported.ends_with("[1700000000.123][error][ffmpeg] http: 404 Not Found\n"),
+        "{}",
+        exported
+    );
+}
+
+#[test]
+fn dispatch_screenshot() {
+    let screenshot_dir = env::temp_dir().join(format!("stremio-screenshots-{}", process::id()));
+    let dispatch = Dispatch::with_stores(
+        FakeBackend::default()
+            .with_prop("media-title", PropertyData::Str("Big Buck Bunny"))
+            .with_prop("time-pos", PropertyData::Double(61.5)),
+        PlayerStores {
+            screenshot_dir: Some(screenshot_dir.clone()),
+            ..stores()
+        },
+    );
+    let dir = screenshot_dir.join("shows");
+    let msg = serde_json::json!(["player-screenshot", {"id": 1, "params": {
+        "subtitles": false,
+        "dir": "shows",
+        "template": "{title} {pos}",
+        "base64": true,
+    }}])
+    .to_string();
+    let first = dir.join("Big Buck Bunny 00-01-01.png");
+    assert_eq!(
+        dispatch.handle(&msg),
+        vec![PlayerEvent::Screenshot(PlayerScreenshot {
+            id: 1,
+            path: first.to_string_lossy().into_owned(),
+            base64: Some("UE5H".to_string()),
+        })]
+    );
+    // The existing screenshot is not overwritten
+    let second = dir.join("Big Buck Bunny 00-01-01-2.png");
+    assert_eq!(
+        dispatch.handle(&msg),
+        vec![PlayerEvent::Screenshot(PlayerScreenshot {
+            id: 1,
+            path: second.to_string_lossy().into_owned(),
+            base64: Some("UE5H".to_string()),
+        })]
+    );
+    fs::remove_dir_all(&screenshot_dir).ok();
+    assert!(dispatch
+        .backend
+        .calls()
+        .contains(&BackendCall::Command(vec![
+            "screenshot-to-file".to_string(),
+            second.to_string_lossy().into_owned(),
+            "video".to_string(),
+        ])));
+
+    for params in [r#"{"format": "jpg"}"#, r#"{"dir": "../shows"}"#] {
+        let msg = format!(r#"["player-screenshot", {{"id": 2, "params": {params}}}]"#);
+        match dispatch.handle(&msg).as_slice() {
+            [PlayerEvent::Error(error)] => {
+                assert!(
+                    error.error.starts_with("invalid screenshot options"),
+                    "{}",
+                    error.error
+                )
+            }
+            events => panic!("unexpected events: {:?}", events),
+        }
+    }
+}
+
+#[test]
+fn dispatch_audio_device() {
+    let dispatch = Dispatch::new(FakeBackend::default());
+    let audio_device = || {
+        dispatch
+            .stores
+            .settings
+            .lock()
+            .unwrap()
+            .audio_device
+            .clone()
+    };
+    assert!(dispatch
+        .handle(r#"["mpv-set-prop", ["audio-device", "wasapi/{hdmi}"]]"#)
+        .is_empty());
+    assert_eq!(audio_device(), Some("wasapi/{hdmi}".to_string()));
+    assert!(dispatch
+        .handle(r#"["mpv-command", ["set", "audio-device", "auto"]]"#)
+        .is_empty());
+    assert_eq!(audio_device(), None);
+
+    // The device is not saved if mpv rejects it
+    let dispatch = Dispatch::new(FakeBackend::default().failing(-5));
+    dispatch.handle(r#"["mpv-set-prop", ["audio-device", "wasapi/{hdmi}"]]"#);
+    assert_eq!(dispatch.stores.settings.lock().unwrap().audio_device, None);
+}
+
+#[test]
+fn dispatch_thumbnail_at() {
+    let empty = PlayerEvent::Thumbnail(PlayerThumbnail {
+        id: 1,
+        time: None,
+        path: None,
+        base64: None,
+    });
+    let dispatch = Dispatch::new(FakeBackend::default());
+    assert_eq!(
+        dispatch.handle(r#"["player-thumbnail-at", {"id": 1, "params": 12.5}]"#),
+        vec![empty.clone()]
+    );
+
+    let cache_dir = env::temp_dir().join(format!("stremio-thumbnails-dispatch-{}", process::id()));
+    let input = cache_dir.join("movie.mkv").to_string_lossy().into_owned();
+    let dir = cache_dir.join(cache_key(&input));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("000001.jpg"), b"JPG").unwrap();
+    fs::write(dir.join("000002.jpg"), b"JPG").unwrap();
+    fs::write(dir.join("done"), b"").unwrap();
+    let dispatch = Dispatch::with_thumbnails(
+        FakeBackend::default(),
+        Thumbnails::new(ThumbnailOptions {
+            ffmpeg: cache_dir.join("missing-ffmpeg"),
+            cache_dir: cache_dir.clone(),
+            interval: 10.,
+            width: 160,
+            disk_budget: 1024,
+        }),
+    );
+    dispatch.stores.thumbnails.start(&input, 60.);
+    assert_eq!(
+        dispatch.handle(r#"["player-thumbnail-at", {"id": 1, "params": 12.5}]"#),
+        vec![PlayerEvent::Thumbnail(PlayerThumbnail {
+            id: 1,
+            time: Some(10.),
+            path: Some(dir.join("000002.jpg").to_string_lossy().into_owned()),
+            base64: Some("SlBH".to_string()),
+        })]
+    );
+
+    // Appending a file to the playlist keeps them
+    dispatch.handle(r#"["mpv-command", ["loadfile", "C:\\other.mkv", "append"]]"#);
+    assert!(dispatch.stores.thumbnails.frame_at(0.).is_some());
+    // Loading another file drops the thumbnails
+    dispatch.handle(r#"["mpv-command", ["loadfile", "C:\\other.mkv"]]"#);
+    assert_eq!(
+        dispatch.handle(r#"["player-thumbnail-at", {"id": 1, "params": 12.5}]"#),
+        vec![empty]
+    );
+    fs::remove_dir_all(&cache_dir).ok();
+
+    match dispatch
+        .handle(r#"["player-thumbnail-at", {"id": 2, "params": "12.5"}]"#)
+        .as_slice()
+    {
+        [PlayerEvent::Error(error)] => {
+            assert!(error.error.starts_with("invalid time"), "{}", error.error)
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+}
+
+#[test]
+fn dispatch_load_with_fallbacks() {
+    let dispatch = Dispatch::new(FakeBackend::default());
+    let msg = r#"["player-load-with-fallbacks", {"id": 3, "params": {"sources": [
+        {"url": "https://a/1.mkv", "options": {"httpHeaders": {"Cookie": "a=1"}}},
+        {"url": "https://b/1.mkv"}], "startupTimeout": 10}}]"#;
+    // Replied to by the event thread
+    assert!(dispatch.handle(msg).is_empty());
+    assert_eq!(
+        dispatch.backend.calls(),
+        vec![
+            BackendCall::CommandNode(loadfile_node(
+                "https://a/1.mkv",
+                LoadfileFlag::Replace,
+                vec![("http-header-fields".to_string(), "Cookie: a=1".to_string())]
+            )),
+            BackendCall::WakeUp,
+        ]
+    );
+    assert!(dispatch.stores.sources.lock().unwrap().deadline().is_some());
+    // Stopping gives up the sources
+    dispatch.handle(r#"["mpv-command", ["stop"]]"#);
+    assert_eq!(dispatch.stores.sources.lock().unwrap().deadline(), None);
+
+    match dispatch
+        .handle(r#"["player-load-with-fallbacks", {"id": 4, "params": {"sources": []}}]"#)
+        .as_slice()
+    {
+        [PlayerEvent::Error(error)] => {
+            assert_eq!(error.error, "invalid sources: `sources` must not be empty")
+        }
+        events => panic!("unexpected events: {:?}", events),
+    }
+
+    let dispatch = Dispatch::new(FakeBackend::default().failing(-13));
+    match dispatch.handle(msg).as_slice() {
+        [PlayerEvent::Error(error)] => assert_eq!(error.code, Some(-13)),
+        events => panic!("unexpected events: {:?}", events),
+    }
+    assert_eq!(dispatch.stores.sources.lock().unwrap().deadline(), None);
+}
@@ -0,0 +1,442 @@
+use flume::{Receiver, Sender};
+use libmpv2::{mpv_end_file_reason, Format};
+use std::{
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use crate::stremio_app::constants::{RESUME_MAX_AGE, RESUME_MAX_ENTRIES, RESUME_SAVE_INTERVAL};
+use crate::stremio_app::stremio_player::{
+    backend::{BackendEvent, PlayerBackend, PlayerEvents},
+    hwdec_fallback::{reload_command, HwdecFallback},
+    observed_props::{
+        ObservedProps, HWDEC_CURRENT_ID, IDLE_ACTIVE_ID, RESUME_PROPS, RESUME_PROPS_ID,
+    },
+    player_log::LogEntry,
+    player_settings::PlayerSettings,
+    resume_store::{unix_time, ResumeEntry, ResumeStore},
+    source_fallback::{SourceFallback, SourceStep},
+    supervisor::PlayerStores,
+    InMsgFn, LoadfileOptions, PlayerEnded, PlayerError, PlayerEvent, PlayerHwdecFallback,
+    PlayerProprChange, PlayerScriptMessage, PlayerSourcesFailed, PropVal,
+};
+
+pub enum ObserveProperty {
+    Observe { name: String, format: Format },
+    Unobserve { name: String },
+}
+
+// Player state which outlives the backend, so it can be restored when the
+// backend is recreated
+#[derive(Default)]
+pub struct PlayerState {
+    pub observed_props: ObservedProps,
+    // The file being played and where it is at
+    pub path: Option<String>,
+    pub time_pos: Option<f64>,
+    // The options it was loaded with, e.g. its HTTP headers
+    pub options: LoadfileOptions,
+    pub aid: Option<String>,
+    pub sid: Option<String>,
+    pub sub_delay: f64,
+}
+
+impl PlayerState {
+    fn update(&mut self, change: &PlayerProprChange) {
+        let data = change.data();
+        match change.name() {
+            "time-pos" => self.time_pos = data.as_f64(),
+            "aid" => self.aid = data.as_str().map(ToString::to_string),
+            "sid" => self.sid = data.as_str().map(ToString::to_string),
+            "sub-delay" => self.sub_delay = data.as_f64().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    fn clear_file(&mut self) {
+        self.path = None;
+        self.time_pos = None;
+        self.options = LoadfileOptions::default();
+        self.aid = None;
+        self.sid = None;
+        self.sub_delay = 0.;
+    }
+    fn resume_entry(&self) -> Option<ResumeEntry> {
+        Some(ResumeEntry {
+            path: self.path.clone()?,
+            time_pos: self.time_pos?,
+            aid: self.aid.clone(),
+            sid: self.sid.clone(),
+            sub_delay: self.sub_delay,
+            updated_at: unix_time(),
+        })
+    }
+}
+
+fn save_resume(resume_store: &Mutex<ResumeStore>, update: impl FnOnce(&mut ResumeStore)) {
+    let mut resume_store = resume_store.lock().unwrap_or_else(PoisonError::into_inner);
+    update(&mut resume_store);
+    resume_store.prune(unix_time(), RESUME_MAX_AGE, RESUME_MAX_ENTRIES);
+    if let Err(error) = resume_store.save() {
+        eprintln!("cannot save resume store: {error}");
+    }
+}
+
+// Saves the codec which failed with hardware decoding and, if `reload` is
+// set, loads the file again with software decoding from where it was.
+// Returns `None` if the file could not be loaded again.
+fn fall_back<B: PlayerBackend + ?Sized>(
+    backend: &B,
+    state: &PlayerState,
+    settings: &Mutex<PlayerSettings>,
+    mut fallback: PlayerHwdecFallback,
+    reload: bool,
+) -> Option<PlayerHwdecFallback> {
+    if let Some(codec) = &fallback.codec {
+        let mut settings = settings.lock().unwrap_or_else(PoisonError::into_inner);
+        settings.add_software_codec(codec, unix_time());
+        if let Err(error) = settings.save() {
+            eprintln!("cannot save player settings: {error}");
+        }
+    }
+    if !reload {
+        return Some(fallback);
+    }
+    let path = state.path.as_ref()?;
+    let command = reload_command(path, state.time_pos, &state.options);
+    match backend.run_command_node(&command) {
+        Ok(()) => {
+            fallback.reloaded = true;
+            fallback.time_pos = state.time_pos;
+            Some(fallback)
+        }
+        Err(error) => {
+            eprintln!("failed to load {path} with software decoding: '{error:#}'");
+            None
+        }
+    }
+}
+
+// Loads the next source of "player-load-with-fallbacks", the ones mpv
+// doesn't accept fail right away. Returns the reply once there is no source
+// left to try.
+fn next_source<B: PlayerBackend + ?Sized>(
+    backend: &B,
+    sources: &Mutex<SourceFallback>,
+    mut step: SourceStep,
+) -> Option<PlayerSourcesFailed> {
+    loop {
+        let loadfile = match step {
+            SourceStep::Load(loadfile) => loadfile,
+            SourceStep::Failed(failed) => return Some(failed),
+        };
+        match backend.run_command_node(&loadfile) {
+            Ok(()) => return None,
+            Err(error) => {
+                eprintln!("failed to load the next source: '{error:#}'");
+                step = sources
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .load_failed(Instant::now())?;
+            }
+        }
+    }
+}
+
+// Turns the backend events into player events until the backend shuts down
+pub fn run_event_loop<B, E>(
+    backend: &B,
+    events: &mut E,
+    state: &mut PlayerState,
+    stores: &PlayerStores,
+    observe_property_receiver: Receiver<ObserveProperty>,
+    player_event_sender: Sender<PlayerEvent>,
+) where
+    B: PlayerBackend + ?Sized,
+    E: PlayerEvents + ?Sized,
+{
+    let resume_store = stores.resume_store.as_ref();
+    let sources = || {
+        stores
+            .sources
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    };
+    // `MPV_EVENT_IDLE` is deprecated, `idle-active` is observed instead
+    events
+        .observe_prop("idle-active", Format::Flag, IDLE_ACTIVE_ID)
+        .expect("failed to observe MPV idle state");
+    for (name, format) in RESUME_PROPS {
+        events
+            .observe_prop(name, format, RESUME_PROPS_ID)
+            .expect("failed to observe MPV playback position");
+    }
+    if let Err(error) = events.observe_prop("hwdec-current", Format::String, HWDEC_CURRENT_ID) {
+        eprintln!("failed to observe MPV hardware decoder: '{error:#}'");
+    }
+    let send_event = |player_event| {
+        player_event_sender
+            .send(player_event)
+            .expect("failed to send PlayerEvent");
+    };
+    // The web UI is told about the properties which can't be observed
+    let observe_failed = |function: InMsgFn, name: &str, error: libmpv2::Error| {
+        eprintln!("failed to {function} MPV property {name}: '{error:#}'");
+        let message = serde_json::json!([function, name]).to_string();
+        send_event(PlayerEvent::Error(PlayerError::from_mpv(&message, &error)));
+    };
+    // The properties are observed again if the backend was recreated
+    for (name, id, format) in state.observed_props.iter() {
+        if let Err(error) = events.observe_prop(name, format, id) {
+            observe_failed(InMsgFn::MpvObserveProp, name, error);
+        }
+    }
+
+    // The playlist entry of the file being played, sent along with `end-file`
+    let mut playlist_entry_id = None;
+    let mut resume_saved_at: Option<Instant> = None;
+    let mut hwdec_fallback = HwdecFallback::default();
+
+    // -- Event handler loop --
+
+    loop {
+        for observe_property in observe_property_receiver.drain() {
+            match observe_property {
+                ObserveProperty::Observe { name, format } => {
+                    if let Some(id) = state.observed_props.observe(&name, format) {
+                        if let Err(error) = events.observe_prop(&name, format, id) {
+                            observe_failed(InMsgFn::MpvObserveProp, &name, error);
+                            state.observed_props.unobserve(&name);
+                        }
+                    }
+                }
+                ObserveProperty::Unobserve { name } => {
+                    if let Some(id) = state.observed_props.unobserve(&name) {
+                        if let Err(error) = events.unobserve_prop(id) {
+                            observe_failed(InMsgFn::MpvUnobserveProp, &name, error);
+                        }
+                    }
+                }
+            }
+        }
+
+        // The source being tried is replaced by the next one once it timed out
+        let timed_out = sources().timed_out(Instant::now());
+        if let Some(failed) = timed_out.and_then(|step| next_source(backend, &stores.sources, step))
+        {
+            send_event(PlayerEvent::SourcesFailed(failed));
+        }
+
+        // -1.0 means to block and wait for an event, sources being tried are
+        // waited for until they time out
+        let timeout = sources().deadline().map_or(-1., |deadline| {
+            deadline
+                .saturating_duration_since(Instant::now())
+                .as_secs_f64()
+        });
+        let event = match events.next_event(timeout) {
+            Some(Ok(event)) => event,
+            Some(Err(error)) => {
+                eprintln!("Event errored: {error:?}");
+                continue;
+            }
+            // dummy event received (may be created on a wake up call or on timeout)
+            None => continue,
+        };
+
+        // even if you don't do anything with the events, it is still necessary to empty the event loop
+        let player_event = match event {
+            BackendEvent::PropertyChange {
+                id: IDLE_ACTIVE_ID,
+                change,
+            } if change.data() == &serde_json::Value::Bool(true) => PlayerEvent::Idle,
+            BackendEvent::PropertyChange {
+                id: IDLE_ACTIVE_ID, ..
+            } => continue,
+            BackendEvent::PropertyChange {
+                id: RESUME_PROPS_ID,
+                change,
+            } => {
+                state.update(&change);
+                let save_due = match resume_saved_at {
+                    Some(saved_at) => {
+                        saved_at.elapsed() >= Duration::from_secs(RESUME_SAVE_INTERVAL)
+                    }
+                    None => true,
+                };
+                if let Some(entry) = state.resume_entry().filter(|_| save_due) {
+                    save_resume(resume_store, |resume_store| resume_store.set(entry));
+                    resume_saved_at = Some(Instant::now());
+                }
+                continue;
+            }
+            BackendEvent::PropertyChange {
+                id: HWDEC_CURRENT_ID,
+                change,
+            } => match hwdec_fallback.hwdec_changed(change.data().as_str()) {
+                Some(fallback) => {
+                    match fall_back(backend, state, &stores.settings, fallback, false) {
+                        Some(fallback) => PlayerEvent::HwdecFallback(fallback),
+                        None => continue,
+                    }
+                }
+                None => continue,
+            },
+            // Changes queued before the property was unobserved
+            BackendEvent::PropertyChange { id, .. } if !state.observed_props.is_observed_id(id) => {
+                continue
+            }
+            BackendEvent::PropertyChange { change, .. } => PlayerEvent::PropChange(change),
+            BackendEvent::EndFile { reason, error } => {
+                let ended = PlayerEnded::from_end_file(reason, error, playlist_entry_id);
+                let (reloading, fallback) = hwdec_fallback.end_file(&ended);
+                // A file played to the end starts over the next time
+                match (reason, &state.path, state.resume_entry()) {
+                    (mpv_end_file_reason::Eof, Some(path), _) => {
+                        save_resume(resume_store, |resume_store| {
+                            resume_store.remove(path);
+                        })
+                    }
+                    (_, _, Some(entry)) => {
+                        save_resume(resume_store, |resume_store| resume_store.set(entry))
+                    }
+                    _ => {}
+                }
+                // The file is played again instead of ending with the error
+                let fallback = fallback.and_then(|fallback| {
+                    fall_back(backend, state, &stores.settings, fallback, true)
+                });
+                state.clear_file();
+                resume_saved_at = None;
+                stores.thumbnails.cancel();
+                if let Some(fallback) = fallback {
+                    send_event(PlayerEvent::HwdecFallback(fallback));
+                    continue;
+                }
+                // Loaded again with software decoding
+                if reloading {
+                    continue;
+                }
+                let step = sources().end_file(&ended, Instant::now());
+                match step {
+                    // The next source is loaded instead of ending
+                    Some(step) => match next_source(backend, &stores.sources, step) {
+                        Some(failed) => {
+                            send_event(PlayerEvent::End(ended));
+                            PlayerEvent::SourcesFailed(failed)
+                        }
+                        None => continue,
+                    },
+                    None => PlayerEvent::End(ended),
+                }
+            }
+            BackendEvent::StartFile {
+                playlist_entry_id: id,
+            } => {
+                playlist_entry_id = Some(id);
+                sources().start_file();
+                PlayerEvent::StartFile
+            }
+            BackendEvent::FileLoaded => {
+                state.path = backend
+                    .get_prop("path", Format::String)
+                    .ok()
+                    .and_then(|path| path.data().as_str().map(ToString::to_string));
+                state.options = state
+                    .path
+                    .as_ref()
+                    .and_then(|path| {
+                        let options = stores
+                            .loadfile_options
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner);
+                        options.get(path).cloned()
+                    })
+                    .unwrap_or_default();
+                // Live streams have no duration and no thumbnails
+                let duration = backend
+                    .get_prop("duration", Format::Double)
+                    .ok()
+                    .and_then(|duration| duration.data().as_f64())
+                    .filter(|duration| *duration > 0.);
+                if let (Some(path), Some(duration)) = (&state.path, duration) {
+                    stores.thumbnails.start(path, duration);
+                }
+                let get_string = |name| {
+                    backend
+                        .get_prop(name, Format::String)
+                        .ok()
+                        .and_then(|prop| prop.data().as_str().map(ToString::to_string))
+                };
+                let software_codecs = stores
+                    .settings
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .software_codecs(unix_time());
+                let software_decoding = get_string("hwdec").as_deref() == Some("no");
+                let fallback = hwdec_fallback.file_loaded(
+                    get_string("video-format"),
+                    &software_codecs,
+                    software_decoding,
+                );
+                send_event(PlayerEvent::FileLoaded);
+                let fallback = match fallback {
+                    Some(fallback) => fallback,
+                    None => continue,
+                };
+                // Only for this file, the option is restored when it ends
+                let software = PropVal::Str("no".to_string());
+                match backend.set_prop("file-local-options/hwdec", &software) {
+                    Ok(()) => PlayerEvent::HwdecFallback(fallback),
+                    Err(error) => {
+                        eprintln!("cannot turn off hardware decoding: '{error:#}'");
+                        continue;
+                    }
+                }
+            }
+            BackendEvent::Seek => PlayerEvent::Seek,
+            BackendEvent::PlaybackRestart => {
+                let loaded = sources().playback_restart();
+                send_event(PlayerEvent::PlaybackRestart);
+                match loaded {
+                    Some(loaded) => PlayerEvent::SourceLoaded(loaded),
+                    None => continue,
+                }
+            }
+            BackendEvent::VideoReconfig => PlayerEvent::VideoReconfig,
+            BackendEvent::AudioReconfig => PlayerEvent::AudioReconfig,
+            BackendEvent::LogMessage {
+                level,
+                prefix,
+                text,
+            } => {
+                stores
+                    .player_log
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(LogEntry::new(&level, &prefix, &text));
+                let fallback = match hwdec_fallback.log_message(&level, &prefix) {
+                    Some(fallback) => fallback,
+                    None => continue,
+                };
+                match fall_back(backend, state, &stores.settings, fallback, true) {
+                    Some(fallback) => PlayerEvent::HwdecFallback(fallback),
+                    None => {
+                        hwdec_fallback.reload_failed();
+                        continue;
+                    }
+                }
+            }
+            BackendEvent::ClientMessage(mut args) => {
+                if args.is_empty() {
+                    continue;
+                }
+                let name = args.remove(0);
+                PlayerEvent::ScriptMessage(PlayerScriptMessage { name, args })
+            }
+            BackendEvent::Shutdown => {
+                break;
+            }
+        };
+        send_event(player_event);
+    }
+}
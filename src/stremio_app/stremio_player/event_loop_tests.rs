@@ -0,0 +1,689 @@
+use crate::stremio_app::stremio_player::{
+    backend::BackendEvent,
+    backend_fake::{end_file, prop_change, start_file, BackendCall, FakeBackend, FakeEvents},
+    communication::{loadfile_node, EndFileError},
+    event_loop::{run_event_loop, ObserveProperty, PlayerState},
+    observed_props::{HWDEC_CURRENT_ID, IDLE_ACTIVE_ID, RESUME_PROPS_ID},
+    player_log::{LogEntry, PlayerLog},
+    resume_store::{unix_time, ResumeEntry},
+    source_fallback::FallbackArgs,
+    supervisor::PlayerStores,
+    thumbnails::{cache_key, ThumbnailOptions, Thumbnails},
+    HwdecFallbackReason, LoadfileFlag, LoadfileOptions, PlayerEnded, PlayerError, PlayerEvent,
+    PlayerHwdecFallback, PlayerProprChange, PlayerScriptMessage, PlayerSourceFailure,
+    PlayerSourceLoaded, PlayerSourcesFailed, PropVal, SourceFailReason,
+};
+use libmpv2::{events::PropertyData, mpv_end_file_reason, Format};
+use serde_json::json;
+use std::{
+    env, fs, process,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+fn run(
+    backend: &Arc<FakeBackend>,
+    events: Vec<BackendEvent>,
+    state: &mut PlayerState,
+    stores: &PlayerStores,
+    observe: Vec<ObserveProperty>,
+) -> Vec<PlayerEvent> {
+    let (observe_property_sender, observe_property_receiver) = flume::unbounded();
+    let (player_event_sender, player_event_receiver) = flume::unbounded();
+    observe
+        .into_iter()
+        .for_each(|observe_property| observe_property_sender.send(observe_property).unwrap());
+    backend.shut_down();
+    run_event_loop(
+        backend.as_ref(),
+        &mut FakeEvents::new(backend, events),
+        state,
+        stores,
+        observe_property_receiver,
+        player_event_sender,
+    );
+    player_event_receiver.drain().collect()
+}
+
+fn stores() -> PlayerStores {
+    PlayerStores {
+        resume_store: Arc::default(),
+        player_log: Arc::new(Mutex::new(PlayerLog::new(10))),
+        settings: Arc::default(),
+        thumbnails: Arc::default(),
+        sources: Arc::default(),
+        loadfile_options: Arc::default(),
+        screenshot_dir: None,
+    }
+}
+
+// The properties the player observes for its own use
+fn player_observed() -> Vec<BackendCall> {
+    let observe = |name: &str| BackendCall::Observe(name.to_string(), RESUME_PROPS_ID);
+    vec![
+        BackendCall::Observe("idle-active".to_string(), IDLE_ACTIVE_ID),
+        observe("time-pos"),
+        observe("aid"),
+        observe("sid"),
+        observe("sub-delay"),
+        BackendCall::Observe("hwdec-current".to_string(), HWDEC_CURRENT_ID),
+    ]
+}
+
+fn observe(name: &str) -> ObserveProperty {
+    ObserveProperty::Observe {
+        name: name.to_string(),
+        format: Format::Double,
+    }
+}
+
+#[test]
+fn event_loop_events() {
+    let backend = Arc::new(FakeBackend::default());
+    let events = vec![
+        prop_change(IDLE_ACTIVE_ID, "idle-active", PropertyData::Flag(false)),
+        start_file(7),
+        BackendEvent::FileLoaded,
+        prop_change(1, "pause", PropertyData::Flag(true)),
+        BackendEvent::Seek,
+        BackendEvent::PlaybackRestart,
+        end_file(mpv_end_file_reason::Error, Some(-13)),
+        prop_change(IDLE_ACTIVE_ID, "idle-active", PropertyData::Flag(true)),
+        BackendEvent::Shutdown,
+        BackendEvent::FileLoaded,
+    ];
+    assert_eq!(
+        run(
+            &backend,
+            events,
+            &mut PlayerState::default(),
+            &stores(),
+            vec![observe("pause")]
+        ),
+        vec![
+            PlayerEvent::StartFile,
+            PlayerEvent::FileLoaded,
+            PlayerEvent::PropChange(PlayerProprChange::from_name_value(
+                "pause".to_string(),
+                PropertyData::Flag(true)
+            )),
+            PlayerEvent::Seek,
+            PlayerEvent::PlaybackRestart,
+            PlayerEvent::End(PlayerEnded::from_end_file(
+                mpv_end_file_reason::Error,
+                Some(-13),
+                Some(7)
+            )),
+            PlayerEvent::Idle,
+        ]
+    );
+}
+
+#[test]
+fn event_loop_observe() {
+    let backend = Arc::new(FakeBackend::default());
+    let unobserve = |name: &str| ObserveProperty::Unobserve {
+        name: name.to_string(),
+    };
+    let player_events = run(
+        &backend,
+        vec![
+            prop_change(1, "time-pos", PropertyData::Double(1.)),
+            prop_change(2, "volume", PropertyData::Double(50.)),
+        ],
+        &mut PlayerState::default(),
+        &stores(),
+        vec![
+            observe("time-pos"),
+            observe("time-pos"),
+            observe("volume"),
+            unobserve("time-pos"),
+            unobserve("duration"),
+        ],
+    );
+    // The change queued before `time-pos` was unobserved is dropped
+    assert_eq!(
+        player_events,
+        vec![PlayerEvent::PropChange(PlayerProprChange::from_name_value(
+            "volume".to_string(),
+            PropertyData::Double(50.)
+        ))]
+    );
+    assert_eq!(
+        backend.calls(),
+        [
+            player_observed(),
+            vec![
+                BackendCall::Observe("time-pos".to_string(), 1),
+                BackendCall::Observe("volume".to_string(), 2),
+                BackendCall::Unobserve(1),
+            ]
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn event_loop_observe_failed() {
+    let backend = Arc::new(FakeBackend::default().failing_observe("volume", -8));
+    let player_events = run(
+        &backend,
+        Vec::new(),
+        &mut PlayerState::default(),
+        &stores(),
+        vec![observe("volume")],
+    );
+    assert_eq!(
+        player_events,
+        vec![PlayerEvent::Error(PlayerError::from_mpv(
+            r#"["mpv-observe-prop", "volume"]"#,
+            &libmpv2::Error::Raw(-8)
+        ))]
+    );
+}
+
+#[test]
+fn event_loop_state() {
+    let mut state = PlayerState::default();
+    let stores = stores();
+    let backend =
+        Arc::new(FakeBackend::default().with_prop("path", PropertyData::Str("C:\\a b.mkv")));
+    run(
+        &backend,
+        vec![
+            BackendEvent::FileLoaded,
+            prop_change(RESUME_PROPS_ID, "time-pos", PropertyData::Double(42.5)),
+        ],
+        &mut state,
+        &stores,
+        vec![observe("time-pos")],
+    );
+    assert_eq!(state.path.as_deref(), Some("C:\\a b.mkv"));
+    assert_eq!(state.time_pos, Some(42.5));
+
+    // A recreated backend observes the same properties
+    let backend = Arc::new(FakeBackend::default());
+    run(
+        &backend,
+        vec![end_file(mpv_end_file_reason::Eof, None)],
+        &mut state,
+        &stores,
+        Vec::new(),
+    );
+    assert_eq!(
+        backend.calls(),
+        [
+            player_observed(),
+            vec![BackendCall::Observe("time-pos".to_string(), 1)]
+        ]
+        .concat()
+    );
+    assert_eq!(state.path, None);
+    assert_eq!(state.time_pos, None);
+}
+
+#[test]
+fn event_loop_resume() {
+    let stores = stores();
+    let backend =
+        Arc::new(FakeBackend::default().with_prop("path", PropertyData::Str("C:\\a b.mkv")));
+    let player_events = run(
+        &backend,
+        vec![
+            BackendEvent::FileLoaded,
+            prop_change(RESUME_PROPS_ID, "aid", PropertyData::Str("2")),
+            prop_change(RESUME_PROPS_ID, "sub-delay", PropertyData::Double(-0.5)),
+            prop_change(RESUME_PROPS_ID, "time-pos", PropertyData::Double(42.5)),
+            prop_change(RESUME_PROPS_ID, "time-pos", PropertyData::Double(43.5)),
+            end_file(mpv_end_file_reason::Stop, None),
+        ],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    // The resume properties are not sent to the web UI
+    assert!(
+        !player_events
+            .iter()
+            .any(|player_event| matches!(player_event, PlayerEvent::PropChange(_))),
+        "{:?}",
+        player_events
+    );
+    let entry = stores
+        .resume_store
+        .lock()
+        .unwrap()
+        .get("c:/A B.mkv")
+        .cloned()
+        .expect("expected a resume entry");
+    assert_eq!(
+        entry,
+        ResumeEntry {
+            path: "C:\\a b.mkv".to_string(),
+            time_pos: 43.5,
+            aid: Some("2".to_string()),
+            sid: None,
+            sub_delay: -0.5,
+            updated_at: entry.updated_at,
+        }
+    );
+
+    // A file played to the end is forgotten
+    run(
+        &backend,
+        vec![
+            BackendEvent::FileLoaded,
+            prop_change(RESUME_PROPS_ID, "time-pos", PropertyData::Double(3600.)),
+            end_file(mpv_end_file_reason::Eof, None),
+        ],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    assert!(stores.resume_store.lock().unwrap().is_empty());
+}
+
+#[test]
+fn event_loop_log() {
+    let stores = stores();
+    let log_message = |level: &str, text: &str| BackendEvent::LogMessage {
+        level: level.to_string(),
+        prefix: "ffmpeg".to_string(),
+        text: text.to_string(),
+    };
+    let player_events = run(
+        &Arc::new(FakeBackend::default()),
+        vec![
+            log_message("warn", "slow connection\n"),
+            BackendEvent::Seek,
+            log_message("error", "http: 404 Not Found\n"),
+        ],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    // The log messages are not sent to the web UI
+    assert_eq!(player_events, vec![PlayerEvent::Seek]);
+    let logs: Vec<(String, String, String)> = stores
+        .player_log
+        .lock()
+        .unwrap()
+        .entries()
+        .map(
+            |LogEntry {
+                 level,
+                 prefix,
+                 text,
+                 ..
+             }| (level.clone(), prefix.clone(), text.clone()),
+        )
+        .collect();
+    assert_eq!(
+        logs,
+        vec![
+            (
+                "warn".to_string(),
+                "ffmpeg".to_string(),
+                "slow connection".to_string()
+            ),
+            (
+                "error".to_string(),
+                "ffmpeg".to_string(),
+                "http: 404 Not Found".to_string()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn event_loop_thumbnails() {
+    let cache_dir = env::temp_dir().join(format!("stremio-thumbnails-events-{}", process::id()));
+    let input = cache_dir.join("movie.mkv").to_string_lossy().into_owned();
+    let dir = cache_dir.join(cache_key(&input));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("000001.jpg"), b"").unwrap();
+    fs::write(dir.join("done"), b"").unwrap();
+    let stores = PlayerStores {
+        thumbnails: Arc::new(Thumbnails::new(ThumbnailOptions {
+            ffmpeg: cache_dir.join("missing-ffmpeg"),
+            cache_dir: cache_dir.clone(),
+            interval: 10.,
+            width: 160,
+            disk_budget: 1024,
+        })),
+        ..stores()
+    };
+    let backend = |duration: f64| {
+        Arc::new(
+            FakeBackend::default()
+                .with_prop("path", PropertyData::Str(&input))
+                .with_prop("duration", PropertyData::Double(duration)),
+        )
+    };
+    // Live streams have no duration
+    run(
+        &backend(0.),
+        vec![BackendEvent::FileLoaded],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    assert_eq!(stores.thumbnails.frame_at(0.), None);
+
+    run(
+        &backend(60.),
+        vec![BackendEvent::FileLoaded],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    assert!(stores.thumbnails.frame_at(0.).is_some());
+
+    run(
+        &backend(60.),
+        vec![end_file(mpv_end_file_reason::Stop, None)],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    assert_eq!(stores.thumbnails.frame_at(0.), None);
+    fs::remove_dir_all(&cache_dir).ok();
+}
+
+#[test]
+fn event_loop_hwdec_fallback() {
+    let stores = stores();
+    let backend = Arc::new(
+        FakeBackend::default()
+            .with_prop("path", PropertyData::Str("C:\\a b.mkv"))
+            .with_prop("video-format", PropertyData::Str("hevc"))
+            .with_prop("hwdec", PropertyData::Str("auto")),
+    );
+    let decode_error = || BackendEvent::LogMessage {
+        level: "error".to_string(),
+        prefix: "vd".to_string(),
+        text: "Error while decoding frame (hardware decoding)!\n".to_string(),
+    };
+    let hwdec_current =
+        |hwdec: &str| prop_change(HWDEC_CURRENT_ID, "hwdec-current", PropertyData::Str(hwdec));
+    let player_events = run(
+        &backend,
+        vec![
+            BackendEvent::FileLoaded,
+            hwdec_current("d3d11va"),
+            prop_change(RESUME_PROPS_ID, "time-pos", PropertyData::Double(42.5)),
+            decode_error(),
+            decode_error(),
+            decode_error(),
+            hwdec_current("no"),
+            // Ended by the new `loadfile`
+            end_file(mpv_end_file_reason::Stop, None),
+        ],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    assert_eq!(
+        player_events,
+        vec![
+            PlayerEvent::FileLoaded,
+            PlayerEvent::HwdecFallback(PlayerHwdecFallback {
+                reason: HwdecFallbackReason::DecodeErrors,
+                hwdec: Some("d3d11va".to_string()),
+                codec: Some("hevc".to_string()),
+                reloaded: true,
+                time_pos: Some(42.5),
+            }),
+        ]
+    );
+    assert!(backend
+        .calls()
+        .contains(&BackendCall::CommandNode(loadfile_node(
+            "C:\\a b.mkv",
+            LoadfileFlag::Replace,
+            vec![
+                ("start".to_string(), "42.5".to_string()),
+                ("hwdec".to_string(), "no".to_string())
+            ]
+        ))));
+    assert_eq!(
+        stores.settings.lock().unwrap().software_codecs(unix_time()),
+        ["hevc"]
+    );
+
+    // The next files with the codec are decoded by software right away
+    let backend = Arc::new(
+        FakeBackend::default()
+            .with_prop("path", PropertyData::Str("C:\\b.mkv"))
+            .with_prop("video-format", PropertyData::Str("hevc"))
+            .with_prop("hwdec", PropertyData::Str("auto")),
+    );
+    let player_events = run(
+        &backend,
+        vec![BackendEvent::FileLoaded],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    assert_eq!(
+        player_events,
+        vec![
+            PlayerEvent::FileLoaded,
+            PlayerEvent::HwdecFallback(PlayerHwdecFallback {
+                reason: HwdecFallbackReason::Remembered,
+                hwdec: None,
+                codec: Some("hevc".to_string()),
+                reloaded: false,
+                time_pos: None,
+            }),
+        ]
+    );
+    assert!(backend.calls().contains(&BackendCall::SetProperty(
+        "file-local-options/hwdec".to_string(),
+        PropVal::Str("no".to_string()),
+    )));
+}
+
+#[test]
+fn event_loop_hwdec_end_file_error() {
+    let backend = Arc::new(
+        FakeBackend::default()
+            .with_prop("path", PropertyData::Str("https://a/1.mkv"))
+            .with_prop("video-format", PropertyData::Str("av1")),
+    );
+    let events = |error| {
+        vec![
+            BackendEvent::FileLoaded,
+            prop_change(
+                HWDEC_CURRENT_ID,
+                "hwdec-current",
+                PropertyData::Str("nvdec"),
+            ),
+            BackendEvent::LogMessage {
+                level: "error".to_string(),
+                prefix: "ffmpeg/video".to_string(),
+                text: "av1: Failed to get pixel format.\n".to_string(),
+            },
+            end_file(mpv_end_file_reason::Error, Some(error)),
+        ]
+    };
+    // Loaded with an HTTP header
+    let header_stores = stores();
+    let options = LoadfileOptions {
+        http_headers: [("Cookie".to_string(), "a=1".to_string())].into(),
+        ..LoadfileOptions::default()
+    };
+    header_stores
+        .loadfile_options
+        .lock()
+        .unwrap()
+        .insert("https://a/1.mkv".to_string(), options);
+    let player_events = run(
+        &backend,
+        events(-1),
+        &mut PlayerState::default(),
+        &header_stores,
+        Vec::new(),
+    );
+    // The error is not sent, the file is played again from the start
+    assert_eq!(
+        player_events,
+        vec![
+            PlayerEvent::FileLoaded,
+            PlayerEvent::HwdecFallback(PlayerHwdecFallback {
+                reason: HwdecFallbackReason::EndFileError,
+                hwdec: Some("nvdec".to_string()),
+                codec: Some("av1".to_string()),
+                reloaded: true,
+                time_pos: None,
+            }),
+        ]
+    );
+    assert!(backend
+        .calls()
+        .contains(&BackendCall::CommandNode(loadfile_node(
+            "https://a/1.mkv",
+            LoadfileFlag::Replace,
+            vec![
+                ("http-header-fields".to_string(), "Cookie: a=1".to_string()),
+                ("hwdec".to_string(), "no".to_string())
+            ]
+        ))));
+
+    // Network errors end the file as usual
+    let stores = stores();
+    let player_events = run(
+        &Arc::new(FakeBackend::default().with_prop("video-format", PropertyData::Str("av1"))),
+        events(-13),
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    assert_eq!(
+        player_events,
+        vec![
+            PlayerEvent::FileLoaded,
+            PlayerEvent::End(PlayerEnded::from_end_file(
+                mpv_end_file_reason::Error,
+                Some(-13),
+                None
+            )),
+        ]
+    );
+    assert!(stores.settings.lock().unwrap().software_decoding.is_empty());
+}
+
+#[test]
+fn event_loop_script_message() {
+    let client_message =
+        |args: &[&str]| BackendEvent::ClientMessage(args.iter().map(ToString::to_string).collect());
+    let player_events = run(
+        &Arc::new(FakeBackend::default()),
+        vec![
+            client_message(&["silence-skipped", "12.5", "30"]),
+            client_message(&[]),
+            client_message(&["osd-hidden"]),
+        ],
+        &mut PlayerState::default(),
+        &stores(),
+        Vec::new(),
+    );
+    assert_eq!(
+        player_events,
+        vec![
+            PlayerEvent::ScriptMessage(PlayerScriptMessage {
+                name: "silence-skipped".to_string(),
+                args: vec!["12.5".to_string(), "30".to_string()],
+            }),
+            PlayerEvent::ScriptMessage(PlayerScriptMessage {
+                name: "osd-hidden".to_string(),
+                args: Vec::new(),
+            }),
+        ]
+    );
+}
+
+#[test]
+fn event_loop_source_fallback() {
+    let stores = stores();
+    let start = |id: u64, urls: serde_json::Value| {
+        let args: FallbackArgs = serde_json::from_value(json!({ "sources": urls })).unwrap();
+        stores
+            .sources
+            .lock()
+            .unwrap()
+            .start(id, args, Instant::now());
+    };
+    let failure = || PlayerSourceFailure {
+        url: "https://a/1.mkv".to_string(),
+        reason: SourceFailReason::Error,
+        error: Some(EndFileError::LoadingFailed),
+    };
+    start(
+        1,
+        json!([{"url": "https://a/1.mkv"}, {"url": "https://b/1.mkv"}]),
+    );
+    let backend = Arc::new(FakeBackend::default());
+    let player_events = run(
+        &backend,
+        vec![
+            start_file(1),
+            end_file(mpv_end_file_reason::Error, Some(-13)),
+            start_file(2),
+            BackendEvent::PlaybackRestart,
+        ],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    // The end of the first source is not sent
+    assert_eq!(
+        player_events,
+        vec![
+            PlayerEvent::StartFile,
+            PlayerEvent::StartFile,
+            PlayerEvent::PlaybackRestart,
+            PlayerEvent::SourceLoaded(PlayerSourceLoaded {
+                id: 1,
+                index: 1,
+                url: "https://b/1.mkv".to_string(),
+                failed: vec![failure()],
+            }),
+        ]
+    );
+    assert!(backend
+        .calls()
+        .contains(&BackendCall::CommandNode(loadfile_node(
+            "https://b/1.mkv",
+            LoadfileFlag::Replace,
+            Vec::new()
+        ))));
+
+    // The last source ends as usual
+    start(2, json!([{"url": "https://a/1.mkv"}]));
+    let player_events = run(
+        &Arc::new(FakeBackend::default()),
+        vec![
+            start_file(3),
+            end_file(mpv_end_file_reason::Error, Some(-13)),
+        ],
+        &mut PlayerState::default(),
+        &stores,
+        Vec::new(),
+    );
+    assert_eq!(
+        player_events,
+        vec![
+            PlayerEvent::StartFile,
+            PlayerEvent::End(PlayerEnded::from_end_file(
+                mpv_end_file_reason::Error,
+                Some(-13),
+                Some(3)
+            )),
+            PlayerEvent::SourcesFailed(PlayerSourcesFailed {
+                id: 2,
+                failed: vec![failure()],
+            }),
+        ]
+    );
+}
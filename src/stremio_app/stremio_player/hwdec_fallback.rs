@@ -0,0 +1,112 @@
+use crate::stremio_app::constants::HWDEC_MAX_DECODE_ERRORS;
+use crate::stremio_app::stremio_player::{
+    communication::{loadfile_node, EndReason, NodeValue},
+    HwdecFallbackReason, LoadfileFlag, LoadfileOptions, PlayerEnded, PlayerHwdecFallback,
+};
+
+// Decides when the file being played has to fall back to software decoding.
+// Hardware decoders fail on some HEVC and AV1 files, either with decoding
+// errors, by ending the file with an error after them, or by mpv dropping
+// them after the errors. The event loop carries out the decisions.
+#[derive(Default, Debug)]
+pub struct HwdecFallback {
+    // The codec of the video being played, from `video-format`
+    codec: Option<String>,
+    // The hardware decoder in use, from `hwdec-current`
+    hwdec: Option<String>,
+    decode_errors: u32,
+    // The file is being loaded again, so its `end-file` is not reported
+    reloading: bool,
+}
+
+impl HwdecFallback {
+    // Software decoding is used right away for the codecs which failed
+    // before, unless it is used anyway
+    pub fn file_loaded(
+        &mut self,
+        codec: Option<String>,
+        software_codecs: &[String],
+        software_decoding: bool,
+    ) -> Option<PlayerHwdecFallback> {
+        self.codec = codec;
+        self.decode_errors = 0;
+        let codec = self.codec.as_ref()?;
+        (!software_decoding && software_codecs.contains(codec))
+            .then(|| self.fallback(HwdecFallbackReason::Remembered, None))
+    }
+
+    // Called with the new `hwdec-current`, "no" or empty for software
+    // decoding. mpv drops a hardware decoder which keeps failing by itself.
+    pub fn hwdec_changed(&mut self, hwdec: Option<&str>) -> Option<PlayerHwdecFallback> {
+        let hwdec = hwdec.filter(|hwdec| !hwdec.is_empty() && *hwdec != "no");
+        let dropped = self.hwdec.take().filter(|_| hwdec.is_none());
+        self.hwdec = hwdec.map(ToString::to_string);
+        dropped
+            .filter(|_| self.decode_errors > 0 && !self.reloading)
+            .map(|dropped| self.fallback(HwdecFallbackReason::DecoderLost, Some(dropped)))
+    }
+
+    // Counts the decoding errors of the hardware decoder, the file is
+    // loaded again after `HWDEC_MAX_DECODE_ERRORS` of them
+    pub fn log_message(&mut self, level: &str, prefix: &str) -> Option<PlayerHwdecFallback> {
+        let decoder = prefix == "vd" || prefix.starts_with("ffmpeg/video");
+        if level != "error" || !decoder || self.hwdec.is_none() || self.reloading {
+            return None;
+        }
+        self.decode_errors += 1;
+        (self.decode_errors == HWDEC_MAX_DECODE_ERRORS).then(|| {
+            self.reloading = true;
+            let hwdec = self.hwdec.clone();
+            self.fallback(HwdecFallbackReason::DecodeErrors, hwdec)
+        })
+    }
+
+    // Returns whether the file ended because it is being loaded again with
+    // software decoding, and the decision if it ended with an error after
+    // the hardware decoder failed. Errors of the stream itself, like network
+    // errors, don't count. Forgets the file.
+    pub fn end_file(&mut self, ended: &PlayerEnded) -> (bool, Option<PlayerHwdecFallback>) {
+        let reloading = self.reloading;
+        let decoder_failed = self.decode_errors > 0 && !ended.is_stream_error();
+        let fallback = match (&ended.reason, self.hwdec.clone()) {
+            (EndReason::Error, Some(hwdec)) if decoder_failed && !reloading => {
+                Some(self.fallback(HwdecFallbackReason::EndFileError, Some(hwdec)))
+            }
+            _ => None,
+        };
+        // mpv only reports `hwdec-current` when it changes
+        *self = Self {
+            hwdec: self.hwdec.take(),
+            ..Self::default()
+        };
+        (reloading, fallback)
+    }
+
+    // The reload failed, the file ends as usual
+    pub fn reload_failed(&mut self) {
+        self.reloading = false;
+    }
+
+    fn fallback(&self, reason: HwdecFallbackReason, hwdec: Option<String>) -> PlayerHwdecFallback {
+        PlayerHwdecFallback {
+            reason,
+            hwdec,
+            codec: self.codec.clone(),
+            reloaded: false,
+            time_pos: None,
+        }
+    }
+}
+
+// The `loadfile` command which plays `path` again from `time_pos` with
+// software decoding and the options it was loaded with. The options only
+// apply to this file.
+pub fn reload_command(path: &str, time_pos: Option<f64>, options: &LoadfileOptions) -> NodeValue {
+    let options = LoadfileOptions {
+        start: time_pos.or(options.start),
+        ..options.clone()
+    };
+    let mut options = options.to_mpv();
+    options.push(("hwdec".to_string(), "no".to_string()));
+    loadfile_node(path, LoadfileFlag::Replace, options)
+}
@@ -0,0 +1,174 @@
+use crate::stremio_app::stremio_player::{
+    hwdec_fallback::{reload_command, HwdecFallback},
+    HwdecFallbackReason, LoadfileOptions, PlayerEnded, PlayerHwdecFallback,
+};
+use libmpv2::{mpv_end_file_reason, EndFileReason};
+use serde_json::json;
+
+fn fallback(reason: HwdecFallbackReason, hwdec: Option<&str>) -> PlayerHwdecFallback {
+    PlayerHwdecFallback {
+        reason,
+        hwdec: hwdec.map(ToString::to_string),
+        codec: Some("hevc".to_string()),
+        reloaded: false,
+        time_pos: None,
+    }
+}
+
+fn ended(reason: EndFileReason, error: Option<i32>) -> PlayerEnded {
+    PlayerEnded::from_end_file(reason, error, None)
+}
+
+fn decode_error(hwdec_fallback: &mut HwdecFallback) -> Option<PlayerHwdecFallback> {
+    hwdec_fallback.log_message("error", "vd")
+}
+
+#[test]
+fn hwdec_fallback_decode_errors() {
+    let mut hwdec_fallback = HwdecFallback::default();
+    hwdec_fallback.file_loaded(Some("hevc".to_string()), &[], false);
+    // Software decoding errors don't count
+    assert_eq!(decode_error(&mut hwdec_fallback), None);
+    assert_eq!(hwdec_fallback.hwdec_changed(Some("d3d11va")), None);
+    assert_eq!(hwdec_fallback.log_message("warn", "vd"), None);
+    assert_eq!(hwdec_fallback.log_message("error", "ao"), None);
+    assert_eq!(decode_error(&mut hwdec_fallback), None);
+    assert_eq!(hwdec_fallback.log_message("error", "ffmpeg/video"), None);
+    assert_eq!(
+        decode_error(&mut hwdec_fallback),
+        Some(fallback(HwdecFallbackReason::DecodeErrors, Some("d3d11va")))
+    );
+    // Once only, while the file is loaded again
+    assert_eq!(decode_error(&mut hwdec_fallback), None);
+    assert_eq!(hwdec_fallback.hwdec_changed(Some("no")), None);
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Stop, None)),
+        (true, None)
+    );
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Stop, None)),
+        (false, None)
+    );
+}
+
+#[test]
+fn hwdec_fallback_decoder_lost() {
+    let mut hwdec_fallback = HwdecFallback::default();
+    hwdec_fallback.file_loaded(Some("hevc".to_string()), &[], false);
+    hwdec_fallback.hwdec_changed(Some("d3d11va"));
+    // Video reconfigurations without errors
+    assert_eq!(hwdec_fallback.hwdec_changed(Some("")), None);
+    hwdec_fallback.hwdec_changed(Some("d3d11va"));
+    assert_eq!(decode_error(&mut hwdec_fallback), None);
+    assert_eq!(
+        hwdec_fallback.hwdec_changed(Some("no")),
+        Some(fallback(HwdecFallbackReason::DecoderLost, Some("d3d11va")))
+    );
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Eof, None)),
+        (false, None)
+    );
+}
+
+#[test]
+fn hwdec_fallback_end_file_error() {
+    let mut hwdec_fallback = HwdecFallback::default();
+    hwdec_fallback.file_loaded(Some("hevc".to_string()), &[], false);
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Error, None)),
+        (false, None)
+    );
+
+    // Without decoding errors the decoder is not to blame
+    hwdec_fallback.file_loaded(Some("hevc".to_string()), &[], false);
+    hwdec_fallback.hwdec_changed(Some("dxva2"));
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Error, None)),
+        (false, None)
+    );
+    // Nor for network errors
+    hwdec_fallback.file_loaded(Some("hevc".to_string()), &[], false);
+    decode_error(&mut hwdec_fallback);
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Error, Some(-13))),
+        (false, None)
+    );
+
+    hwdec_fallback.file_loaded(Some("hevc".to_string()), &[], false);
+    decode_error(&mut hwdec_fallback);
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Error, None)),
+        (
+            false,
+            Some(fallback(HwdecFallbackReason::EndFileError, Some("dxva2")))
+        )
+    );
+    // Played again with software decoding
+    hwdec_fallback.file_loaded(Some("hevc".to_string()), &[], true);
+    hwdec_fallback.hwdec_changed(Some("no"));
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Error, None)),
+        (false, None)
+    );
+
+    // The decoder dropped by mpv was already reported
+    hwdec_fallback.file_loaded(Some("hevc".to_string()), &[], false);
+    hwdec_fallback.hwdec_changed(Some("dxva2"));
+    decode_error(&mut hwdec_fallback);
+    assert!(hwdec_fallback.hwdec_changed(Some("no")).is_some());
+    assert_eq!(
+        hwdec_fallback.end_file(&ended(mpv_end_file_reason::Error, None)),
+        (false, None)
+    );
+}
+
+#[test]
+fn hwdec_fallback_remembered() {
+    let mut hwdec_fallback = HwdecFallback::default();
+    let software_codecs = ["av1".to_string(), "hevc".to_string()];
+    assert_eq!(
+        hwdec_fallback.file_loaded(Some("hevc".to_string()), &software_codecs, false),
+        Some(fallback(HwdecFallbackReason::Remembered, None))
+    );
+    assert_eq!(
+        hwdec_fallback.file_loaded(Some("hevc".to_string()), &software_codecs, true),
+        None
+    );
+    assert_eq!(
+        hwdec_fallback.file_loaded(Some("h264".to_string()), &software_codecs, false),
+        None
+    );
+    assert_eq!(
+        hwdec_fallback.file_loaded(None, &software_codecs, false),
+        None
+    );
+}
+
+#[test]
+fn hwdec_fallback_reload_command() {
+    assert_eq!(
+        serde_json::Value::from(reload_command(
+            "C:\\a b.mkv",
+            Some(61.5),
+            &LoadfileOptions {
+                start: Some(10.),
+                ..LoadfileOptions::default()
+            }
+        )),
+        json!({"name": "loadfile", "url": "C:\\a b.mkv", "flags": "replace",
+            "options": {"start": "61.5", "hwdec": "no"}})
+    );
+    assert_eq!(
+        serde_json::Value::from(reload_command(
+            "https://example.com/a.mp4",
+            None,
+            &LoadfileOptions {
+                start: Some(10.),
+                user_agent: Some("Stremio".to_string()),
+                ..LoadfileOptions::default()
+            }
+        )),
+        json!({"name": "loadfile", "url": "https://example.com/a.mp4", "flags": "replace",
+            "options": {"start": "10", "user-agent": "Stremio", "hwdec": "no"}})
+    );
+}
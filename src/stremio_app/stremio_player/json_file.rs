@@ -0,0 +1,28 @@
+use serde::{de::DeserializeOwned, Serialize};
+use std::{fs, io, path::Path};
+
+// Reads the JSON `file`. Returns the default value if the file is missing or
+// can't be read, `what` names the file in the error messages.
+pub fn load<T: DeserializeOwned + Default>(file: &Path, what: &str) -> T {
+    match fs::read_to_string(file) {
+        Ok(content) => serde_json::from_str(&content).unwrap_or_else(|error| {
+            eprintln!("invalid {what} {}: {error}", file.display());
+            T::default()
+        }),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => T::default(),
+        Err(error) => {
+            eprintln!("cannot read {what} {}: {error}", file.display());
+            T::default()
+        }
+    }
+}
+
+// Writes to a temporary file first, so a crash doesn't leave a truncated file
+pub fn save<T: Serialize>(file: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp_file = file.with_extension("tmp");
+    fs::write(&temp_file, serde_json::to_vec(value)?)?;
+    fs::rename(&temp_file, file)
+}
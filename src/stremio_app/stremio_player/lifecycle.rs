@@ -0,0 +1,48 @@
+use flume::Sender;
+use std::{
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+const JOIN_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// The threads of a running player
+pub struct PlayerThreads {
+    // Dropping the sender stops the supervisor thread, which quits the backend
+    stop_sender: Sender<()>,
+    threads: Vec<(&'static str, JoinHandle<()>)>,
+}
+
+impl PlayerThreads {
+    pub fn new(stop_sender: Sender<()>, threads: Vec<(&'static str, JoinHandle<()>)>) -> Self {
+        Self {
+            stop_sender,
+            threads,
+        }
+    }
+
+    // Stops handling messages from the web UI and waits for the backend to
+    // quit and the threads to finish, so the pending events are delivered.
+    // Returns `false` if some thread is still running after `timeout`.
+    pub fn shutdown(self, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+        drop(self.stop_sender);
+        join_until(self.threads, deadline)
+    }
+}
+
+fn join_until(threads: Vec<(&'static str, JoinHandle<()>)>, deadline: Instant) -> bool {
+    let mut all_finished = true;
+    for (name, thread) in threads {
+        while !thread.is_finished() && Instant::now() < deadline {
+            thread::sleep(JOIN_POLL_INTERVAL);
+        }
+        if !thread.is_finished() {
+            eprintln!("player {name} thread did not stop in time");
+            all_finished = false;
+        } else if thread.join().is_err() {
+            eprintln!("player {name} thread panicked");
+        }
+    }
+    all_finished
+}
@@ -0,0 +1,37 @@
+use crate::stremio_app::stremio_player::lifecycle::PlayerThreads;
+use std::{thread, time::Duration};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn shutdown_stops_threads() {
+    let (stop_sender, stop_receiver) = flume::bounded::<()>(0);
+    let (event_sender, event_receiver) = flume::bounded::<()>(0);
+    // The supervisor stops on the stop signal, the other threads once it has stopped
+    let supervisor_thread = thread::spawn(move || {
+        while stop_receiver.recv().is_ok() {}
+        drop(event_sender);
+    });
+    let throttle_thread = thread::spawn(move || while event_receiver.recv().is_ok() {});
+
+    let threads = PlayerThreads::new(
+        stop_sender,
+        vec![
+            ("supervisor", supervisor_thread),
+            ("throttle", throttle_thread),
+        ],
+    );
+    assert!(threads.shutdown(TIMEOUT));
+}
+
+#[test]
+fn shutdown_times_out() {
+    let (stop_sender, _stop_receiver) = flume::bounded::<()>(0);
+    let (_event_sender, event_receiver) = flume::bounded::<()>(0);
+    let throttle_thread = thread::spawn(move || {
+        event_receiver.recv().ok();
+    });
+
+    let threads = PlayerThreads::new(stop_sender, vec![("throttle", throttle_thread)]);
+    assert!(!threads.shutdown(Duration::from_millis(50)));
+}
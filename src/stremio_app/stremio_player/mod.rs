@@ -1,9 +1,64 @@
 pub mod player;
 pub use player::Player;
+pub mod backend;
 pub mod communication;
+pub mod dispatch;
+pub mod event_loop;
+pub mod hwdec_fallback;
+pub mod json_file;
+pub mod lifecycle;
+pub mod mpv_config;
+pub mod mpv_scripts;
+pub mod observed_props;
+pub mod player_log;
+pub mod player_settings;
+pub mod resume_store;
+pub mod screenshot;
+pub mod source_fallback;
+pub mod supervisor;
+pub mod throttle;
+pub mod thumbnails;
 pub use communication::{
-    CmdVal, InMsg, InMsgArgs, InMsgFn, PlayerEnded, PlayerEvent, PlayerProprChange, PlayerResponse,
-    PropKey, PropVal,
+    CmdVal, HwdecFallbackReason, InMsg, InMsgArgs, InMsgFn, LoadfileFlag, LoadfileOptions,
+    NodeValue, PlayerAudioDeviceMissing, PlayerCapabilities, PlayerEnded, PlayerError, PlayerEvent,
+    PlayerHwdecFallback, PlayerLogs, PlayerLogsExported, PlayerPropValue, PlayerProprChange,
+    PlayerResponse, PlayerRestarted, PlayerResume, PlayerScreenshot, PlayerScriptMessage,
+    PlayerSourceFailure, PlayerSourceLoaded, PlayerSourcesFailed, PlayerThumbnail, PropKey,
+    PropVal, RequestArgs, SourceFailReason,
 };
 #[cfg(test)]
+mod backend_fake;
+#[cfg(test)]
+mod backend_tests;
+#[cfg(test)]
 mod communication_tests;
+#[cfg(test)]
+mod dispatch_tests;
+#[cfg(test)]
+mod event_loop_tests;
+#[cfg(test)]
+mod hwdec_fallback_tests;
+#[cfg(test)]
+mod lifecycle_tests;
+#[cfg(test)]
+mod mpv_config_tests;
+#[cfg(test)]
+mod mpv_scripts_tests;
+#[cfg(test)]
+mod observed_props_tests;
+#[cfg(test)]
+mod player_log_tests;
+#[cfg(test)]
+mod player_settings_tests;
+#[cfg(test)]
+mod resume_store_tests;
+#[cfg(test)]
+mod screenshot_tests;
+#[cfg(test)]
+mod source_fallback_tests;
+#[cfg(test)]
+mod supervisor_tests;
+#[cfg(test)]
+mod throttle_tests;
+#[cfg(test)]
+mod thumbnails_tests;
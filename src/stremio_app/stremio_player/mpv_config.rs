@@ -0,0 +1,232 @@
+use std::{fmt, fs, io, path::Path};
+
+// Options which may be set in the user's `mpv.conf`. Anything which could
+// take over the window, the IPC, load code or write files is left out.
+pub const ALLOWED_OPTIONS: &[&str] = &[
+    // Video output and decoding
+    "vo",
+    "gpu-api",
+    "gpu-context",
+    "d3d11-adapter",
+    "d3d11-flip",
+    "d3d11va-zero-copy",
+    "vulkan-device",
+    "hwdec",
+    "hwdec-codecs",
+    "vd-lavc-threads",
+    "vd-lavc-skiploopfilter",
+    "video-sync",
+    "interpolation",
+    "deinterlace",
+    // Scaling and image quality
+    "scale",
+    "cscale",
+    "dscale",
+    "tscale",
+    "scale-antiring",
+    "dscale-antiring",
+    "cscale-antiring",
+    "correct-downscaling",
+    "linear-downscaling",
+    "sigmoid-upscaling",
+    "deband",
+    "deband-iterations",
+    "deband-threshold",
+    "deband-range",
+    "deband-grain",
+    "dither",
+    "dither-depth",
+    "fbo-format",
+    "tone-mapping",
+    "hdr-compute-peak",
+    "target-colorspace-hint",
+    "icc-profile-auto",
+    "video-output-levels",
+    // Cache
+    "cache",
+    "cache-secs",
+    "cache-pause",
+    "cache-pause-initial",
+    "cache-pause-wait",
+    "demuxer-max-bytes",
+    "demuxer-max-back-bytes",
+    "demuxer-readahead-secs",
+    "network-timeout",
+    // Audio
+    "ao",
+    "audio-device",
+    "audio-channels",
+    "audio-exclusive",
+    "audio-samplerate",
+    "audio-spdif",
+    "audio-normalize-downmix",
+    "audio-pitch-correction",
+    "volume-max",
+    // Tracks and subtitles
+    "alang",
+    "slang",
+    "sub-codepage",
+    "sub-font",
+    "sub-font-size",
+    "sub-color",
+    "sub-border-color",
+    "sub-border-size",
+    "sub-shadow-offset",
+    "sub-scale",
+    "sub-pos",
+    "sub-ass-override",
+    "sub-fix-timing",
+    // Screenshots
+    "screenshot-format",
+    "screenshot-jpeg-quality",
+    "screenshot-png-compression",
+    "screenshot-high-bit-depth",
+];
+
+// Allowed options which may only be set to one of these values. The other
+// outputs can write files, e.g. `vo=image` or `ao=pcm`.
+pub const ALLOWED_VALUES: &[(&str, &[&str])] = &[
+    ("vo", &["gpu", "gpu-next", "direct3d"]),
+    ("ao", &["wasapi", "openal", "sdl"]),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RejectReason {
+    NotAllowed,
+    ValueNotAllowed,
+    Profile,
+    InvalidSyntax,
+}
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotAllowed => write!(f, "option is not allowed"),
+            Self::ValueNotAllowed => write!(f, "value is not allowed"),
+            Self::Profile => write!(f, "profiles are not supported"),
+            Self::InvalidSyntax => write!(f, "invalid syntax"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RejectedLine {
+    // 1-based, as shown by text editors
+    pub line: usize,
+    pub text: String,
+    pub reason: RejectReason,
+}
+impl fmt::Display for RejectedLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: '{}' ({})", self.line, self.text, self.reason)
+    }
+}
+
+// The options of an `mpv.conf` file which passed the allow-list and the
+// lines which were rejected
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct MpvConfig {
+    pub options: Vec<(String, String)>,
+    pub rejected: Vec<RejectedLine>,
+}
+
+impl MpvConfig {
+    // An empty config if the file doesn't exist
+    pub fn load(path: &Path) -> io::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => Ok(Self::parse(&content)),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    // Parses the mpv config file syntax: `option=value` or `option` for
+    // flags, an optional `--` prefix, `no-option` to disable a flag, quoted
+    // or `%length%` prefixed values and `#` comments. Profile sections are
+    // rejected as a whole.
+    pub fn parse(content: &str) -> Self {
+        let mut config = Self::default();
+        let mut in_profile = false;
+        for (index, text) in content.lines().enumerate() {
+            let line = text.trim();
+            let reject = |reason| RejectedLine {
+                line: index + 1,
+                text: line.to_string(),
+                reason,
+            };
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[') {
+                in_profile = section.split(']').next().unwrap_or_default().trim() != "default";
+                if in_profile {
+                    config.rejected.push(reject(RejectReason::Profile));
+                }
+                continue;
+            }
+            if in_profile {
+                config.rejected.push(reject(RejectReason::Profile));
+                continue;
+            }
+            match parse_option(line) {
+                Some((name, _)) if !ALLOWED_OPTIONS.contains(&name.as_str()) => {
+                    config.rejected.push(reject(RejectReason::NotAllowed))
+                }
+                Some((name, value)) if !is_allowed_value(&name, &value) => {
+                    config.rejected.push(reject(RejectReason::ValueNotAllowed))
+                }
+                Some(option) => config.options.push(option),
+                None => config.rejected.push(reject(RejectReason::InvalidSyntax)),
+            }
+        }
+        config
+    }
+}
+
+fn is_allowed_value(name: &str, value: &str) -> bool {
+    !ALLOWED_VALUES
+        .iter()
+        .any(|(option, values)| *option == name && !values.contains(&value))
+}
+
+fn parse_option(line: &str) -> Option<(String, String)> {
+    let line = line.strip_prefix("--").unwrap_or(line);
+    let (name, value) = match line.split_once('=') {
+        Some((name, value)) => (name.trim(), Some(parse_value(value.trim())?)),
+        None => (strip_comment(line), None),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return None;
+    }
+    Some(match value {
+        Some(value) => (name.to_string(), value),
+        None => match name.strip_prefix("no-") {
+            Some(name) if ALLOWED_OPTIONS.contains(&name) => (name.to_string(), "no".to_string()),
+            _ => (name.to_string(), "yes".to_string()),
+        },
+    })
+}
+
+fn parse_value(value: &str) -> Option<String> {
+    for quote in ['"', '\''] {
+        if let Some(quoted) = value.strip_prefix(quote) {
+            let (value, rest) = quoted.split_once(quote)?;
+            return is_comment(rest).then(|| value.to_string());
+        }
+    }
+    if let Some(prefixed) = value.strip_prefix('%') {
+        let (length, rest) = prefixed.split_once('%')?;
+        let length = length.parse().ok()?;
+        let value = rest.get(..length)?;
+        return is_comment(&rest[length..]).then(|| value.to_string());
+    }
+    Some(strip_comment(value).to_string())
+}
+
+fn strip_comment(text: &str) -> &str {
+    text.split('#').next().unwrap_or_default().trim()
+}
+
+fn is_comment(text: &str) -> bool {
+    let text = text.trim();
+    text.is_empty() || text.starts_with('#')
+}
@@ -0,0 +1,84 @@
+use crate::stremio_app::stremio_player::mpv_config::{MpvConfig, RejectReason, RejectedLine};
+use std::path::Path;
+
+fn options(config: &MpvConfig) -> Vec<(&str, &str)> {
+    config
+        .options
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect()
+}
+
+#[test]
+fn mpv_config_options() {
+    let config = MpvConfig::parse(
+        r#"
+# Scaling
+scale=ewa_lanczossharp
+--dscale = mitchell   # trailing comment
+deband
+no-correct-downscaling
+demuxer-max-bytes=512MiB
+alang="jpn,jp # not a comment"
+sub-font='Noto Sans'
+slang=%7%eng,eng# comment
+"#,
+    );
+    assert_eq!(
+        options(&config),
+        vec![
+            ("scale", "ewa_lanczossharp"),
+            ("dscale", "mitchell"),
+            ("deband", "yes"),
+            ("correct-downscaling", "no"),
+            ("demuxer-max-bytes", "512MiB"),
+            ("alang", "jpn,jp # not a comment"),
+            ("sub-font", "Noto Sans"),
+            ("slang", "eng,eng"),
+        ]
+    );
+    assert!(config.rejected.is_empty(), "{:?}", config.rejected);
+}
+
+#[test]
+fn mpv_config_rejected() {
+    let config = MpvConfig::parse(
+        "hwdec=d3d11va\nwid=0\n--input-ipc-server=\\\\.\\pipe\\mpv\nscripts=evil.lua\nload-scripts\n\
+         sub-font=\"unterminated\n=yes\n[fast]\nscale=bilinear\n[default]\ncache=yes\n\
+         vo=image\nvo=gpu-next\nao=pcm\n",
+    );
+    assert_eq!(
+        options(&config),
+        vec![("hwdec", "d3d11va"), ("cache", "yes"), ("vo", "gpu-next")]
+    );
+    let rejected = |line: usize, text: &str, reason| RejectedLine {
+        line,
+        text: text.to_string(),
+        reason,
+    };
+    assert_eq!(
+        config.rejected,
+        vec![
+            rejected(2, "wid=0", RejectReason::NotAllowed),
+            rejected(
+                3,
+                "--input-ipc-server=\\\\.\\pipe\\mpv",
+                RejectReason::NotAllowed
+            ),
+            rejected(4, "scripts=evil.lua", RejectReason::NotAllowed),
+            rejected(5, "load-scripts", RejectReason::NotAllowed),
+            rejected(6, "sub-font=\"unterminated", RejectReason::InvalidSyntax),
+            rejected(7, "=yes", RejectReason::InvalidSyntax),
+            rejected(8, "[fast]", RejectReason::Profile),
+            rejected(9, "scale=bilinear", RejectReason::Profile),
+            rejected(12, "vo=image", RejectReason::ValueNotAllowed),
+            rejected(14, "ao=pcm", RejectReason::ValueNotAllowed),
+        ]
+    );
+}
+
+#[test]
+fn mpv_config_missing_file() {
+    let config = MpvConfig::load(Path::new("this/file/does/not/exist/mpv.conf"));
+    assert_eq!(config.ok(), Some(MpvConfig::default()));
+}
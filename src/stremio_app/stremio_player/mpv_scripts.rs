@@ -0,0 +1,51 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+// mpv separates the paths of list options with ";" on Windows
+#[cfg(windows)]
+const PATH_LIST_SEPARATOR: char = ';';
+#[cfg(not(windows))]
+const PATH_LIST_SEPARATOR: char = ':';
+
+// The mpv scripts in `dir`, sorted by name: Lua and JavaScript files, and
+// folders with a `main.lua` or `main.js`. Empty if `dir` doesn't exist.
+pub fn find_scripts(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+    let mut scripts: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| {
+            if path.is_dir() {
+                path.join("main.lua").is_file() || path.join("main.js").is_file()
+            } else {
+                let extension = path.extension().and_then(|extension| extension.to_str());
+                matches!(extension, Some("lua" | "js"))
+            }
+        })
+        .collect();
+    scripts.sort();
+    Ok(scripts)
+}
+
+// The value of mpv's `scripts` option. Paths which contain the list
+// separator can't be passed and are left out.
+pub fn scripts_option(scripts: &[PathBuf]) -> String {
+    scripts
+        .iter()
+        .map(|script| script.to_string_lossy())
+        .filter(|script| {
+            let valid = !script.contains(PATH_LIST_SEPARATOR);
+            if !valid {
+                eprintln!("cannot load the mpv script {script}");
+            }
+            valid
+        })
+        .collect::<Vec<_>>()
+        .join(&PATH_LIST_SEPARATOR.to_string())
+}
@@ -0,0 +1,48 @@
+use crate::stremio_app::stremio_player::mpv_scripts::{find_scripts, scripts_option};
+use std::{env, fs, process};
+
+#[test]
+fn mpv_scripts_find() {
+    let dir = env::temp_dir().join(format!("stremio-mpv-scripts-{}", process::id()));
+    fs::create_dir_all(dir.join("folder-script")).unwrap();
+    fs::create_dir_all(dir.join("assets")).unwrap();
+    fs::write(dir.join("folder-script").join("main.js"), "").unwrap();
+    for name in [
+        "skip-silence.lua",
+        "osd.js",
+        "readme.txt",
+        "assets/icon.lua",
+    ] {
+        fs::write(dir.join(name), "").unwrap();
+    }
+    assert_eq!(
+        find_scripts(&dir).unwrap(),
+        vec![
+            dir.join("folder-script"),
+            dir.join("osd.js"),
+            dir.join("skip-silence.lua"),
+        ]
+    );
+    assert!(find_scripts(&dir.join("missing")).unwrap().is_empty());
+    fs::remove_dir_all(&dir).ok();
+}
+
+#[test]
+fn mpv_scripts_option() {
+    let separator = if cfg!(windows) { ";" } else { ":" };
+    let dir = env::temp_dir();
+    let scripts = [
+        dir.join("a.lua"),
+        dir.join(format!("b{separator}c.lua")),
+        dir.join("d.js"),
+    ];
+    assert_eq!(
+        scripts_option(&scripts),
+        format!(
+            "{}{separator}{}",
+            dir.join("a.lua").display(),
+            dir.join("d.js").display()
+        )
+    );
+    assert_eq!(scripts_option(&[]), "");
+}
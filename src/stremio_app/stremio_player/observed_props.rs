@@ -0,0 +1,54 @@
+use libmpv2::Format;
+use std::collections::HashMap;
+
+// Reply ids of the properties the player observes for its own use. The ids
+// of the properties observed by the web UI start from 1.
+pub const IDLE_ACTIVE_ID: u64 = u64::MAX;
+pub const RESUME_PROPS_ID: u64 = u64::MAX - 1;
+pub const HWDEC_CURRENT_ID: u64 = u64::MAX - 2;
+
+// The properties kept in the resume store, all observed with `RESUME_PROPS_ID`
+pub const RESUME_PROPS: [(&str, Format); 4] = [
+    ("time-pos", Format::Double),
+    ("aid", Format::String),
+    ("sid", Format::String),
+    ("sub-delay", Format::Double),
+];
+
+// Keeps track of the properties observed by the web UI, so each one is
+// observed only once, can be unobserved by name and observed again when mpv
+// is recreated.
+#[derive(Default)]
+pub struct ObservedProps {
+    props: HashMap<String, (u64, Format)>,
+    last_id: u64,
+}
+
+impl ObservedProps {
+    // Returns the reply id to observe the property with, or `None` if it is
+    // already observed.
+    pub fn observe(&mut self, name: &str, format: Format) -> Option<u64> {
+        if self.props.contains_key(name) {
+            return None;
+        }
+        self.last_id += 1;
+        self.props.insert(name.to_string(), (self.last_id, format));
+        Some(self.last_id)
+    }
+    // Returns the reply id the property was observed with, or `None` if it
+    // is not observed.
+    pub fn unobserve(&mut self, name: &str) -> Option<u64> {
+        self.props.remove(name).map(|(id, _)| id)
+    }
+    // Changes with the id of an unobserved property may still be queued
+    pub fn is_observed_id(&self, id: u64) -> bool {
+        self.props
+            .values()
+            .any(|(observed_id, _)| *observed_id == id)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u64, Format)> {
+        self.props
+            .iter()
+            .map(|(name, (id, format))| (name.as_str(), *id, *format))
+    }
+}
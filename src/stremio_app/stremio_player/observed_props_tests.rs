@@ -0,0 +1,61 @@
+use crate::stremio_app::stremio_player::observed_props::{ObservedProps, IDLE_ACTIVE_ID};
+use libmpv2::Format;
+
+#[test]
+fn observe_unique_ids() {
+    let mut props = ObservedProps::default();
+    let pause = props
+        .observe("pause", Format::Flag)
+        .expect("new observation");
+    let time_pos = props
+        .observe("time-pos", Format::Double)
+        .expect("new observation");
+    assert_ne!(pause, 0);
+    assert_ne!(pause, time_pos);
+    assert_ne!(pause, IDLE_ACTIVE_ID);
+    assert_ne!(time_pos, IDLE_ACTIVE_ID);
+}
+
+#[test]
+fn observe_dedupe() {
+    let mut props = ObservedProps::default();
+    let pause = props
+        .observe("pause", Format::Flag)
+        .expect("new observation");
+    assert_eq!(props.observe("pause", Format::Flag), None);
+    assert!(props.is_observed_id(pause));
+}
+
+#[test]
+fn unobserve() {
+    let mut props = ObservedProps::default();
+    let first = props.observe("pause", Format::Flag);
+    assert_eq!(props.unobserve("pause"), first);
+    assert_eq!(props.unobserve("pause"), None);
+    assert!(!props.is_observed_id(first.unwrap()));
+    assert_eq!(props.unobserve("time-pos"), None);
+
+    // Observing again after unobserving needs a new id, mpv may still have
+    // queued changes with the old one
+    let second = props.observe("pause", Format::Flag);
+    assert!(second.is_some());
+    assert_ne!(first, second);
+    assert!(props.is_observed_id(second.unwrap()));
+}
+
+#[test]
+fn observed_formats() {
+    let mut props = ObservedProps::default();
+    let pause = props
+        .observe("pause", Format::Flag)
+        .expect("new observation");
+    let time_pos = props
+        .observe("time-pos", Format::Double)
+        .expect("new observation");
+    props.unobserve("pause");
+    let observed: Vec<_> = props.iter().collect();
+    assert!(matches!(
+        observed.as_slice(),
+        [("time-pos", id, Format::Double)] if *id == time_pos && *id != pause
+    ));
+}
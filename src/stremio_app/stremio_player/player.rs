@@ -1,27 +1,67 @@
+use crate::stremio_app::config::config_dir;
+use crate::stremio_app::constants::{
+    FFMPEG_EXE, MPV_CONFIG_FILE, MPV_SCRIPTS_DIR, PLAYER_LOG_FILE, PLAYER_LOG_LEVEL,
+    PLAYER_LOG_LEVEL_ENV, PLAYER_LOG_SIZE, PLAYER_PROP_THROTTLE, PLAYER_RESUME_AFTER_RESTART,
+    PLAYER_SCRIPTS_DIR_ENV, PLAYER_SETTINGS_FILE, PLAYER_SHUTDOWN_TIMEOUT, RESUME_FILE,
+    SCREENSHOT_DIR, THUMBNAIL_DIR, THUMBNAIL_DISK_BUDGET, THUMBNAIL_INTERVAL, THUMBNAIL_WIDTH,
+};
 use crate::stremio_app::ipc;
+use crate::stremio_app::media_probe::bin_path;
 use crate::stremio_app::RPCResponse;
-use flume::{Receiver, Sender};
-use libmpv2::{events::Event, events::EventContext, Format, Mpv, SetData};
+use flume::{Receiver, RecvTimeoutError, Sender};
+use libmpv2::{events::EventContext, Mpv};
 use native_windows_gui::{self as nwg, PartialUi};
 use std::{
-    sync::Arc,
+    cell::RefCell,
+    env,
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 use winapi::shared::windef::HWND;
 
 use crate::stremio_app::stremio_player::{
-    CmdVal, InMsg, InMsgArgs, InMsgFn, PlayerEnded, PlayerEvent, PlayerProprChange, PlayerResponse,
-    PropKey, PropVal,
+    backend::request_log_messages,
+    lifecycle::PlayerThreads,
+    mpv_config::MpvConfig,
+    mpv_scripts::{find_scripts, scripts_option},
+    player_log::{LogEntry, PlayerLog},
+    player_settings::PlayerSettings,
+    resume_store::ResumeStore,
+    screenshot,
+    supervisor::{run_supervisor, PlayerStores},
+    throttle::PropThrottle,
+    thumbnails::{ThumbnailOptions, Thumbnails},
+    PlayerCapabilities, PlayerEvent, PlayerResponse,
 };
 
-struct ObserveProperty {
-    name: String,
-    format: Format,
-}
-
 #[derive(Default)]
 pub struct Player {
     pub channel: ipc::Channel,
+    // Set by the handshake with the web UI
+    pub capabilities: Arc<Mutex<PlayerCapabilities>>,
+    threads: RefCell<Option<PlayerThreads>>,
+}
+
+impl Player {
+    // Stops the player threads and mpv and removes the temp screenshots.
+    // Does nothing if the player has already been shut down.
+    pub fn shutdown(&self) {
+        let threads = self.threads.borrow_mut().take();
+        if let Some(threads) = threads {
+            if !threads.shutdown(Duration::from_millis(PLAYER_SHUTDOWN_TIMEOUT)) {
+                eprintln!("MPV did not shut down in time");
+            }
+            screenshot::cleanup(&screenshot::temp_dir(), Duration::ZERO);
+        }
+    }
+}
+
+impl Drop for Player {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
 }
 
 impl PartialUi for Player {
@@ -40,24 +80,51 @@ impl PartialUi for Player {
 
         let (in_msg_sender, in_msg_receiver) = flume::unbounded();
         let (rpc_response_sender, rpc_response_receiver) = flume::unbounded();
-        let (observe_property_sender, observe_property_receiver) = flume::unbounded();
+        let (player_event_sender, player_event_receiver) = flume::unbounded();
+        let (stop_sender, stop_receiver) = flume::bounded(0);
         data.channel = ipc::Channel::new(Some((in_msg_sender, rpc_response_receiver)));
 
-        let mpv = create_shareable_mpv(window_handle);
-
-        let _event_thread = create_event_thread(
-            Arc::clone(&mpv),
-            observe_property_receiver,
+        let throttle_thread = create_throttle_thread(
+            data.capabilities.clone(),
+            player_event_receiver,
             rpc_response_sender,
         );
-        let _message_thread = create_message_thread(mpv, observe_property_sender, in_msg_receiver);
-        // @TODO implement a mechanism to stop threads on `Player` drop if needed
+        let supervisor_thread = create_supervisor_thread(
+            window_handle,
+            in_msg_receiver,
+            stop_receiver,
+            player_event_sender,
+        );
+        *data.threads.get_mut() = Some(PlayerThreads::new(
+            stop_sender,
+            vec![
+                ("supervisor", supervisor_thread),
+                ("throttle", throttle_thread),
+            ],
+        ));
 
         Ok(())
     }
 }
 
-fn create_shareable_mpv(window_handle: HWND) -> Arc<Mpv> {
+fn create_shareable_mpv(
+    window_handle: HWND,
+    mpv_config: &MpvConfig,
+    player_log: &Mutex<PlayerLog>,
+) -> Arc<Mpv> {
+    let config_dir = config_dir();
+    let scripts_dir = env::var_os(PLAYER_SCRIPTS_DIR_ENV)
+        .map(PathBuf::from)
+        .or_else(|| config_dir.as_ref().map(|dir| dir.join(MPV_SCRIPTS_DIR)));
+    let scripts = scripts_dir
+        .map(|dir| {
+            find_scripts(&dir).unwrap_or_else(|error| {
+                eprintln!("cannot read the mpv scripts in {}: {error}", dir.display());
+                Vec::new()
+            })
+        })
+        .unwrap_or_default();
+
     let mpv = Mpv::with_initializer(|initializer| {
         macro_rules! set_property {
             ($name:literal, $value:expr) => {
@@ -76,178 +143,158 @@ fn create_shareable_mpv(window_handle: HWND) -> Arc<Mpv> {
         set_property!("msg-level", "all=no");
         set_property!("quiet", "yes");
         set_property!("hwdec", "auto");
+        // The user's options are applied last, so they override the defaults
+        for (name, value) in &mpv_config.options {
+            if let Err(error) = initializer.set_property(name, value.as_str()) {
+                let text = format!("cannot set {name}={value}: '{error:#}'");
+                eprintln!("{MPV_CONFIG_FILE} {text}");
+                player_log
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(LogEntry::new("warn", MPV_CONFIG_FILE, &text));
+            }
+        }
+        if !scripts.is_empty() {
+            set_property!("scripts", scripts_option(&scripts).as_str());
+        }
         Ok(())
     });
     Arc::new(mpv.expect("cannot build MPV"))
 }
 
-fn create_event_thread(
-    mpv: Arc<Mpv>,
-    observe_property_receiver: Receiver<ObserveProperty>,
+fn create_throttle_thread(
+    capabilities: Arc<Mutex<PlayerCapabilities>>,
+    player_event_receiver: Receiver<PlayerEvent>,
     rpc_response_sender: Sender<String>,
 ) -> JoinHandle<()> {
     thread::spawn(move || {
-        let mut event_context = EventContext::new(mpv.ctx);
-        event_context
-            .disable_deprecated_events()
-            .expect("failed to disable deprecated MPV events");
-
-        // -- Event handler loop --
+        let mut throttle = PropThrottle::new(
+            PLAYER_PROP_THROTTLE
+                .iter()
+                .map(|(name, interval)| (*name, Duration::from_millis(*interval))),
+        );
+        let send = |player_event: PlayerEvent| {
+            let capabilities = *capabilities.lock().unwrap_or_else(PoisonError::into_inner);
+            let player_response = PlayerResponse::from(player_event.for_capabilities(capabilities));
+            rpc_response_sender
+                .send(RPCResponse::response_message(player_response.to_value()))
+                .expect("failed to send RPCResponse");
+        };
 
         loop {
-            for ObserveProperty { name, format } in observe_property_receiver.drain() {
-                event_context
-                    .observe_property(&name, format, 0)
-                    .expect("failed to observer MPV property");
-            }
-
-            // -1.0 means to block and wait for an event.
-            let event = match event_context.wait_event(-1.) {
-                Some(Ok(event)) => event,
-                Some(Err(error)) => {
-                    eprintln!("Event errored: {error:?}");
-                    continue;
-                }
-                // dummy event received (may be created on a wake up call or on timeout)
-                None => continue,
+            let player_event = match throttle.next_due() {
+                Some(deadline) => player_event_receiver.recv_deadline(deadline),
+                None => player_event_receiver
+                    .recv()
+                    .map_err(|_| RecvTimeoutError::Disconnected),
             };
-
-            // even if you don't do anything with the events, it is still necessary to empty the event loop
-            let player_response = match event {
-                Event::PropertyChange { name, change, .. } => PlayerResponse(
-                    "mpv-prop-change",
-                    PlayerEvent::PropChange(PlayerProprChange::from_name_value(
-                        name.to_string(),
-                        change,
-                    )),
-                ),
-                Event::EndFile(reason) => PlayerResponse(
-                    "mpv-event-ended",
-                    PlayerEvent::End(PlayerEnded::from_end_reason(reason)),
-                ),
-                Event::Shutdown => {
+            match player_event {
+                Ok(player_event) => throttle
+                    .push(player_event, Instant::now())
+                    .into_iter()
+                    .for_each(send),
+                Err(RecvTimeoutError::Timeout) => throttle
+                    .flush_due(Instant::now())
+                    .into_iter()
+                    .for_each(send),
+                Err(RecvTimeoutError::Disconnected) => {
+                    throttle.flush_all().into_iter().for_each(send);
                     break;
                 }
-                _ => continue,
-            };
-
-            rpc_response_sender
-                .send(RPCResponse::response_message(player_response.to_value()))
-                .expect("failed to send RPCResponse");
+            }
         }
     })
 }
 
-fn create_message_thread(
-    mpv: Arc<Mpv>,
-    observe_property_sender: Sender<ObserveProperty>,
+fn create_supervisor_thread(
+    window_handle: HWND,
     in_msg_receiver: Receiver<String>,
+    stop_receiver: Receiver<()>,
+    player_event_sender: Sender<PlayerEvent>,
 ) -> JoinHandle<()> {
+    // Window handles can't be sent between threads, but the window outlives the player
+    let window_handle = window_handle as isize;
+    let log_level = env::var(PLAYER_LOG_LEVEL_ENV).unwrap_or_else(|_| PLAYER_LOG_LEVEL.to_string());
     thread::spawn(move || {
-        // -- Helpers --
-
-        let observe_property = |name: String, format: Format| {
-            observe_property_sender
-                .send(ObserveProperty { name, format })
-                .expect("cannot send ObserveProperty");
-            mpv.wake_up();
-        };
-
-        let send_command = |cmd: CmdVal| {
-            let a1;
-            let a2;
-            let a3;
-            let a4;
-            let (name, args) = match cmd {
-                CmdVal::Quintuple(name, arg1, arg2, arg3, arg4) => {
-                    a1 = format!(r#""{arg1}""#);
-                    a2 = format!(r#""{arg2}""#);
-                    a3 = format!(r#""{arg3}""#);
-                    a4 = format!(r#""{arg4}""#);
-                    (
-                        name,
-                        vec![a1.as_ref(), a2.as_ref(), a3.as_ref(), a4.as_ref()],
-                    )
-                }
-                CmdVal::Quadruple(name, arg1, arg2, arg3) => {
-                    a1 = format!(r#""{arg1}""#);
-                    a2 = format!(r#""{arg2}""#);
-                    a3 = format!(r#""{arg3}""#);
-                    (name, vec![a1.as_ref(), a2.as_ref(), a3.as_ref()])
-                }
-                CmdVal::Tripple(name, arg1, arg2) => {
-                    a1 = format!(r#""{arg1}""#);
-                    a2 = format!(r#""{arg2}""#);
-                    (name, vec![a1.as_ref(), a2.as_ref()])
-                }
-                CmdVal::Double(name, arg1) => {
-                    a1 = format!(r#""{arg1}""#);
-                    (name, vec![a1.as_ref()])
-                }
-                CmdVal::Single((name,)) => (name, vec![]),
-            };
-            if let Err(error) = mpv.command(&name.to_string(), &args) {
-                eprintln!("failed to execute MPV command: '{error:#}'")
+        let player_log = PlayerLog::new(PLAYER_LOG_SIZE);
+        let player_log = Arc::new(Mutex::new(match config_dir() {
+            Some(dir) => player_log.with_export_file(dir.join(PLAYER_LOG_FILE)),
+            None => player_log,
+        }));
+        let mpv_config = load_mpv_config(&player_log);
+        let mpv_log = Arc::clone(&player_log);
+        let create_mpv = move || {
+            let mpv = create_shareable_mpv(window_handle as HWND, &mpv_config, &mpv_log);
+            if let Err(error) = request_log_messages(&mpv, &log_level) {
+                eprintln!("cannot request MPV log messages: '{error:#}'");
             }
+            let event_context = EventContext::new(mpv.ctx);
+            event_context
+                .disable_deprecated_events()
+                .expect("failed to disable deprecated MPV events");
+            (mpv, event_context)
         };
-
-        fn set_property(name: impl ToString, value: impl SetData, mpv: &Mpv) {
-            if let Err(error) = mpv.set_property(&name.to_string(), value) {
-                eprintln!("cannot set MPV property: '{error:#}'")
-            }
-        }
-
-        // -- InMsg handler loop --
-
-        for msg in in_msg_receiver.iter() {
-            let in_msg: InMsg = match serde_json::from_str(&msg) {
-                Ok(in_msg) => in_msg,
-                Err(error) => {
-                    eprintln!("cannot parse InMsg:{:?} {error:#}", &msg);
-                    continue;
-                }
-            };
-
-            match in_msg {
-                InMsg(InMsgFn::MpvObserveProp, InMsgArgs::ObProp(PropKey::Bool(prop))) => {
-                    observe_property(prop.to_string(), Format::Flag);
-                }
-                InMsg(InMsgFn::MpvObserveProp, InMsgArgs::ObProp(PropKey::Int(prop))) => {
-                    observe_property(prop.to_string(), Format::Int64);
-                }
-                InMsg(InMsgFn::MpvObserveProp, InMsgArgs::ObProp(PropKey::Fp(prop))) => {
-                    observe_property(prop.to_string(), Format::Double);
-                }
-                InMsg(InMsgFn::MpvObserveProp, InMsgArgs::ObProp(PropKey::Str(prop))) => {
-                    observe_property(prop.to_string(), Format::String);
-                }
-                InMsg(InMsgFn::MpvSetProp, InMsgArgs::StProp(name, PropVal::Bool(value))) => {
-                    set_property(name, value, &mpv);
-                }
-                InMsg(InMsgFn::MpvSetProp, InMsgArgs::StProp(name, PropVal::Num(value))) => {
-                    set_property(name, value, &mpv);
-                }
-                InMsg(InMsgFn::MpvSetProp, InMsgArgs::StProp(name, PropVal::Str(value))) => {
-                    set_property(name, value, &mpv);
-                }
-                InMsg(InMsgFn::MpvCommand, InMsgArgs::Cmd(cmd)) => {
-                    send_command(cmd);
-                }
-                msg => {
-                    eprintln!("MPV unsupported message: '{msg:?}'");
-                }
-            }
-        }
+        let resume_store = config_dir()
+            .map(|dir| ResumeStore::open(dir.join(RESUME_FILE)))
+            .unwrap_or_default();
+        let settings = config_dir()
+            .map(|dir| PlayerSettings::open(dir.join(PLAYER_SETTINGS_FILE)))
+            .unwrap_or_default();
+        let stores = PlayerStores {
+            resume_store: Arc::new(Mutex::new(resume_store)),
+            player_log,
+            settings: Arc::new(Mutex::new(settings)),
+            thumbnails: Arc::new(create_thumbnails()),
+            sources: Arc::default(),
+            loadfile_options: Arc::default(),
+            screenshot_dir: config_dir().map(|dir| dir.join(SCREENSHOT_DIR)),
+        };
+        run_supervisor(
+            create_mpv,
+            PLAYER_RESUME_AFTER_RESTART,
+            stores,
+            in_msg_receiver,
+            stop_receiver,
+            player_event_sender,
+        );
     })
 }
 
-trait MpvExt {
-    fn wake_up(&self);
+// The rejected lines are added to the player log, so the web UI can show why
+// an option had no effect
+fn load_mpv_config(player_log: &Mutex<PlayerLog>) -> MpvConfig {
+    let mut player_log = player_log.lock().unwrap_or_else(PoisonError::into_inner);
+    let mut warn = |text: String| {
+        eprintln!("{MPV_CONFIG_FILE} {text}");
+        player_log.push(LogEntry::new("warn", MPV_CONFIG_FILE, &text));
+    };
+    let mpv_config = config_dir()
+        .map(|dir| MpvConfig::load(&dir.join(MPV_CONFIG_FILE)))
+        .transpose()
+        .unwrap_or_else(|error| {
+            warn(format!("cannot be read: {error}"));
+            None
+        })
+        .unwrap_or_default();
+    for rejected in &mpv_config.rejected {
+        warn(rejected.to_string());
+    }
+    mpv_config
 }
 
-impl MpvExt for Mpv {
-    // @TODO create a PR to the `libmpv` crate and then remove `libmpv-sys` from Cargo.toml?
-    fn wake_up(&self) {
-        unsafe { libmpv2_sys::mpv_wakeup(self.ctx.as_ptr()) }
+// Thumbnails are not extracted if ffmpeg is not installed next to the shell
+fn create_thumbnails() -> Thumbnails {
+    match bin_path(FFMPEG_EXE) {
+        Ok(ffmpeg) if ffmpeg.is_file() => Thumbnails::new(ThumbnailOptions {
+            ffmpeg,
+            cache_dir: env::temp_dir().join(THUMBNAIL_DIR),
+            interval: THUMBNAIL_INTERVAL,
+            width: THUMBNAIL_WIDTH,
+            disk_budget: THUMBNAIL_DISK_BUDGET,
+        }),
+        _ => {
+            eprintln!("{FFMPEG_EXE} not found, thumbnails are disabled");
+            Thumbnails::default()
+        }
     }
 }
@@ -0,0 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// A log message from mpv
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    // Milliseconds since the Unix epoch
+    pub time: u64,
+    pub level: String,
+    pub prefix: String,
+    pub text: String,
+}
+impl LogEntry {
+    pub fn new(level: &str, prefix: &str, text: &str) -> Self {
+        Self {
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_millis() as u64),
+            level: level.to_string(),
+            prefix: prefix.to_string(),
+            // mpv ends every message with a new line
+            text: text.trim_end().to_string(),
+        }
+    }
+}
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "[{}.{:03}][{}][{}] {}",
+            self.time / 1000,
+            self.time % 1000,
+            self.level,
+            self.prefix,
+            self.text
+        )
+    }
+}
+
+// The last `capacity` log messages from mpv, kept across mpv restarts
+#[derive(Debug)]
+pub struct PlayerLog {
+    entries: VecDeque<LogEntry>,
+    capacity: usize,
+    // Where "player-export-logs" writes to, the web UI can't pick the file
+    export_file: Option<PathBuf>,
+}
+
+impl PlayerLog {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+            export_file: None,
+        }
+    }
+    pub fn with_export_file(mut self, file: PathBuf) -> Self {
+        self.export_file = Some(file);
+        self
+    }
+    pub fn export_file(&self) -> Option<&Path> {
+        self.export_file.as_deref()
+    }
+    pub fn push(&mut self, entry: LogEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+    // Oldest first
+    pub fn entries(&self) -> impl Iterator<Item = &LogEntry> {
+        self.entries.iter()
+    }
+    // Writes the `header` lines followed by the log messages, one per line
+    pub fn export(&self, path: &Path, header: &[String]) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = io::BufWriter::new(fs::File::create(path)?);
+        for line in header {
+            writeln!(file, "{line}")?;
+        }
+        writeln!(file)?;
+        for entry in &self.entries {
+            writeln!(file, "{entry}")?;
+        }
+        file.flush()
+    }
+}
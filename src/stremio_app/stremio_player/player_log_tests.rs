@@ -0,0 +1,46 @@
+use crate::stremio_app::stremio_player::player_log::{LogEntry, PlayerLog};
+use std::{env, fs, process};
+
+fn texts(player_log: &PlayerLog) -> Vec<&str> {
+    player_log
+        .entries()
+        .map(|entry| entry.text.as_str())
+        .collect()
+}
+
+#[test]
+fn player_log_ring_buffer() {
+    let mut player_log = PlayerLog::new(3);
+    for text in ["a\n", "b\n", "c\n", "d\n"] {
+        player_log.push(LogEntry::new("info", "cplayer", text));
+    }
+    // The oldest message is dropped, the new lines are trimmed
+    assert_eq!(texts(&player_log), vec!["b", "c", "d"]);
+
+    let mut player_log = PlayerLog::new(0);
+    player_log.push(LogEntry::new("info", "cplayer", "a"));
+    assert!(texts(&player_log).is_empty());
+}
+
+#[test]
+fn player_log_export() {
+    let mut player_log = PlayerLog::new(10);
+    player_log.push(LogEntry {
+        time: 1_700_000_000_005,
+        level: "warn".to_string(),
+        prefix: "ao/wasapi".to_string(),
+        text: "device lost".to_string(),
+    });
+    let path = env::temp_dir()
+        .join(format!("stremio-player-log-{}", process::id()))
+        .join("player-log.txt");
+    player_log
+        .export(&path, &["stremio-shell-ng 5.0.5".to_string()])
+        .expect("cannot export the player log");
+    let exported = fs::read_to_string(&path).unwrap();
+    fs::remove_dir_all(path.parent().unwrap()).ok();
+    assert_eq!(
+        exported,
+        "stremio-shell-ng 5.0.5\n\n[1700000000.005][warn][ao/wasapi] device lost\n"
+    );
+}
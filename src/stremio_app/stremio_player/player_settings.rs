@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use std::{io, path::PathBuf};
+
+use crate::stremio_app::{constants::HWDEC_FALLBACK_MAX_AGE, stremio_player::json_file};
+
+// Player settings picked in the web UI which mpv doesn't keep between runs,
+// saved in a JSON file. Settings without a file are only kept in memory.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct PlayerSettings {
+    #[serde(skip)]
+    file: Option<PathBuf>,
+    // The mpv `audio-device`, `None` for "auto"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub audio_device: Option<String>,
+    // The codecs which failed with hardware decoding, e.g. "hevc"
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub software_decoding: Vec<SoftwareDecoding>,
+}
+
+// A codec is decoded by software until `HWDEC_FALLBACK_MAX_AGE` passes
+// since it failed with hardware decoding
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SoftwareDecoding {
+    pub codec: String,
+    // Unix time in seconds
+    pub failed_at: u64,
+}
+
+impl PlayerSettings {
+    // Starts with the defaults if the file is missing or can't be read
+    pub fn open(file: PathBuf) -> Self {
+        let settings: Self = json_file::load(&file, "player settings");
+        Self {
+            file: Some(file),
+            ..settings
+        }
+    }
+    // The codecs which failed with hardware decoding and did not expire
+    pub fn software_codecs(&self, now: u64) -> Vec<String> {
+        self.software_decoding
+            .iter()
+            .filter(|entry| now.saturating_sub(entry.failed_at) <= HWDEC_FALLBACK_MAX_AGE)
+            .map(|entry| entry.codec.clone())
+            .collect()
+    }
+    // Remembers a failure of the codec, the expired ones are dropped
+    pub fn add_software_codec(&mut self, codec: &str, now: u64) {
+        self.software_decoding.retain(|entry| {
+            entry.codec != codec && now.saturating_sub(entry.failed_at) <= HWDEC_FALLBACK_MAX_AGE
+        });
+        self.software_decoding.push(SoftwareDecoding {
+            codec: codec.to_string(),
+            failed_at: now,
+        });
+    }
+    pub fn save(&self) -> io::Result<()> {
+        match &self.file {
+            Some(file) => json_file::save(file, self),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,47 @@
+use crate::stremio_app::{
+    constants::HWDEC_FALLBACK_MAX_AGE, stremio_player::player_settings::PlayerSettings,
+};
+use std::{env, fs, process};
+
+#[test]
+fn player_settings_save() {
+    let file = env::temp_dir()
+        .join(format!("stremio-player-settings-{}", process::id()))
+        .join("player-settings.json");
+    let mut settings = PlayerSettings::open(file.clone());
+    assert_eq!(settings.audio_device, None);
+    settings.audio_device = Some("wasapi/{0.0.0.00000000}.{hdmi}".to_string());
+    settings.save().expect("cannot save the player settings");
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        r#"{"audioDevice":"wasapi/{0.0.0.00000000}.{hdmi}"}"#
+    );
+    assert_eq!(PlayerSettings::open(file.clone()), settings);
+
+    fs::write(&file, "not json").unwrap();
+    assert_eq!(PlayerSettings::open(file.clone()).audio_device, None);
+    fs::remove_dir_all(file.parent().unwrap()).ok();
+}
+
+#[test]
+fn player_settings_software_codecs() {
+    let mut settings = PlayerSettings::default();
+    settings.add_software_codec("hevc", 1000);
+    settings.add_software_codec("av1", 2000);
+    assert_eq!(settings.software_codecs(2000), ["hevc", "av1"]);
+    // Expired
+    let now = 1000 + HWDEC_FALLBACK_MAX_AGE + 1;
+    assert_eq!(settings.software_codecs(now), ["av1"]);
+    // Failed again, once per codec
+    settings.add_software_codec("av1", now);
+    settings.add_software_codec("hevc", now);
+    assert_eq!(settings.software_codecs(now), ["av1", "hevc"]);
+    assert_eq!(settings.software_decoding.len(), 2);
+    assert_eq!(
+        serde_json::to_value(&settings).unwrap(),
+        serde_json::json!({"softwareDecoding": [
+            {"codec": "av1", "failedAt": now},
+            {"codec": "hevc", "failedAt": now},
+        ]})
+    );
+}
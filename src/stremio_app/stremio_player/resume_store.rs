@@ -0,0 +1,190 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    io,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use url::Url;
+
+use crate::stremio_app::stremio_player::json_file;
+
+// Where the playback of a file or URL stopped
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResumeEntry {
+    pub path: String,
+    pub time_pos: f64,
+    // Track ids as mpv reports them, e.g. "2" or "no"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aid: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    pub sub_delay: f64,
+    // Seconds since the Unix epoch
+    pub updated_at: u64,
+}
+
+// Resume positions keyed by `media_key`, kept in a JSON file. A store
+// without a file is only kept in memory.
+#[derive(Default, Debug)]
+pub struct ResumeStore {
+    file: Option<PathBuf>,
+    entries: HashMap<String, ResumeEntry>,
+}
+
+impl ResumeStore {
+    // Starts with an empty store if the file is missing or can't be read
+    pub fn open(file: PathBuf) -> Self {
+        let entries = json_file::load(&file, "resume store");
+        Self {
+            file: Some(file),
+            entries,
+        }
+    }
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn get(&self, path: &str) -> Option<&ResumeEntry> {
+        self.entries.get(&media_key(path))
+    }
+    // The path is saved without the credentials it may have
+    pub fn set(&mut self, mut entry: ResumeEntry) {
+        entry.path = strip_credentials(&entry.path);
+        self.entries.insert(media_key(&entry.path), entry);
+    }
+    pub fn remove(&mut self, path: &str) -> bool {
+        self.entries.remove(&media_key(path)).is_some()
+    }
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+    // Drops the entries not updated within `max_age` seconds, then the least
+    // recently updated ones above `max_entries`
+    pub fn prune(&mut self, now: u64, max_age: u64, max_entries: usize) {
+        self.entries
+            .retain(|_, entry| now.saturating_sub(entry.updated_at) <= max_age);
+        if self.entries.len() > max_entries {
+            let mut updated: Vec<_> = self
+                .entries
+                .iter()
+                .map(|(key, entry)| (entry.updated_at, key.clone()))
+                .collect();
+            updated.sort();
+            let excess = self.entries.len() - max_entries;
+            for (_, key) in updated.into_iter().take(excess) {
+                self.entries.remove(&key);
+            }
+        }
+    }
+    pub fn save(&self) -> io::Result<()> {
+        match &self.file {
+            Some(file) => json_file::save(file, &self.entries),
+            None => Ok(()),
+        }
+    }
+}
+
+// Query parameters with these in their names are taken as credentials, e.g.
+// "token", "api_key" or "X-Amz-Signature"
+const CREDENTIAL_PARAMS: [&str; 8] = [
+    "auth",
+    "credential",
+    "key",
+    "pass",
+    "secret",
+    "session",
+    "signature",
+    "token",
+];
+
+// `C:\video.mkv` parses as a URL with the scheme "c"
+fn parse_url(path: &str) -> Option<Url> {
+    let bytes = path.as_bytes();
+    let is_drive_path = bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':';
+    Url::parse(path).ok().filter(|_| !is_drive_path)
+}
+
+// Removes the user name, the password and the credential query parameters
+// of a URL. Local paths are returned as they are.
+pub fn strip_credentials(path: &str) -> String {
+    match parse_url(path) {
+        Some(mut url) if url.scheme() != "file" => {
+            remove_credentials(&mut url);
+            url.to_string()
+        }
+        _ => path.to_string(),
+    }
+}
+
+fn remove_credentials(url: &mut Url) {
+    // Fails only for URLs which can't have credentials
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    if url.query().is_none() {
+        return;
+    }
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .into_owned()
+        .filter(|(name, _)| {
+            let name = name.to_lowercase();
+            !CREDENTIAL_PARAMS.iter().any(|param| name.contains(param))
+        })
+        .collect();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+}
+
+// Identifies the same media given in different forms. Local paths are
+// compared case insensitively, as on Windows, with either kind of slashes or
+// as `file://` URLs. URLs are compared without the fragment, without
+// credentials and with sorted query parameters.
+pub fn media_key(path: &str) -> String {
+    match parse_url(path) {
+        Some(url) if url.scheme() == "file" => {
+            let path = urlencoding::decode(url.path())
+                .map(|path| path.into_owned())
+                .unwrap_or_else(|_| url.path().to_string());
+            // `file:///C:/video.mkv` has the path `/C:/video.mkv`
+            let path = match path.strip_prefix('/') {
+                Some(drive_path) if drive_path.as_bytes().get(1) == Some(&b':') => {
+                    drive_path.to_string()
+                }
+                _ => path,
+            };
+            local_media_key(&path)
+        }
+        Some(mut url) => {
+            url.set_fragment(None);
+            remove_credentials(&mut url);
+            let mut query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+            if query.is_empty() {
+                url.set_query(None);
+            } else {
+                query.sort();
+                url.query_pairs_mut().clear().extend_pairs(query);
+            }
+            url.to_string()
+        }
+        None => local_media_key(path),
+    }
+}
+
+fn local_media_key(path: &str) -> String {
+    format!("file:{}", path.replace('\\', "/").to_lowercase())
+}
+
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
@@ -0,0 +1,177 @@
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::Deserialize;
+use std::{
+    env, fs, io,
+    path::{Component, Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use crate::stremio_app::constants::{SCREENSHOT_TEMPLATE, SCREENSHOT_TEMP_DIR};
+
+// The options of "player-screenshot", all of them are optional
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScreenshotArgs {
+    // Whether the subtitles are drawn on the frame
+    pub subtitles: bool,
+    // The screenshot is kept in this subfolder of the screenshots folder
+    // instead of the temp one
+    pub dir: Option<PathBuf>,
+    // The file name without extension, see `file_name`
+    pub template: Option<String>,
+    // Whether to reply with the PNG data too
+    pub base64: bool,
+}
+impl Default for ScreenshotArgs {
+    fn default() -> Self {
+        Self {
+            subtitles: true,
+            dir: None,
+            template: None,
+            base64: false,
+        }
+    }
+}
+impl ScreenshotArgs {
+    pub fn mpv_flag(&self) -> &'static str {
+        if self.subtitles {
+            "subtitles"
+        } else {
+            "video"
+        }
+    }
+    pub fn template(&self) -> &str {
+        self.template.as_deref().unwrap_or(SCREENSHOT_TEMPLATE)
+    }
+    // Absolute paths and ".." are rejected, so the web UI can't write outside
+    // of the screenshots folder
+    pub fn check(&self) -> Result<(), String> {
+        match &self.dir {
+            Some(dir)
+                if dir.as_os_str().is_empty()
+                    || !dir
+                        .components()
+                        .all(|component| matches!(component, Component::Normal(_))) =>
+            {
+                Err("`dir` must be a subfolder of the screenshots folder".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+// The folder for screenshots the web UI didn't ask to keep
+pub fn temp_dir() -> PathBuf {
+    env::temp_dir().join(SCREENSHOT_TEMP_DIR)
+}
+
+// Fills in the template, which may contain "{title}" for the media title,
+// "{pos}" for the playback position as HH-MM-SS and "{date}" for the UTC
+// time as YYYYMMDD-HHMMSS. Characters Windows doesn't allow in file names
+// are replaced with "_".
+pub fn file_name(template: &str, title: &str, time_pos: f64, now: SystemTime) -> String {
+    let name = template
+        .replace("{title}", title)
+        .replace("{pos}", &format_time_pos(time_pos))
+        .replace("{date}", &format_date(now));
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    // Windows drops trailing dots and spaces
+    let name = name.trim().trim_end_matches('.');
+    if name.is_empty() {
+        "screenshot".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+// A PNG file named `name` in `dir` that doesn't exist yet
+pub fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(format!("{name}.png"));
+    if !path.exists() {
+        return path;
+    }
+    (2..)
+        .map(|n| dir.join(format!("{name}-{n}.png")))
+        .find(|path| !path.exists())
+        .expect("no free screenshot name")
+}
+
+pub fn encode_base64(path: &Path) -> io::Result<String> {
+    fs::read(path).map(|data| STANDARD.encode(data))
+}
+
+// Removes the PNG files in `dir` which were last modified more than
+// `max_age` ago. Errors are only logged.
+pub fn cleanup(dir: &Path, max_age: Duration) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return,
+        Err(error) => {
+            eprintln!("cannot read screenshot folder {}: {error}", dir.display());
+            return;
+        }
+    };
+    let now = SystemTime::now();
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.extension().and_then(|extension| extension.to_str()) != Some("png") {
+            continue;
+        }
+        let expired = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .is_ok_and(|modified| now.duration_since(modified).is_ok_and(|age| age >= max_age));
+        if expired {
+            if let Err(error) = fs::remove_file(&path) {
+                eprintln!("cannot remove screenshot {}: {error}", path.display());
+            }
+        }
+    }
+}
+
+fn format_time_pos(time_pos: f64) -> String {
+    let secs = if time_pos.is_finite() && time_pos > 0.0 {
+        time_pos as u64
+    } else {
+        0
+    };
+    format!("{:02}-{:02}-{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn format_date(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (year, month, day) = civil_from_days((secs / 86400) as i64);
+    let secs = secs % 86400;
+    format!(
+        "{year:04}{month:02}{day:02}-{:02}{:02}{:02}",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+// The proleptic Gregorian date of the days since 1970-01-01, see
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month + 2) / 5 + 1) as u32;
+    let month = if month < 10 { month + 3 } else { month - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
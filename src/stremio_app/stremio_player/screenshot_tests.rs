@@ -0,0 +1,81 @@
+use crate::stremio_app::stremio_player::screenshot::{
+    cleanup, file_name, unique_path, ScreenshotArgs,
+};
+use std::{
+    env, fs,
+    path::PathBuf,
+    process,
+    time::{Duration, UNIX_EPOCH},
+};
+
+#[test]
+fn screenshot_file_name() {
+    // 2023-11-14 22:13:20 UTC
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    assert_eq!(
+        file_name("{title}-{pos}-{date}", "Movie", 3723.9, now),
+        "Movie-01-02-03-20231114-221320"
+    );
+    // Leap day
+    let leap_day = UNIX_EPOCH + Duration::from_secs(1_709_164_800);
+    assert_eq!(file_name("{date}", "", 0.0, leap_day), "20240229-000000");
+    assert_eq!(
+        file_name("{title}", "S01E01: Who? <1/2>", 0.0, now),
+        "S01E01_ Who_ _1_2_"
+    );
+    assert_eq!(file_name("{title}.", "", f64::NAN, now), "screenshot");
+    assert_eq!(file_name("shot {pos}", "", -1.0, now), "shot 00-00-00");
+}
+
+#[test]
+fn screenshot_args() {
+    let args: ScreenshotArgs = serde_json::from_str("{}").unwrap();
+    assert_eq!(args, ScreenshotArgs::default());
+    assert_eq!(args.mpv_flag(), "subtitles");
+    assert_eq!(args.template(), "{title}-{pos}-{date}");
+    let args: ScreenshotArgs =
+        serde_json::from_str(r#"{"subtitles": false, "template": "{pos}"}"#).unwrap();
+    assert_eq!(args.mpv_flag(), "video");
+    assert_eq!(args.template(), "{pos}");
+    assert!(serde_json::from_str::<ScreenshotArgs>(r#"{"format": "jpg"}"#).is_err());
+}
+
+#[test]
+fn screenshot_args_dir() {
+    let check = |dir: &str| {
+        ScreenshotArgs {
+            dir: Some(PathBuf::from(dir)),
+            ..ScreenshotArgs::default()
+        }
+        .check()
+    };
+    assert_eq!(ScreenshotArgs::default().check(), Ok(()));
+    assert_eq!(check("Big Buck Bunny"), Ok(()));
+    assert_eq!(check("shows/Big Buck Bunny"), Ok(()));
+    for dir in ["", "/tmp", "../shows", "shows/../../tmp", "./shows"] {
+        assert!(check(dir).is_err(), "{}", dir);
+    }
+}
+
+#[test]
+fn screenshot_cleanup() {
+    let dir = env::temp_dir().join(format!("stremio-screenshot-cleanup-{}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let first = unique_path(&dir, "shot");
+    fs::write(&first, b"PNG").unwrap();
+    let second = unique_path(&dir, "shot");
+    assert_eq!(second, dir.join("shot-2.png"));
+    fs::write(&second, b"PNG").unwrap();
+    fs::write(dir.join("notes.txt"), b"").unwrap();
+
+    cleanup(&dir, Duration::from_secs(60 * 60));
+    assert!(first.exists() && second.exists());
+    // Only the screenshots are removed
+    cleanup(&dir, Duration::ZERO);
+    assert!(!first.exists() && !second.exists());
+    assert!(dir.join("notes.txt").exists());
+    fs::remove_dir_all(&dir).ok();
+
+    // A missing folder is fine
+    cleanup(&dir, Duration::ZERO);
+}
@@ -0,0 +1,216 @@
+use serde::Deserialize;
+use std::time::{Duration, Instant};
+
+use crate::stremio_app::constants::SOURCE_STARTUP_TIMEOUT;
+use crate::stremio_app::stremio_player::{
+    communication::{loadfile_node, EndFileError, EndReason, NodeValue},
+    LoadfileFlag, LoadfileOptions, PlayerEnded, PlayerSourceFailure, PlayerSourceLoaded,
+    PlayerSourcesFailed, SourceFailReason,
+};
+
+// The arguments of "player-load-with-fallbacks"
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct FallbackArgs {
+    // Tried in order until one of them plays
+    pub sources: Vec<FallbackSource>,
+    // Seconds, `SOURCE_STARTUP_TIMEOUT` if it is not set
+    #[serde(default)]
+    pub startup_timeout: Option<f64>,
+}
+impl FallbackArgs {
+    pub fn check(&self) -> Result<(), String> {
+        if self.sources.is_empty() {
+            return Err("`sources` must not be empty".to_string());
+        }
+        for source in &self.sources {
+            if source.url.is_empty() {
+                return Err("`url` must not be empty".to_string());
+            }
+            source.options.check()?;
+        }
+        if self
+            .startup_timeout
+            .is_some_and(|timeout| !timeout.is_finite() || timeout <= 0.)
+        {
+            return Err("`startupTimeout` must be a positive number".to_string());
+        }
+        Ok(())
+    }
+    fn startup_timeout(&self) -> Duration {
+        self.startup_timeout.map_or(
+            Duration::from_secs(SOURCE_STARTUP_TIMEOUT),
+            Duration::from_secs_f64,
+        )
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FallbackSource {
+    pub url: String,
+    // The same options as "loadfile", e.g. the HTTP headers an add-on needs
+    #[serde(default)]
+    pub options: LoadfileOptions,
+}
+impl FallbackSource {
+    // The `loadfile` command which plays the source instead of the file
+    // being played
+    pub fn loadfile_command(&self) -> NodeValue {
+        loadfile_node(&self.url, LoadfileFlag::Replace, self.options.to_mpv())
+    }
+}
+
+// What to do once the source being tried failed
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceStep {
+    // Run the `loadfile` command of the next source
+    Load(NodeValue),
+    // The last source failed
+    Failed(PlayerSourcesFailed),
+}
+
+#[derive(Debug)]
+struct Attempt {
+    id: u64,
+    args: FallbackArgs,
+    // The source being tried
+    index: usize,
+    // Set on `start-file`, the files which end before are not the source
+    started: bool,
+    deadline: Instant,
+    failed: Vec<PlayerSourceFailure>,
+}
+
+// Tries the sources of "player-load-with-fallbacks" in turn. A source plays
+// once mpv restarts playback after loading it, it fails if it ends with a
+// stream error or doesn't play before the startup timeout. The message
+// thread starts the attempts and the event thread follows them.
+#[derive(Default, Debug)]
+pub struct SourceFallback {
+    attempt: Option<Attempt>,
+}
+
+impl SourceFallback {
+    // Returns the `loadfile` command of the first source. The sources which
+    // were being tried are dropped without a reply.
+    pub fn start(&mut self, id: u64, args: FallbackArgs, now: Instant) -> NodeValue {
+        let loadfile = args.sources[0].loadfile_command();
+        self.attempt = Some(Attempt {
+            id,
+            deadline: now + args.startup_timeout(),
+            args,
+            index: 0,
+            started: false,
+            failed: Vec::new(),
+        });
+        loadfile
+    }
+
+    // Another file was loaded or playback was stopped
+    pub fn cancel(&mut self) {
+        self.attempt = None;
+    }
+
+    // When the source being tried times out
+    pub fn deadline(&self) -> Option<Instant> {
+        self.attempt.as_ref().map(|attempt| attempt.deadline)
+    }
+
+    pub fn start_file(&mut self) {
+        if let Some(attempt) = &mut self.attempt {
+            attempt.started = true;
+        }
+    }
+
+    // The reply once the source being tried plays
+    pub fn playback_restart(&mut self) -> Option<PlayerSourceLoaded> {
+        if !self.attempt.as_ref()?.started {
+            return None;
+        }
+        let attempt = self.attempt.take()?;
+        Some(PlayerSourceLoaded {
+            id: attempt.id,
+            index: attempt.index,
+            url: attempt.args.sources[attempt.index].url.clone(),
+            failed: attempt.failed,
+        })
+    }
+
+    // Stream errors move on to the next source. The attempt ends with the
+    // other errors, as another source is unlikely to fix them, and without
+    // a reply if the file was stopped.
+    pub fn end_file(&mut self, ended: &PlayerEnded, now: Instant) -> Option<SourceStep> {
+        if !self.attempt.as_ref()?.started {
+            return None;
+        }
+        match ended.reason {
+            // The playlist entries the source redirected to are loaded next
+            EndReason::Redirect => None,
+            EndReason::Error if ended.is_stream_error() => {
+                let attempt = self.attempt.take()?;
+                Some(self.next(attempt, SourceFailReason::Error, ended.error, now))
+            }
+            EndReason::Error => {
+                let mut attempt = self.attempt.take()?;
+                attempt.fail(SourceFailReason::Error, ended.error);
+                Some(SourceStep::Failed(PlayerSourcesFailed {
+                    id: attempt.id,
+                    failed: attempt.failed,
+                }))
+            }
+            _ => {
+                self.cancel();
+                None
+            }
+        }
+    }
+
+    pub fn timed_out(&mut self, now: Instant) -> Option<SourceStep> {
+        if self.deadline()? > now {
+            return None;
+        }
+        let attempt = self.attempt.take()?;
+        Some(self.next(attempt, SourceFailReason::Timeout, None, now))
+    }
+
+    // mpv didn't accept the `loadfile` command of the source
+    pub fn load_failed(&mut self, now: Instant) -> Option<SourceStep> {
+        let attempt = self.attempt.take()?;
+        Some(self.next(attempt, SourceFailReason::Error, None, now))
+    }
+
+    fn next(
+        &mut self,
+        mut attempt: Attempt,
+        reason: SourceFailReason,
+        error: Option<EndFileError>,
+        now: Instant,
+    ) -> SourceStep {
+        attempt.fail(reason, error);
+        attempt.index += 1;
+        match attempt.args.sources.get(attempt.index) {
+            Some(source) => {
+                let loadfile = source.loadfile_command();
+                attempt.started = false;
+                attempt.deadline = now + attempt.args.startup_timeout();
+                self.attempt = Some(attempt);
+                SourceStep::Load(loadfile)
+            }
+            None => SourceStep::Failed(PlayerSourcesFailed {
+                id: attempt.id,
+                failed: attempt.failed,
+            }),
+        }
+    }
+}
+
+impl Attempt {
+    fn fail(&mut self, reason: SourceFailReason, error: Option<EndFileError>) {
+        self.failed.push(PlayerSourceFailure {
+            url: self.args.sources[self.index].url.clone(),
+            reason,
+            error,
+        });
+    }
+}
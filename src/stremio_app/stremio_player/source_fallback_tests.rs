@@ -0,0 +1,191 @@
+use crate::stremio_app::stremio_player::{
+    communication::{loadfile_node, EndFileError},
+    source_fallback::{FallbackArgs, SourceFallback, SourceStep},
+    LoadfileFlag, NodeValue, PlayerEnded, PlayerSourceFailure, PlayerSourceLoaded,
+    PlayerSourcesFailed, SourceFailReason,
+};
+use libmpv2::mpv_end_file_reason;
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+fn args(urls: &[&str]) -> FallbackArgs {
+    let sources: Vec<_> = urls.iter().map(|url| json!({ "url": url })).collect();
+    serde_json::from_value(json!({ "sources": sources, "startupTimeout": 5 })).unwrap()
+}
+
+fn loadfile(url: &str) -> NodeValue {
+    loadfile_node(url, LoadfileFlag::Replace, Vec::new())
+}
+
+fn failure(
+    url: &str,
+    reason: SourceFailReason,
+    error: Option<EndFileError>,
+) -> PlayerSourceFailure {
+    PlayerSourceFailure {
+        url: url.to_string(),
+        reason,
+        error,
+    }
+}
+
+fn ended_with(error: i32) -> PlayerEnded {
+    PlayerEnded::from_end_file(mpv_end_file_reason::Error, Some(error), None)
+}
+
+#[test]
+fn source_fallback_args() {
+    let args: FallbackArgs = serde_json::from_value(json!({"sources": [{"url": "https://a/1.mkv",
+        "options": {"start": 30, "userAgent": "Stremio"}}]}))
+    .unwrap();
+    assert_eq!(args.check(), Ok(()));
+    assert_eq!(
+        serde_json::Value::from(args.sources[0].loadfile_command()),
+        json!({"name": "loadfile", "url": "https://a/1.mkv", "flags": "replace",
+            "options": {"start": "30", "user-agent": "Stremio"}})
+    );
+
+    let check = |value: serde_json::Value| {
+        serde_json::from_value::<FallbackArgs>(value)
+            .map_err(|error| error.to_string())
+            .and_then(|args| args.check())
+    };
+    assert_eq!(
+        check(json!({"sources": []})),
+        Err("`sources` must not be empty".to_string())
+    );
+    assert_eq!(
+        check(json!({"sources": [{"url": ""}]})),
+        Err("`url` must not be empty".to_string())
+    );
+    assert_eq!(
+        check(json!({"sources": [{"url": "a.mkv"}], "startupTimeout": 0})),
+        Err("`startupTimeout` must be a positive number".to_string())
+    );
+    assert_eq!(
+        check(json!({"sources": [{"url": "a.mkv", "options": {"referrer": "a\nb"}}]})),
+        Err("invalid `referrer`".to_string())
+    );
+    assert!(check(json!({"sources": [{"url": "a.mkv", "title": "A"}]})).is_err());
+}
+
+#[test]
+fn source_fallback_end_file() {
+    let now = Instant::now();
+    let mut sources = SourceFallback::default();
+    assert_eq!(sources.deadline(), None);
+    assert_eq!(
+        sources.start(1, args(&["a.mkv", "b.mkv", "c.mkv"]), now),
+        loadfile("a.mkv")
+    );
+    assert_eq!(sources.deadline(), Some(now + Duration::from_secs(5)));
+    // The file played before ends first
+    assert_eq!(sources.end_file(&ended_with(-13), now), None);
+    sources.start_file();
+    assert_eq!(
+        sources.end_file(&ended_with(-13), now),
+        Some(SourceStep::Load(loadfile("b.mkv")))
+    );
+    // Ended by the new `loadfile`
+    assert_eq!(
+        sources.end_file(
+            &PlayerEnded::from_end_reason(mpv_end_file_reason::Stop),
+            now
+        ),
+        None
+    );
+    sources.start_file();
+    assert_eq!(
+        sources.end_file(
+            &PlayerEnded::from_end_reason(mpv_end_file_reason::Redirect),
+            now
+        ),
+        None
+    );
+    sources.start_file();
+    assert_eq!(
+        sources.playback_restart(),
+        Some(PlayerSourceLoaded {
+            id: 1,
+            index: 1,
+            url: "b.mkv".to_string(),
+            failed: vec![failure(
+                "a.mkv",
+                SourceFailReason::Error,
+                Some(EndFileError::LoadingFailed)
+            )],
+        })
+    );
+    assert_eq!(sources.deadline(), None);
+    assert_eq!(sources.playback_restart(), None);
+
+    // Other errors are not fixed by another source
+    sources.start(2, args(&["a.mkv", "b.mkv"]), now);
+    sources.start_file();
+    assert_eq!(
+        sources.end_file(&ended_with(-14), now),
+        Some(SourceStep::Failed(PlayerSourcesFailed {
+            id: 2,
+            failed: vec![failure(
+                "a.mkv",
+                SourceFailReason::Error,
+                Some(EndFileError::AudioOutputFailed)
+            )],
+        }))
+    );
+    assert_eq!(sources.deadline(), None);
+
+    // Stopped without a reply
+    sources.start(3, args(&["a.mkv", "b.mkv"]), now);
+    sources.start_file();
+    assert_eq!(
+        sources.end_file(
+            &PlayerEnded::from_end_reason(mpv_end_file_reason::Quit),
+            now
+        ),
+        None
+    );
+    assert_eq!(sources.deadline(), None);
+}
+
+#[test]
+fn source_fallback_timeout() {
+    let now = Instant::now();
+    let later = |secs: u64| now + Duration::from_secs(secs);
+    let mut sources = SourceFallback::default();
+    assert_eq!(sources.timed_out(later(60)), None);
+    sources.start(1, args(&["a.mkv", "b.mkv"]), now);
+    sources.start_file();
+    assert_eq!(sources.timed_out(later(4)), None);
+    assert_eq!(
+        sources.timed_out(later(5)),
+        Some(SourceStep::Load(loadfile("b.mkv")))
+    );
+    // The next source has its own timeout
+    assert_eq!(sources.deadline(), Some(later(10)));
+    sources.start_file();
+    assert_eq!(
+        sources.end_file(&ended_with(-17), later(6)),
+        Some(SourceStep::Failed(PlayerSourcesFailed {
+            id: 1,
+            failed: vec![
+                failure("a.mkv", SourceFailReason::Timeout, None),
+                failure(
+                    "b.mkv",
+                    SourceFailReason::Error,
+                    Some(EndFileError::UnknownFormat)
+                ),
+            ],
+        }))
+    );
+
+    // The sources mpv doesn't accept fail right away
+    sources.start(2, args(&["a.mkv", "b.mkv"]), now);
+    assert_eq!(
+        sources.load_failed(now),
+        Some(SourceStep::Load(loadfile("b.mkv")))
+    );
+    sources.cancel();
+    assert_eq!(sources.load_failed(now), None);
+    assert_eq!(sources.timed_out(later(60)), None);
+}
@@ -0,0 +1,338 @@
+use flume::{Receiver, Selector, Sender};
+use libmpv2::Format;
+use std::{
+    collections::{HashMap, VecDeque},
+    mem,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+    sync::{Arc, Mutex, PoisonError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::stremio_app::constants::{PLAYER_MAX_RESTARTS, PLAYER_RESTART_WINDOW};
+use crate::stremio_app::stremio_player::{
+    backend::{PlayerBackend, PlayerEvents},
+    communication::loadfile_node,
+    dispatch::MessageHandler,
+    event_loop::{run_event_loop, PlayerState},
+    player_log::PlayerLog,
+    player_settings::PlayerSettings,
+    resume_store::ResumeStore,
+    source_fallback::SourceFallback,
+    thumbnails::Thumbnails,
+    LoadfileFlag, LoadfileOptions, PlayerAudioDeviceMissing, PlayerError, PlayerEvent,
+    PlayerRestarted, PropVal,
+};
+
+// What the player keeps across backend instances
+#[derive(Clone)]
+pub struct PlayerStores {
+    pub resume_store: Arc<Mutex<ResumeStore>>,
+    pub player_log: Arc<Mutex<PlayerLog>>,
+    pub settings: Arc<Mutex<PlayerSettings>>,
+    pub thumbnails: Arc<Thumbnails>,
+    pub sources: Arc<Mutex<SourceFallback>>,
+    // The options the files in the playlist were loaded with, by URL, so
+    // they are loaded again the same way
+    pub loadfile_options: Arc<Mutex<HashMap<String, LoadfileOptions>>>,
+    // Where the screenshots the web UI keeps are written to
+    pub screenshot_dir: Option<PathBuf>,
+}
+
+enum SessionEnd {
+    // By the app
+    Stopped,
+    // The backend shut down by itself
+    Quit,
+    Crashed,
+}
+
+// A backend instance with the thread running its event loop
+struct Session<B: PlayerBackend> {
+    backend: Arc<B>,
+    handler: MessageHandler<B>,
+    event_thread: JoinHandle<()>,
+    // Receives once the backend shuts down by itself, e.g. when the user
+    // quits with a key binding or a script. Disconnected without receiving
+    // if the event thread panics.
+    ended_receiver: Receiver<()>,
+}
+
+impl<B: PlayerBackend + 'static> Session<B> {
+    // The backend and its events are created on the event thread, as the
+    // events may not be sent between threads. Returns `None` if creating the
+    // backend panicked.
+    fn start<E, F>(
+        create_backend: &Arc<F>,
+        state: &Arc<Mutex<PlayerState>>,
+        stores: &PlayerStores,
+        player_event_sender: &Sender<PlayerEvent>,
+    ) -> Option<Self>
+    where
+        E: PlayerEvents,
+        F: Fn() -> (Arc<B>, E) + Send + Sync + 'static,
+    {
+        let (observe_property_sender, observe_property_receiver) = flume::unbounded();
+        let (backend_sender, backend_receiver) = flume::bounded(1);
+        let (ended_sender, ended_receiver) = flume::bounded::<()>(1);
+        let create_backend = Arc::clone(create_backend);
+        let state = Arc::clone(state);
+        let event_stores = stores.clone();
+        let event_sender = player_event_sender.clone();
+        let event_thread = thread::spawn(move || {
+            let (backend, mut events) = create_backend();
+            backend_sender
+                .send(Arc::clone(&backend))
+                .expect("failed to send the player backend");
+            let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+            run_event_loop(
+                backend.as_ref(),
+                &mut events,
+                &mut state,
+                &event_stores,
+                observe_property_receiver,
+                event_sender,
+            );
+            ended_sender.try_send(()).ok();
+        });
+
+        match backend_receiver.recv() {
+            Ok(backend) => Some(Self {
+                handler: MessageHandler::new(
+                    Arc::clone(&backend),
+                    stores.clone(),
+                    observe_property_sender,
+                    player_event_sender.clone(),
+                ),
+                backend,
+                event_thread,
+                ended_receiver,
+            }),
+            Err(_) => {
+                event_thread.join().ok();
+                None
+            }
+        }
+    }
+
+    // Handles the messages from the web UI until the player is stopped, the
+    // backend shuts down or the event thread panics
+    fn run(
+        &self,
+        in_msg_receiver: &Receiver<String>,
+        stop_receiver: &Receiver<()>,
+        player_event_sender: &Sender<PlayerEvent>,
+    ) -> SessionEnd {
+        enum Next {
+            Msg(String),
+            Stop,
+            Quit,
+            Crashed,
+        }
+        loop {
+            let next = Selector::new()
+                .recv(in_msg_receiver, |msg| msg.map_or(Next::Stop, Next::Msg))
+                .recv(stop_receiver, |_| Next::Stop)
+                .recv(&self.ended_receiver, |ended| {
+                    ended.map_or(Next::Crashed, |()| Next::Quit)
+                })
+                .wait();
+            match next {
+                Next::Msg(msg) => {
+                    // Only the message fails, the backend is still running
+                    let handle = AssertUnwindSafe(|| self.handler.handle(&msg));
+                    if panic::catch_unwind(handle).is_err() {
+                        player_event_sender
+                            .send(PlayerEvent::Error(PlayerError::new(
+                                &msg,
+                                "failed to handle the message",
+                            )))
+                            .expect("failed to send PlayerEvent");
+                    }
+                }
+                Next::Stop => return SessionEnd::Stopped,
+                Next::Quit => return SessionEnd::Quit,
+                Next::Crashed => return SessionEnd::Crashed,
+            }
+        }
+    }
+
+    // Tells the backend to quit if it is still running and waits for the
+    // event thread to finish
+    fn end(self) {
+        if !self.event_thread.is_finished() {
+            if let Err(error) = self.backend.run_command(&["quit".to_string()]) {
+                eprintln!("failed to quit MPV: '{error:#}'");
+            }
+        }
+        if self.event_thread.join().is_err() {
+            eprintln!("player event thread panicked");
+        }
+    }
+}
+
+// Runs the backend made by `create_backend` until the player is stopped. If
+// the backend shuts down by itself or its event thread panics, the backend is
+// created again and the observed properties and the saved audio device are
+// restored. After a panic the file being played is also loaded again at its
+// last position if `resume` is set. Gives up after `PLAYER_MAX_RESTARTS`
+// restarts within `PLAYER_RESTART_WINDOW`.
+pub fn run_supervisor<B, E, F>(
+    create_backend: F,
+    resume: bool,
+    stores: PlayerStores,
+    in_msg_receiver: Receiver<String>,
+    stop_receiver: Receiver<()>,
+    player_event_sender: Sender<PlayerEvent>,
+) where
+    B: PlayerBackend + 'static,
+    E: PlayerEvents,
+    F: Fn() -> (Arc<B>, E) + Send + Sync + 'static,
+{
+    let create_backend = Arc::new(create_backend);
+    let state = Arc::new(Mutex::new(PlayerState::default()));
+    let restart_window = Duration::from_secs(PLAYER_RESTART_WINDOW);
+    let mut restarts = VecDeque::new();
+    let mut restarted = None;
+
+    loop {
+        let session_end =
+            match Session::start(&create_backend, &state, &stores, &player_event_sender) {
+                Some(session) => {
+                    restore_audio_device(
+                        session.backend.as_ref(),
+                        &stores.settings,
+                        &player_event_sender,
+                    );
+                    if let Some((restarted, options)) = restarted.take() {
+                        let restarted = restore(session.backend.as_ref(), restarted, &options);
+                        player_event_sender
+                            .send(PlayerEvent::Restarted(restarted))
+                            .expect("failed to send PlayerEvent");
+                    }
+                    let session_end =
+                        session.run(&in_msg_receiver, &stop_receiver, &player_event_sender);
+                    session.end();
+                    match session_end {
+                        SessionEnd::Stopped => return,
+                        SessionEnd::Quit => eprintln!("MPV quit by itself"),
+                        SessionEnd::Crashed => eprintln!("MPV stopped unexpectedly"),
+                    }
+                    session_end
+                }
+                None => {
+                    eprintln!("cannot create MPV");
+                    SessionEnd::Crashed
+                }
+            };
+
+        let now = Instant::now();
+        restarts.retain(|restart| now.duration_since(*restart) < restart_window);
+        if restarts.len() >= PLAYER_MAX_RESTARTS {
+            eprintln!("MPV failed too many times, the player is disabled");
+            reject_messages(&in_msg_receiver, &stop_receiver, &player_event_sender);
+            return;
+        }
+        restarts.push_back(now);
+
+        // The event thread has finished, so the state is not locked. The
+        // file the user quit is not loaded again.
+        let resume = resume && matches!(session_end, SessionEnd::Crashed);
+        let mut last_state = state.lock().unwrap_or_else(PoisonError::into_inner);
+        let options = mem::take(&mut last_state.options);
+        let last_restarted = PlayerRestarted {
+            path: last_state.path.take().filter(|_| resume),
+            time_pos: last_state.time_pos.take().filter(|_| resume),
+        };
+        restarted = Some((last_restarted, options));
+    }
+}
+
+// Selects the saved audio device if mpv can see it. Otherwise mpv keeps
+// using "auto" and the web UI is told about the missing device.
+fn restore_audio_device<B: PlayerBackend + ?Sized>(
+    backend: &B,
+    settings: &Mutex<PlayerSettings>,
+    player_event_sender: &Sender<PlayerEvent>,
+) {
+    let device = match &settings
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .audio_device
+    {
+        Some(device) => device.clone(),
+        None => return,
+    };
+    let available = match backend.get_prop("audio-device-list", Format::Node) {
+        Ok(devices) => devices.data().as_array().is_some_and(|devices| {
+            devices
+                .iter()
+                .any(|available| available["name"].as_str() == Some(device.as_str()))
+        }),
+        Err(error) => {
+            eprintln!("cannot get the MPV audio devices: '{error:#}'");
+            false
+        }
+    };
+    let selected = available
+        && backend
+            .set_prop("audio-device", &PropVal::Str(device.clone()))
+            .map_err(|error| eprintln!("cannot select the audio device {device}: '{error:#}'"))
+            .is_ok();
+    if !selected {
+        player_event_sender
+            .send(PlayerEvent::AudioDeviceMissing(PlayerAudioDeviceMissing {
+                device,
+            }))
+            .expect("failed to send PlayerEvent");
+    }
+}
+
+// Loads the file which was being played with the options it was loaded
+// with, returns what was resumed
+fn restore<B: PlayerBackend + ?Sized>(
+    backend: &B,
+    restarted: PlayerRestarted,
+    options: &LoadfileOptions,
+) -> PlayerRestarted {
+    let path = match restarted.path {
+        Some(ref path) => path,
+        None => return PlayerRestarted::default(),
+    };
+    let options = LoadfileOptions {
+        start: restarted.time_pos.or(options.start),
+        ..options.clone()
+    };
+    match backend.run_command_node(&loadfile_node(
+        path,
+        LoadfileFlag::Replace,
+        options.to_mpv(),
+    )) {
+        Ok(()) => restarted,
+        Err(error) => {
+            eprintln!("failed to resume {path}: '{error:#}'");
+            PlayerRestarted::default()
+        }
+    }
+}
+
+// Replies with an error to every message until the player is stopped
+fn reject_messages(
+    in_msg_receiver: &Receiver<String>,
+    stop_receiver: &Receiver<()>,
+    player_event_sender: &Sender<PlayerEvent>,
+) {
+    while let Some(msg) = Selector::new()
+        .recv(in_msg_receiver, Result::ok)
+        .recv(stop_receiver, |_| None)
+        .wait()
+    {
+        player_event_sender
+            .send(PlayerEvent::Error(PlayerError::new(
+                &msg,
+                "player is not running",
+            )))
+            .expect("failed to send PlayerEvent");
+    }
+}
@@ -0,0 +1,356 @@
+use crate::stremio_app::constants::PLAYER_MAX_RESTARTS;
+use crate::stremio_app::stremio_player::{
+    backend::BackendEvent,
+    backend_fake::{prop_change, BackendCall, FakeBackend, FakeEvents},
+    communication::loadfile_node,
+    observed_props::RESUME_PROPS_ID,
+    player_log::PlayerLog,
+    player_settings::PlayerSettings,
+    supervisor::{run_supervisor, PlayerStores},
+    LoadfileFlag, LoadfileOptions, PlayerAudioDeviceMissing, PlayerError, PlayerEvent,
+    PlayerRestarted, PropVal,
+};
+use flume::{Receiver, Sender};
+use libmpv2::events::PropertyData;
+use serde_json::json;
+use std::{
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+fn stores() -> PlayerStores {
+    PlayerStores {
+        resume_store: Arc::default(),
+        player_log: Arc::new(Mutex::new(PlayerLog::new(10))),
+        settings: Arc::default(),
+        thumbnails: Arc::default(),
+        sources: Arc::default(),
+        loadfile_options: Arc::default(),
+        screenshot_dir: None,
+    }
+}
+
+struct Supervisor {
+    backends: Arc<Mutex<Vec<Arc<FakeBackend>>>>,
+    in_msg_sender: Sender<String>,
+    stop_sender: Sender<()>,
+    player_event_receiver: Receiver<PlayerEvent>,
+    thread: JoinHandle<()>,
+}
+
+impl Supervisor {
+    // `create` makes the backend and its scripted events for each session
+    fn start<F>(create: F, resume: bool) -> Self
+    where
+        F: Fn(usize) -> (FakeBackend, Vec<BackendEvent>) + Send + Sync + 'static,
+    {
+        Self::start_with_stores(create, resume, stores())
+    }
+    fn start_with_stores<F>(create: F, resume: bool, stores: PlayerStores) -> Self
+    where
+        F: Fn(usize) -> (FakeBackend, Vec<BackendEvent>) + Send + Sync + 'static,
+    {
+        let backends = Arc::new(Mutex::new(Vec::new()));
+        let (in_msg_sender, in_msg_receiver) = flume::unbounded();
+        let (stop_sender, stop_receiver) = flume::bounded(0);
+        let (player_event_sender, player_event_receiver) = flume::unbounded();
+        let created = Arc::clone(&backends);
+        let create_backend = move || {
+            let mut created = created.lock().unwrap();
+            let (backend, events) = create(created.len());
+            let backend = Arc::new(backend);
+            created.push(Arc::clone(&backend));
+            let events = FakeEvents::new(&backend, events);
+            (backend, events)
+        };
+        let thread = thread::spawn(move || {
+            run_supervisor(
+                create_backend,
+                resume,
+                stores,
+                in_msg_receiver,
+                stop_receiver,
+                player_event_sender,
+            )
+        });
+        Self {
+            backends,
+            in_msg_sender,
+            stop_sender,
+            player_event_receiver,
+            thread,
+        }
+    }
+    fn backend(&self, session: usize) -> Arc<FakeBackend> {
+        wait_for(|| self.backends.lock().unwrap().len() > session);
+        Arc::clone(&self.backends.lock().unwrap()[session])
+    }
+    fn send(&self, msg: &str) {
+        self.in_msg_sender.send(msg.to_string()).unwrap();
+    }
+    fn recv_until(&self, f: impl Fn(&PlayerEvent) -> bool) -> PlayerEvent {
+        loop {
+            let player_event = self
+                .player_event_receiver
+                .recv_timeout(TIMEOUT)
+                .expect("expected PlayerEvent");
+            if f(&player_event) {
+                return player_event;
+            }
+        }
+    }
+    fn stop(self) -> Vec<Arc<FakeBackend>> {
+        drop(self.stop_sender);
+        self.thread.join().expect("supervisor panicked");
+        let backends = self.backends.lock().unwrap();
+        backends.clone()
+    }
+}
+
+fn wait_for(f: impl Fn() -> bool) {
+    let deadline = Instant::now() + TIMEOUT;
+    while !f() {
+        assert!(Instant::now() < deadline, "timed out");
+        thread::sleep(Duration::from_millis(1));
+    }
+}
+
+#[test]
+fn supervisor_restarts() {
+    // Loaded with a user agent
+    let stores = stores();
+    let options = LoadfileOptions {
+        user_agent: Some("Stremio".to_string()),
+        ..LoadfileOptions::default()
+    };
+    stores
+        .loadfile_options
+        .lock()
+        .unwrap()
+        .insert("https://a/1.mkv".to_string(), options);
+    let supervisor = Supervisor::start_with_stores(
+        |session| match session {
+            0 => (
+                FakeBackend::default().with_prop("path", PropertyData::Str("https://a/1.mkv")),
+                vec![
+                    BackendEvent::FileLoaded,
+                    prop_change(RESUME_PROPS_ID, "time-pos", PropertyData::Double(42.5)),
+                    BackendEvent::Seek,
+                ],
+            ),
+            _ => (FakeBackend::default(), Vec::new()),
+        },
+        true,
+        stores,
+    );
+    supervisor.send(r#"["mpv-observe-prop", "time-pos"]"#);
+    let first = supervisor.backend(0);
+    wait_for(|| {
+        first
+            .calls()
+            .contains(&BackendCall::Observe("time-pos".to_string(), 1))
+    });
+    // The position was saved
+    supervisor.recv_until(|player_event| matches!(player_event, PlayerEvent::Seek));
+
+    // The event thread panics
+    first.crash();
+    assert_eq!(
+        supervisor.recv_until(|player_event| matches!(player_event, PlayerEvent::Restarted(_))),
+        PlayerEvent::Restarted(PlayerRestarted {
+            path: Some("https://a/1.mkv".to_string()),
+            time_pos: Some(42.5),
+        })
+    );
+    let second = supervisor.backend(1);
+    wait_for(|| {
+        second
+            .calls()
+            .contains(&BackendCall::Observe("time-pos".to_string(), 1))
+    });
+    assert!(second
+        .calls()
+        .contains(&BackendCall::CommandNode(loadfile_node(
+            "https://a/1.mkv",
+            LoadfileFlag::Replace,
+            vec![
+                ("start".to_string(), "42.5".to_string()),
+                ("user-agent".to_string(), "Stremio".to_string())
+            ]
+        ))));
+
+    let backends = supervisor.stop();
+    assert_eq!(backends.len(), 2);
+    assert!(second.is_shut_down());
+}
+
+#[test]
+fn supervisor_restarts_without_resume() {
+    let supervisor = Supervisor::start(
+        |session| match session {
+            0 => (
+                FakeBackend::default().with_prop("path", PropertyData::Str("C:\\a.mkv")),
+                vec![BackendEvent::FileLoaded],
+            ),
+            _ => (FakeBackend::default(), Vec::new()),
+        },
+        false,
+    );
+    supervisor.recv_until(|player_event| matches!(player_event, PlayerEvent::FileLoaded));
+    supervisor.backend(0).crash();
+    assert_eq!(
+        supervisor.recv_until(|player_event| matches!(player_event, PlayerEvent::Restarted(_))),
+        PlayerEvent::Restarted(PlayerRestarted::default())
+    );
+    let backends = supervisor.stop();
+    assert!(!backends[1]
+        .calls()
+        .iter()
+        .any(|call| matches!(call, BackendCall::Command(args) if args[0] == "loadfile")));
+}
+
+#[test]
+fn supervisor_quit() {
+    let supervisor = Supervisor::start(
+        |_| {
+            (
+                FakeBackend::default().with_prop("path", PropertyData::Str("C:\\a.mkv")),
+                vec![BackendEvent::FileLoaded],
+            )
+        },
+        true,
+    );
+    // The user quits mpv with a key binding, it is created again without
+    // the file
+    supervisor.backend(0).shut_down();
+    assert_eq!(
+        supervisor.recv_until(|player_event| matches!(player_event, PlayerEvent::Restarted(_))),
+        PlayerEvent::Restarted(PlayerRestarted::default())
+    );
+    // The messages are handled by the new backend
+    supervisor.send(r#"["mpv-set-prop", ["pause", true]]"#);
+    let second = supervisor.backend(1);
+    wait_for(|| {
+        second.calls().contains(&BackendCall::SetProperty(
+            "pause".to_string(),
+            PropVal::Bool(true),
+        ))
+    });
+    assert!(!second
+        .calls()
+        .iter()
+        .any(|call| matches!(call, BackendCall::CommandNode(_))));
+    let backends = supervisor.stop();
+    assert_eq!(backends.len(), 2);
+}
+
+#[test]
+fn supervisor_message_panics() {
+    let supervisor = Supervisor::start(
+        |_| (FakeBackend::default().panicking("stop"), Vec::new()),
+        true,
+    );
+    supervisor.send(r#"["mpv-command", ["stop"]]"#);
+    assert_eq!(
+        supervisor.recv_until(|player_event| matches!(player_event, PlayerEvent::Error(_))),
+        PlayerEvent::Error(PlayerError::new(
+            r#"["mpv-command", ["stop"]]"#,
+            "failed to handle the message",
+        ))
+    );
+    // The next messages are handled by the same backend
+    supervisor.send(r#"["mpv-set-prop", ["pause", true]]"#);
+    let first = supervisor.backend(0);
+    wait_for(|| {
+        first.calls().contains(&BackendCall::SetProperty(
+            "pause".to_string(),
+            PropVal::Bool(true),
+        ))
+    });
+    let backends = supervisor.stop();
+    assert_eq!(backends.len(), 1);
+}
+
+#[test]
+fn supervisor_gives_up() {
+    // Every backend crashes as soon as it is created
+    let supervisor = Supervisor::start(
+        |_| {
+            let backend = FakeBackend::default();
+            backend.crash();
+            (backend, Vec::new())
+        },
+        true,
+    );
+    wait_for(|| {
+        // Messages are handled by the backends until the supervisor gives up
+        supervisor.send(r#"["mpv-command", ["stop"]]"#);
+        supervisor
+            .player_event_receiver
+            .recv_timeout(Duration::from_millis(10))
+            .is_ok_and(|player_event| {
+                player_event
+                    == PlayerEvent::Error(PlayerError::new(
+                        r#"["mpv-command", ["stop"]]"#,
+                        "player is not running",
+                    ))
+            })
+    });
+    let backends = supervisor.stop();
+    assert_eq!(backends.len(), PLAYER_MAX_RESTARTS + 1);
+}
+
+#[test]
+fn supervisor_audio_device() {
+    let hdmi = "wasapi/{hdmi}";
+    let mut settings = PlayerSettings::default();
+    settings.audio_device = Some(hdmi.to_string());
+    let stores = PlayerStores {
+        settings: Arc::new(Mutex::new(settings)),
+        ..stores()
+    };
+    let supervisor = Supervisor::start_with_stores(
+        move |session| match session {
+            0 => (
+                FakeBackend::default().with_json_prop(
+                    "audio-device-list",
+                    json!([{"name": "auto"}, {"name": hdmi, "description": "HDMI"}]),
+                ),
+                Vec::new(),
+            ),
+            // The receiver was unplugged before mpv was restarted
+            _ => (
+                FakeBackend::default()
+                    .with_json_prop("audio-device-list", json!([{"name": "auto"}])),
+                Vec::new(),
+            ),
+        },
+        false,
+        stores,
+    );
+    let first = supervisor.backend(0);
+    wait_for(|| {
+        first.calls().contains(&BackendCall::SetProperty(
+            "audio-device".to_string(),
+            PropVal::Str(hdmi.to_string()),
+        ))
+    });
+
+    first.crash();
+    assert_eq!(
+        supervisor.recv_until(|player_event| {
+            matches!(player_event, PlayerEvent::AudioDeviceMissing(_))
+        }),
+        PlayerEvent::AudioDeviceMissing(PlayerAudioDeviceMissing {
+            device: hdmi.to_string(),
+        })
+    );
+    let backends = supervisor.stop();
+    assert!(!backends[1]
+        .calls()
+        .iter()
+        .any(|call| matches!(call, BackendCall::SetProperty(..))));
+}
@@ -0,0 +1,95 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use crate::stremio_app::stremio_player::PlayerEvent;
+
+struct ThrottledProp {
+    interval: Duration,
+    last_sent: Option<Instant>,
+    pending: Option<PlayerEvent>,
+}
+
+// Rate limits the changes of high-frequency properties. A change that comes
+// too early is held back and replaced by any newer one, so the latest value
+// is always delivered once its interval has passed.
+pub struct PropThrottle {
+    props: HashMap<String, ThrottledProp>,
+}
+
+impl PropThrottle {
+    pub fn new<'a>(intervals: impl IntoIterator<Item = (&'a str, Duration)>) -> Self {
+        Self {
+            props: intervals
+                .into_iter()
+                .map(|(name, interval)| {
+                    let prop = ThrottledProp {
+                        interval,
+                        last_sent: None,
+                        pending: None,
+                    };
+                    (name.to_string(), prop)
+                })
+                .collect(),
+        }
+    }
+    // Returns the events that can be sent right away
+    pub fn push(&mut self, event: PlayerEvent, now: Instant) -> Vec<PlayerEvent> {
+        let prop = match &event {
+            PlayerEvent::PropChange(change) => self.props.get_mut(change.name()),
+            // Anything else may depend on the previous property values, e.g.
+            // the position before a seek, so those are sent first
+            _ => {
+                let mut events = self.flush_all();
+                events.push(event);
+                return events;
+            }
+        };
+        match prop {
+            Some(prop)
+                if prop
+                    .last_sent
+                    .is_some_and(|sent| now < sent + prop.interval) =>
+            {
+                prop.pending = Some(event);
+                vec![]
+            }
+            Some(prop) => {
+                prop.last_sent = Some(now);
+                prop.pending = None;
+                vec![event]
+            }
+            None => vec![event],
+        }
+    }
+    // Returns the held back events whose interval has passed
+    pub fn flush_due(&mut self, now: Instant) -> Vec<PlayerEvent> {
+        self.props
+            .values_mut()
+            .filter(|prop| {
+                prop.last_sent
+                    .is_none_or(|sent| sent + prop.interval <= now)
+            })
+            .filter_map(|prop| {
+                let event = prop.pending.take()?;
+                prop.last_sent = Some(now);
+                Some(event)
+            })
+            .collect()
+    }
+    pub fn flush_all(&mut self) -> Vec<PlayerEvent> {
+        self.props
+            .values_mut()
+            .filter_map(|prop| prop.pending.take())
+            .collect()
+    }
+    // When `flush_due` has to be called next
+    pub fn next_due(&self) -> Option<Instant> {
+        self.props
+            .values()
+            .filter(|prop| prop.pending.is_some())
+            .filter_map(|prop| prop.last_sent.map(|sent| sent + prop.interval))
+            .min()
+    }
+}
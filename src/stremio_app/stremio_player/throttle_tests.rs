@@ -0,0 +1,128 @@
+use std::time::{Duration, Instant};
+
+use crate::stremio_app::stremio_player::{throttle::PropThrottle, PlayerEvent, PlayerProprChange};
+use libmpv2::events::PropertyData;
+
+struct FakeClock(Instant);
+impl FakeClock {
+    fn now(&self) -> Instant {
+        self.0
+    }
+    fn advance(&mut self, millis: u64) -> Instant {
+        self.0 += Duration::from_millis(millis);
+        self.0
+    }
+}
+
+fn prop_change(name: &str, value: f64) -> PlayerEvent {
+    PlayerEvent::PropChange(PlayerProprChange::from_name_value(
+        name.to_string(),
+        PropertyData::Double(value),
+    ))
+}
+
+fn throttle() -> PropThrottle {
+    PropThrottle::new([
+        ("time-pos", Duration::from_millis(250)),
+        ("demuxer-cache-state", Duration::from_millis(1000)),
+    ])
+}
+
+fn values(events: Vec<PlayerEvent>) -> Vec<serde_json::Value> {
+    events
+        .into_iter()
+        .map(|event| serde_json::to_value(event).expect("serializable event"))
+        .collect()
+}
+
+#[test]
+fn first_change_passes() {
+    let clock = FakeClock(Instant::now());
+    let mut throttle = throttle();
+    assert_eq!(
+        throttle
+            .push(prop_change("time-pos", 1.0), clock.now())
+            .len(),
+        1
+    );
+    assert_eq!(throttle.next_due(), None);
+}
+
+#[test]
+fn unthrottled_passes() {
+    let clock = FakeClock(Instant::now());
+    let mut throttle = throttle();
+    for _ in 0..3 {
+        assert_eq!(
+            throttle.push(prop_change("volume", 1.0), clock.now()).len(),
+            1
+        );
+    }
+}
+
+#[test]
+fn coalesce_latest() {
+    let mut clock = FakeClock(Instant::now());
+    let mut throttle = throttle();
+    let start = clock.now();
+    throttle.push(prop_change("time-pos", 1.0), start);
+    assert!(throttle
+        .push(prop_change("time-pos", 1.1), clock.advance(50))
+        .is_empty());
+    assert!(throttle
+        .push(prop_change("time-pos", 1.2), clock.advance(50))
+        .is_empty());
+    assert_eq!(
+        throttle.next_due(),
+        Some(start + Duration::from_millis(250))
+    );
+
+    // Not due yet
+    assert!(throttle.flush_due(clock.advance(100)).is_empty());
+    assert_eq!(
+        values(throttle.flush_due(clock.advance(50))),
+        values(vec![prop_change("time-pos", 1.2)])
+    );
+    assert_eq!(throttle.next_due(), None);
+    assert!(throttle.flush_due(clock.advance(1000)).is_empty());
+}
+
+#[test]
+fn interval_per_prop() {
+    let mut clock = FakeClock(Instant::now());
+    let mut throttle = throttle();
+    throttle.push(prop_change("time-pos", 1.0), clock.now());
+    throttle.push(prop_change("demuxer-cache-state", 1.0), clock.now());
+    throttle.push(prop_change("time-pos", 2.0), clock.advance(100));
+    throttle.push(prop_change("demuxer-cache-state", 2.0), clock.now());
+
+    assert_eq!(
+        values(throttle.flush_due(clock.advance(200))),
+        values(vec![prop_change("time-pos", 2.0)])
+    );
+    // A change after a flush waits for the interval again
+    assert!(throttle
+        .push(prop_change("time-pos", 3.0), clock.advance(100))
+        .is_empty());
+    let flushed = values(throttle.flush_due(clock.advance(700)));
+    assert_eq!(flushed.len(), 2);
+    for event in values(vec![
+        prop_change("time-pos", 3.0),
+        prop_change("demuxer-cache-state", 2.0),
+    ]) {
+        assert!(flushed.contains(&event));
+    }
+}
+
+#[test]
+fn other_events_flush_pending() {
+    let mut clock = FakeClock(Instant::now());
+    let mut throttle = throttle();
+    throttle.push(prop_change("time-pos", 1.0), clock.now());
+    throttle.push(prop_change("time-pos", 2.0), clock.advance(10));
+    assert_eq!(
+        values(throttle.push(PlayerEvent::Seek, clock.advance(10))),
+        values(vec![prop_change("time-pos", 2.0), PlayerEvent::Seek])
+    );
+    assert!(throttle.flush_all().is_empty());
+}
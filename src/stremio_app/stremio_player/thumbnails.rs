@@ -0,0 +1,333 @@
+use sha2::{Digest, Sha256};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use crate::stremio_app::media_probe::{hide_window, media_input};
+
+// Written to the folder of a file once all its frames are extracted
+const DONE_FILE: &str = "done";
+// How often ffmpeg is checked for cancellation
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone)]
+pub struct ThumbnailOptions {
+    pub ffmpeg: PathBuf,
+    // A folder per file is created in it
+    pub cache_dir: PathBuf,
+    // Seconds between two frames
+    pub interval: f64,
+    // Pixels, the height keeps the aspect ratio
+    pub width: u32,
+    // Bytes all the folders may take
+    pub disk_budget: u64,
+}
+
+// The frame nearest to the asked time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Thumbnail {
+    pub time: f64,
+    pub path: PathBuf,
+}
+
+// Low resolution frames of the file being played, extracted by ffmpeg in
+// the background every `interval` seconds. The folders of the files played
+// before are kept as a cache, the least recently used are removed when the
+// disk budget is reached. Without options nothing is extracted.
+#[derive(Default)]
+pub struct Thumbnails {
+    options: Option<ThumbnailOptions>,
+    frames: Mutex<Option<Frames>>,
+}
+
+struct Frames {
+    dir: PathBuf,
+    job: Option<Job>,
+}
+
+struct Job {
+    cancel: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl Thumbnails {
+    pub fn new(options: ThumbnailOptions) -> Self {
+        Self {
+            options: Some(options),
+            frames: Mutex::default(),
+        }
+    }
+
+    // Starts extracting the frames of `input`, a local path or an HTTP(S)
+    // URL which lasts `duration` seconds. The frames extracted for it before
+    // are used if they are complete.
+    pub fn start(&self, input: &str, duration: f64) {
+        self.cancel();
+        let options = match &self.options {
+            Some(options) => options.clone(),
+            None => return,
+        };
+        let input = match media_input(input) {
+            Some(input) => input,
+            None => return,
+        };
+        let dir = options.cache_dir.join(cache_key(&input));
+        let done_file = dir.join(DONE_FILE);
+        let job = if done_file.is_file() {
+            // Marks the folder as recently used
+            if let Err(error) = fs::write(&done_file, b"") {
+                eprintln!("cannot update {}: {error}", done_file.display());
+            }
+            None
+        } else {
+            let cancel = Arc::new(AtomicBool::new(false));
+            let job_cancel = Arc::clone(&cancel);
+            let job_dir = dir.clone();
+            let thread =
+                thread::spawn(move || extract(&options, &input, duration, &job_dir, &job_cancel));
+            Some(Job { cancel, thread })
+        };
+        *self.frames() = Some(Frames { dir, job });
+    }
+
+    // Stops the extraction and forgets the file. Frames which were not all
+    // extracted are removed.
+    pub fn cancel(&self) {
+        // The lock is released before waiting for the thread
+        let job = self.frames().take().and_then(|frames| frames.job);
+        if let Some(job) = job {
+            job.cancel.store(true, Ordering::SeqCst);
+            if job.thread.join().is_err() {
+                eprintln!("thumbnail thread panicked");
+            }
+        }
+    }
+
+    #[cfg(test)]
+    pub fn is_extracting(&self) -> bool {
+        self.frames()
+            .as_ref()
+            .and_then(|frames| frames.job.as_ref())
+            .is_some_and(|job| !job.thread.is_finished())
+    }
+
+    // The extracted frame nearest to `time`, or the last one if the frames
+    // up to `time` are not extracted yet
+    pub fn frame_at(&self, time: f64) -> Option<Thumbnail> {
+        let interval = self.options.as_ref()?.interval;
+        let frames = self.frames();
+        let frames = frames.as_ref()?;
+        let mut count = fs::read_dir(&frames.dir)
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "jpg"))
+            .count();
+        // ffmpeg may still be writing the last one
+        if frames
+            .job
+            .as_ref()
+            .is_some_and(|job| !job.thread.is_finished())
+        {
+            count = count.saturating_sub(1);
+        }
+        let index = ((time.max(0.) / interval).round() as usize).min(count.checked_sub(1)?);
+        Some(Thumbnail {
+            time: index as f64 * interval,
+            path: frames.dir.join(frame_name(index)),
+        })
+    }
+
+    fn frames(&self) -> MutexGuard<'_, Option<Frames>> {
+        self.frames.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for Thumbnails {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// The folder name for the frames of `input`
+pub fn cache_key(input: &str) -> String {
+    format!("{:x}", Sha256::digest(input.as_bytes()))
+}
+
+// The frames are numbered from 1 by ffmpeg
+fn frame_name(index: usize) -> String {
+    format!("{:06}.jpg", index + 1)
+}
+
+enum Frame {
+    Extracted,
+    Failed,
+    Cancelled,
+}
+
+// Runs ffmpeg once per frame until all the frames are extracted, the job is
+// cancelled or the disk budget is reached. Each run seeks to its frame, so
+// only the data around the key frames is read instead of the whole file.
+fn extract(
+    options: &ThumbnailOptions,
+    input: &str,
+    duration: f64,
+    dir: &Path,
+    cancel: &AtomicBool,
+) {
+    if let Err(error) = fs::create_dir_all(dir) {
+        eprintln!("cannot create thumbnail folder {}: {error}", dir.display());
+        return;
+    }
+    // The other folders are not touched while ffmpeg runs
+    evict(&options.cache_dir, dir, options.disk_budget);
+    let count = (duration / options.interval).ceil() as usize;
+    let mut complete = true;
+    for index in 0..count {
+        let time = index as f64 * options.interval;
+        match extract_frame(options, input, time, &dir.join(frame_name(index)), cancel) {
+            Frame::Extracted => {}
+            Frame::Failed if index == 0 => {
+                remove_dir(dir);
+                return;
+            }
+            // The frames extracted so far are shown but not cached
+            Frame::Failed => {
+                complete = false;
+                break;
+            }
+            Frame::Cancelled => {
+                remove_dir(dir);
+                return;
+            }
+        }
+        if dir_size(dir).unwrap_or_default() > options.disk_budget {
+            eprintln!("thumbnail disk budget reached for {input}");
+            break;
+        }
+    }
+    if complete {
+        if let Err(error) = fs::write(dir.join(DONE_FILE), b"") {
+            eprintln!("cannot write {}: {error}", dir.join(DONE_FILE).display());
+        }
+    }
+    // Makes room for the frames of this file
+    evict(&options.cache_dir, dir, options.disk_budget);
+}
+
+// Writes the key frame at or before `time` to `path`
+fn extract_frame(
+    options: &ThumbnailOptions,
+    input: &str,
+    time: f64,
+    path: &Path,
+    cancel: &AtomicBool,
+) -> Frame {
+    // Only the key frames are decoded, which is much faster and close enough
+    let child = hide_window(&mut Command::new(&options.ffmpeg))
+        .args(["-v", "error", "-nostdin", "-y", "-skip_frame", "nokey"])
+        .args(["-noaccurate_seek", "-ss"])
+        .arg(time.to_string())
+        .args(["-i", input])
+        .args([
+            "-an",
+            "-sn",
+            "-dn",
+            "-frames:v",
+            "1",
+            "-update",
+            "1",
+            "-q:v",
+            "5",
+        ])
+        .arg("-vf")
+        .arg(format!("scale={}:-2", options.width))
+        .arg(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(error) => {
+            eprintln!("cannot start ffmpeg: {error}");
+            return Frame::Failed;
+        }
+    };
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() && path.is_file() => return Frame::Extracted,
+            Ok(Some(status)) => {
+                eprintln!("ffmpeg cannot extract the thumbnail at {time} of {input}: {status}");
+                return Frame::Failed;
+            }
+            Ok(None) => {}
+            Err(error) => {
+                eprintln!("cannot wait for ffmpeg: {error}");
+                return Frame::Failed;
+            }
+        }
+        if cancel.load(Ordering::SeqCst) {
+            child.kill().ok();
+            child.wait().ok();
+            return Frame::Cancelled;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+// Removes the least recently used folders in `cache_dir` except `keep`
+// until they all fit in `budget`. Returns the bytes taken by the folders
+// left.
+pub fn evict(cache_dir: &Path, keep: &Path, budget: u64) -> u64 {
+    let mut dirs: Vec<(PathBuf, u64, SystemTime)> = match fs::read_dir(cache_dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .map(|path| {
+                let last_used = fs::metadata(path.join(DONE_FILE))
+                    .or_else(|_| fs::metadata(&path))
+                    .and_then(|metadata| metadata.modified())
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+                let size = dir_size(&path).unwrap_or_default();
+                (path, size, last_used)
+            })
+            .collect(),
+        Err(_) => return 0,
+    };
+    let mut total: u64 = dirs.iter().map(|(_, size, _)| size).sum();
+    dirs.sort_by_key(|(_, _, last_used)| *last_used);
+    for (path, size, _) in dirs {
+        if total <= budget {
+            break;
+        }
+        if path != keep {
+            remove_dir(&path);
+            total -= size;
+        }
+    }
+    total
+}
+
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.metadata().map(|metadata| metadata.len()))
+        .sum()
+}
+
+fn remove_dir(dir: &Path) {
+    if let Err(error) = fs::remove_dir_all(dir) {
+        if error.kind() != io::ErrorKind::NotFound {
+            eprintln!("cannot remove thumbnail folder {}: {error}", dir.display());
+        }
+    }
+}
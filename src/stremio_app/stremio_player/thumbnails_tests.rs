@@ -0,0 +1,221 @@
+use crate::stremio_app::stremio_player::thumbnails::{
+    cache_key, evict, Thumbnail, ThumbnailOptions, Thumbnails,
+};
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{self, Command, Stdio},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("stremio-thumbnails-{name}-{}", process::id()));
+    fs::remove_dir_all(&dir).ok();
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn options(ffmpeg: PathBuf, cache_dir: &Path) -> ThumbnailOptions {
+    ThumbnailOptions {
+        ffmpeg,
+        cache_dir: cache_dir.to_path_buf(),
+        interval: 10.,
+        width: 160,
+        disk_budget: 10 * 1024 * 1024,
+    }
+}
+
+fn frame_count(dir: &Path) -> usize {
+    fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "jpg"))
+                .count()
+        })
+        .unwrap_or_default()
+}
+
+fn wait_for_extraction(thumbnails: &Thumbnails) {
+    let started = Instant::now();
+    while thumbnails.is_extracting() {
+        assert!(
+            started.elapsed() < Duration::from_secs(60),
+            "the extraction didn't finish"
+        );
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// Writes a folder of `size` bytes last used at `last_used`
+fn write_cache_dir(cache_dir: &Path, name: &str, size: usize, last_used: SystemTime) -> PathBuf {
+    let dir = cache_dir.join(name);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("000001.jpg"), vec![0; size]).unwrap();
+    let done = fs::File::create(dir.join("done")).unwrap();
+    done.set_modified(last_used).unwrap();
+    dir
+}
+
+#[test]
+fn thumbnails_frame_at() {
+    let cache_dir = test_dir("frame-at");
+    let input = cache_dir.join("movie.mkv").to_string_lossy().into_owned();
+    let dir = cache_dir.join(cache_key(&input));
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["000001.jpg", "000002.jpg", "000003.jpg", "done"] {
+        fs::write(dir.join(name), b"").unwrap();
+    }
+    // The complete folder is used without running ffmpeg
+    let thumbnails = Thumbnails::new(options(cache_dir.join("missing-ffmpeg"), &cache_dir));
+    assert_eq!(thumbnails.frame_at(0.), None);
+    thumbnails.start(&input, 60.);
+    assert!(!thumbnails.is_extracting());
+    let frame = |time: f64, name: &str| {
+        Some(Thumbnail {
+            time,
+            path: dir.join(name),
+        })
+    };
+    assert_eq!(thumbnails.frame_at(-5.), frame(0., "000001.jpg"));
+    assert_eq!(thumbnails.frame_at(14.), frame(10., "000002.jpg"));
+    assert_eq!(thumbnails.frame_at(16.), frame(20., "000003.jpg"));
+    // Past the last frame
+    assert_eq!(thumbnails.frame_at(500.), frame(20., "000003.jpg"));
+
+    thumbnails.cancel();
+    assert_eq!(thumbnails.frame_at(10.), None);
+    // The cache is kept
+    assert_eq!(frame_count(&dir), 3);
+    fs::remove_dir_all(&cache_dir).ok();
+}
+
+#[test]
+fn thumbnails_unsupported() {
+    let cache_dir = test_dir("unsupported");
+    let thumbnails = Thumbnails::new(options(cache_dir.join("missing-ffmpeg"), &cache_dir));
+    // Other protocols are not passed to ffmpeg
+    thumbnails.start("concat:a.mkv|b.mkv", 60.);
+    assert!(!thumbnails.is_extracting());
+    assert_eq!(thumbnails.frame_at(0.), None);
+
+    // A folder which ffmpeg failed to fill is removed
+    let input = cache_dir.join("movie.mkv").to_string_lossy().into_owned();
+    thumbnails.start(&input, 60.);
+    wait_for_extraction(&thumbnails);
+    assert_eq!(thumbnails.frame_at(0.), None);
+    assert!(!cache_dir.join(cache_key(&input)).exists());
+
+    // Disabled without options
+    let thumbnails = Thumbnails::default();
+    thumbnails.start(&input, 60.);
+    assert!(!thumbnails.is_extracting());
+    assert_eq!(thumbnails.frame_at(0.), None);
+    fs::remove_dir_all(&cache_dir).ok();
+}
+
+#[test]
+fn thumbnails_evict() {
+    let cache_dir = test_dir("evict");
+    let now = SystemTime::now();
+    let oldest = write_cache_dir(&cache_dir, "oldest", 100, now - Duration::from_secs(300));
+    let old = write_cache_dir(&cache_dir, "old", 100, now - Duration::from_secs(200));
+    let recent = write_cache_dir(&cache_dir, "recent", 100, now - Duration::from_secs(100));
+
+    assert_eq!(evict(&cache_dir, &recent, 1000), 300);
+    assert!(oldest.exists() && old.exists() && recent.exists());
+
+    // The least recently used folders are removed first
+    assert_eq!(evict(&cache_dir, &recent, 250), 200);
+    assert!(!oldest.exists() && old.exists() && recent.exists());
+
+    // The folder being filled is kept even if it is over the budget
+    assert_eq!(evict(&cache_dir, &old, 50), 100);
+    assert!(old.exists() && !recent.exists());
+
+    assert_eq!(evict(&cache_dir.join("missing"), &old, 0), 0);
+    fs::remove_dir_all(&cache_dir).ok();
+}
+
+// The shipped ffmpeg, or the one set in FFMPEG or found in PATH
+fn find_ffmpeg() -> Option<PathBuf> {
+    let candidates = vec![
+        env::var_os("FFMPEG").map(PathBuf::from),
+        Some(Path::new(env!("CARGO_MANIFEST_DIR")).join("bin/ffmpeg.exe")),
+        Some(PathBuf::from("ffmpeg")),
+    ];
+    candidates.into_iter().flatten().find(|ffmpeg| {
+        Command::new(ffmpeg)
+            .arg("-version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success())
+    })
+}
+
+// A 60 seconds video with a key frame every second
+fn generate_sample(ffmpeg: &Path, path: &Path) {
+    let status = Command::new(ffmpeg)
+        .args(["-v", "error", "-y", "-f", "lavfi", "-i"])
+        .arg("testsrc=duration=60:size=320x240:rate=10")
+        .args(["-c:v", "mpeg4", "-g", "10"])
+        .arg(path)
+        .status()
+        .expect("cannot run ffmpeg");
+    assert!(status.success(), "cannot generate the sample video");
+}
+
+#[test]
+fn thumbnails_sample_video() {
+    let ffmpeg = match find_ffmpeg() {
+        Some(ffmpeg) => ffmpeg,
+        None => {
+            eprintln!("ffmpeg not found, skipping the sample video test");
+            return;
+        }
+    };
+    let cache_dir = test_dir("sample");
+    let sample = cache_dir.join("sample.mp4");
+    generate_sample(&ffmpeg, &sample);
+    let input = sample.to_string_lossy().into_owned();
+    let dir = cache_dir.join(cache_key(&input));
+
+    // Cancelled right away, as on "loadfile"
+    let thumbnails = Thumbnails::new(options(ffmpeg.clone(), &cache_dir));
+    thumbnails.start(&input, 60.);
+    thumbnails.cancel();
+    assert!(!thumbnails.is_extracting());
+    assert!(!dir.exists());
+
+    thumbnails.start(&input, 60.);
+    wait_for_extraction(&thumbnails);
+    assert!(dir.join("done").is_file());
+    // One frame every 10 seconds
+    let count = frame_count(&dir);
+    assert_eq!(count, 6);
+    let thumbnail = thumbnails.frame_at(24.).expect("expected a thumbnail");
+    assert_eq!(thumbnail.time, 20.);
+    let data = fs::read(&thumbnail.path).unwrap();
+    assert!(data.starts_with(&[0xff, 0xd8]), "not a JPEG file");
+    assert_eq!(
+        thumbnails.frame_at(500.).map(|thumbnail| thumbnail.time),
+        Some((count - 1) as f64 * 10.)
+    );
+
+    // Another file evicts it once the budget is reached
+    let other = cache_dir.join("other.mp4");
+    fs::copy(&sample, &other).unwrap();
+    let other = other.to_string_lossy().into_owned();
+    let budget = evict(&cache_dir, &dir, u64::MAX);
+    let thumbnails = Thumbnails::new(ThumbnailOptions {
+        disk_budget: budget,
+        ..options(ffmpeg, &cache_dir)
+    });
+    thumbnails.start(&other, 60.);
+    wait_for_extraction(&thumbnails);
+    assert!(!dir.exists());
+    assert!(thumbnails.frame_at(0.).is_some());
+    fs::remove_dir_all(&cache_dir).ok();
+}